@@ -0,0 +1,92 @@
+use std::error::Error;
+use std::path::PathBuf;
+use std::process;
+
+use clap::Parser;
+
+use wikters::conformance::first_difference;
+use wikters::{open_page_source, Page, PageSource, ReaderKind};
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Run every XML reader over a dump and report the first page where they disagree")]
+struct Args {
+    /// Dump file to compare on (.xml, .bz2, .gz or .zst). Each reader opens it separately.
+    #[clap(short, long)]
+    input: PathBuf,
+
+    /// Limit the number of pages to compare
+    #[clap(short, long)]
+    limit: Option<u64>,
+}
+
+/// What one reader produced for the current page
+fn describe(result: &Result<Option<Page>, String>) -> String {
+    match result {
+        Ok(Some(page)) => format!("page {:?}", page.title),
+        Ok(None) => "end of dump".to_string(),
+        Err(e) => format!("error: {}", e),
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let mut sources = Vec::new();
+    for kind in ReaderKind::ALL {
+        sources.push((kind, open_page_source(kind, Some(&args.input))?));
+    }
+    let mut starts = vec![0u64; sources.len()];
+
+    let mut page_num = 0;
+    loop {
+        if let Some(limit) = args.limit {
+            if page_num >= limit {
+                break;
+            }
+        }
+
+        let results: Vec<Result<Option<Page>, String>> = sources
+            .iter_mut()
+            .map(|(_, source)| source.next_page().map_err(|e| e.to_string()))
+            .collect();
+
+        let (reference_kind, _) = sources[0];
+        let mut divergences = Vec::new();
+        for (i, result) in results.iter().enumerate().skip(1) {
+            let diff = match (&results[0], result) {
+                (Ok(Some(expected)), Ok(Some(actual))) => first_difference(expected, actual),
+                (Ok(None), Ok(None)) => None,
+                (expected, actual) => Some(format!("expected {}, got {}", describe(expected), describe(actual))),
+            };
+            if let Some(diff) = diff {
+                divergences.push((i, diff));
+            }
+        }
+
+        if !divergences.is_empty() {
+            println!("Readers diverge at page {} ({} from {})", page_num, describe(&results[0]), reference_kind.name());
+            for (i, diff) in divergences {
+                println!("  {}: {}", sources[i].0.name(), diff);
+            }
+            println!("Byte offsets of the page in the decompressed input:");
+            for (i, (kind, source)) in sources.iter().enumerate() {
+                match source.byte_offset() {
+                    Some(end) => println!("  {}: {}..{}", kind.name(), starts[i], end),
+                    None => println!("  {}: unknown", kind.name()),
+                }
+            }
+            process::exit(1);
+        }
+
+        if matches!(results[0], Ok(None)) {
+            break;
+        }
+        for (i, (_, source)) in sources.iter().enumerate() {
+            starts[i] = source.byte_offset().unwrap_or(0);
+        }
+        page_num += 1;
+    }
+
+    println!("All {} readers agree on {} pages", sources.len(), page_num);
+    Ok(())
+}
@@ -1,10 +1,8 @@
 use std::error::Error;
-use std::io;
 
 use clap::Parser;
 
-use wikters::quick_xml_reader::QuickXmlReader;
-use wikters::{PageSource, Opts};
+use wikters::{DumpArgs, PageFilters, PageSource, Opts, ReaderKind, Recovery};
 
 #[derive(Debug, Parser)]
 #[command(version, about = "Dump raw English sections for manual inspection")]
@@ -13,6 +11,9 @@ struct Args {
     #[clap(short, long)]
     limit: Option<u64>,
 
+    #[command(flatten)]
+    dump: DumpArgs,
+
     /// Show first N pages with matching language sections (shows raw wikitext)
     #[clap(short, long, default_value = "20")]
     pages_to_show: u64,
@@ -59,36 +60,28 @@ fn main() -> Result<(), Box<dyn Error>> {
         xml: false,
         no_updates: false,
         sample_rate: None,
+        seed: 0,
         handrolled: false,
+        recovery: Recovery::Strict,
     };
 
-    let stdin = io::stdin();
-    let source = QuickXmlReader::new(stdin.lock());
+    let source = args.dump.open_checkpointed(ReaderKind::QuickXml)?;
 
     let mut shown = 0;
     let mut scanned = 0;
 
-    for page_result in std::iter::from_fn({
-        let mut source = source;
-        move || source.next_page().ok().flatten()
-    }) {
-        scanned += 1;
-
-        if let Some(limit) = opts.limit {
-            if scanned > limit {
-                break;
-            }
-        }
+    let pages = source
+        .pages()
+        .limit(opts.limit.unwrap_or(u64::MAX))
+        .inspect(|_| scanned += 1)
+        .articles_only();
+    for page_result in pages {
+        let page_result = page_result?;
 
         if shown >= args.pages_to_show {
             break;
         }
 
-        // Skip non-article namespace
-        if page_result.ns.unwrap_or(-1) != 0 {
-            continue;
-        }
-
         // Apply title filter if specified
         if let Some(ref filter) = args.title_filter {
             if !page_result.title.to_lowercase().contains(&filter.to_lowercase()) {
@@ -1,13 +1,9 @@
 use std::collections::HashMap;
 use std::error::Error;
-use std::io;
 
 use clap::Parser;
 
-use wikters::quick_xml_reader::QuickXmlReader;
-use wikters::regex_reader::RegexReader;
-use wikters::string_ops_reader::StringOpsReader;
-use wikters::{PageSource, Opts};
+use wikters::{DumpArgs, PageFilters, PageSource, Opts, Pipeline, ReaderKind, Recovery};
 
 #[derive(Debug, Parser)]
 #[command(version, about = "Analyze Etymology/Pronunciation/POS nesting patterns in English sections")]
@@ -16,6 +12,9 @@ struct Args {
     #[clap(short, long)]
     limit: Option<u64>,
 
+    #[command(flatten)]
+    dump: DumpArgs,
+
     /// Use regex-based hand-rolled parser
     #[clap(short = 'r', long)]
     handrolled: bool,
@@ -27,13 +26,17 @@ struct Args {
     /// Show examples of each pattern
     #[clap(long)]
     examples: bool,
+
+    /// Worker threads for the per-page analysis (default: one per core)
+    #[clap(short, long)]
+    jobs: Option<usize>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 enum SectionType {
     Etymology,
     Pronunciation,
-    POS(String), // noun, verb, etc
+    Pos(String), // noun, verb, etc
     Other(String),
 }
 
@@ -77,7 +80,7 @@ fn classify_section(text: &str) -> SectionType {
         ];
         for pos in &pos_types {
             if lower.contains(pos) {
-                return SectionType::POS(pos.to_string());
+                return SectionType::Pos(pos.to_string());
             }
         }
         SectionType::Other(text.to_string())
@@ -113,8 +116,7 @@ fn analyze_english_structure(text: &str) -> Option<String> {
     let mut structure = Vec::new();
     let mut last_level = 2;
 
-    for i in start + 1..end {
-        let line = lines[i];
+    for line in &lines[start + 1..end] {
         let trimmed = line.trim();
         
         if !is_valid_heading(trimmed) {
@@ -130,7 +132,7 @@ fn analyze_english_structure(text: &str) -> Option<String> {
         let type_str = match section_type {
             SectionType::Etymology => "Etymology".to_string(),
             SectionType::Pronunciation => "Pronunciation".to_string(),
-            SectionType::POS(pos) => format!("{}", pos),
+            SectionType::Pos(pos) => pos.to_string(),
             SectionType::Other(s) => format!("Other({})", s.split_whitespace().next().unwrap_or("?")),
         };
 
@@ -153,55 +155,51 @@ fn main() -> Result<(), Box<dyn Error>> {
         xml: false,
         no_updates: false,
         sample_rate: None,
+        seed: 0,
         handrolled: args.handrolled,
+        recovery: Recovery::Strict,
     };
 
-    let stdin = io::stdin();
-
-    let source: Box<dyn PageSource> = if args.stringops {
-        Box::new(StringOpsReader::new(stdin.lock()))
+    let kind = if args.stringops {
+        ReaderKind::StringOps
     } else if args.handrolled {
-        Box::new(RegexReader::new(stdin.lock()))
+        ReaderKind::Regex
     } else {
-        Box::new(QuickXmlReader::new(stdin.lock()))
+        ReaderKind::QuickXml
     };
+    let source = args.dump.open_checkpointed(kind)?;
 
     let mut structure_counts: HashMap<String, (u32, Vec<String>)> = HashMap::new();
     let mut pages_with_english = 0;
     let mut pages_processed = 0;
 
-    let mut source = source;
-    loop {
-        if let Some(limit) = opts.limit {
-            if pages_processed >= limit {
-                break;
-            }
-        }
-
-        match source.next_page()? {
-            Some(page) => {
-                pages_processed += 1;
-
-                if page.ns.unwrap_or(-1) != 0 {
-                    continue;
-                }
-
-                if let Some(structure) = analyze_english_structure(&page.rev_text) {
-                    pages_with_english += 1;
-                    
-                    let entry = structure_counts.entry(structure).or_insert((0, Vec::new()));
-                    entry.0 += 1;
-                    if args.examples && entry.1.len() < 3 {
-                        entry.1.push(page.title);
-                    }
+    let pages = source
+        .pages()
+        .limit(opts.limit.unwrap_or(u64::MAX))
+        .inspect(|_| pages_processed += 1)
+        .articles_only();
+    let mut pipeline = Pipeline::new().preserving_order();
+    if let Some(jobs) = args.jobs {
+        pipeline = pipeline.with_workers(jobs);
+    }
+    pipeline.run(
+        pages,
+        |page| (analyze_english_structure(&page.rev_text), page.title),
+        |(structure, title)| {
+            if let Some(structure) = structure {
+                pages_with_english += 1;
+
+                let entry = structure_counts.entry(structure).or_insert((0, Vec::new()));
+                entry.0 += 1;
+                if args.examples && entry.1.len() < 3 {
+                    entry.1.push(title);
                 }
             }
-            None => break,
-        }
-    }
+        },
+    )?;
 
     let mut sorted: Vec<_> = structure_counts.iter().collect();
-    sorted.sort_by(|a, b| b.1.0.cmp(&a.1.0));
+    sorted.sort_by_key(|b| std::cmp::Reverse(b.1.0));
 
     println!("English Section Structure Analysis");
     println!("({} pages scanned, {} with English sections)", pages_processed, pages_with_english);
@@ -1,13 +1,9 @@
 use std::collections::HashMap;
 use std::error::Error;
-use std::io;
 
 use clap::Parser;
 
-use wikters::quick_xml_reader::QuickXmlReader;
-use wikters::regex_reader::RegexReader;
-use wikters::string_ops_reader::StringOpsReader;
-use wikters::{PageSource, Opts};
+use wikters::{DumpArgs, PageFilters, PageSource, Opts, ReaderKind, Recovery};
 
 #[derive(Debug, Parser)]
 #[command(version, about = "Detect homograph patterns: Etymology (L3) with nested POS (L4) vs flat POS (L3)")]
@@ -16,6 +12,9 @@ struct Args {
     #[clap(short, long)]
     limit: Option<u64>,
 
+    #[command(flatten)]
+    dump: DumpArgs,
+
     /// Use regex-based hand-rolled parser
     #[clap(short = 'r', long)]
     handrolled: bool,
@@ -105,8 +104,7 @@ fn classify_english_structure(text: &str) -> HomographPattern {
 
     let mut headings: Vec<(usize, usize, String)> = Vec::new(); // (level, line_index, text)
     
-    for i in start + 1..end {
-        let line = lines[i];
+    for (i, line) in lines.iter().enumerate().take(end).skip(start + 1) {
         let trimmed = line.trim();
         
         if !is_valid_heading(trimmed) {
@@ -180,54 +178,44 @@ fn main() -> Result<(), Box<dyn Error>> {
         xml: false,
         no_updates: false,
         sample_rate: None,
+        seed: 0,
         handrolled: args.handrolled,
+        recovery: Recovery::Strict,
     };
 
-    let stdin = io::stdin();
-
-    let source: Box<dyn PageSource> = if args.stringops {
-        Box::new(StringOpsReader::new(stdin.lock()))
+    let kind = if args.stringops {
+        ReaderKind::StringOps
     } else if args.handrolled {
-        Box::new(RegexReader::new(stdin.lock()))
+        ReaderKind::Regex
     } else {
-        Box::new(QuickXmlReader::new(stdin.lock()))
+        ReaderKind::QuickXml
     };
+    let source = args.dump.open_checkpointed(kind)?;
 
     let mut pattern_counts: HashMap<HomographPattern, (u32, Vec<String>)> = HashMap::new();
     let mut pages_with_english = 0;
     let mut pages_processed = 0;
 
-    let mut source = source;
-    loop {
-        if let Some(limit) = opts.limit {
-            if pages_processed >= limit {
-                break;
-            }
-        }
+    let pages = source
+        .pages()
+        .limit(opts.limit.unwrap_or(u64::MAX))
+        .inspect(|_| pages_processed += 1)
+        .articles_only();
+    for page in pages {
+        let page = page?;
 
-        match source.next_page()? {
-            Some(page) => {
-                pages_processed += 1;
-
-                if page.ns.unwrap_or(-1) != 0 {
-                    continue;
-                }
-
-                let pattern = classify_english_structure(&page.rev_text);
-                pages_with_english += 1;
-                
-                let entry = pattern_counts.entry(pattern).or_insert((0, Vec::new()));
-                entry.0 += 1;
-                if args.examples && entry.1.len() < 3 {
-                    entry.1.push(page.title);
-                }
-            }
-            None => break,
+        let pattern = classify_english_structure(&page.rev_text);
+        pages_with_english += 1;
+        
+        let entry = pattern_counts.entry(pattern).or_insert((0, Vec::new()));
+        entry.0 += 1;
+        if args.examples && entry.1.len() < 3 {
+            entry.1.push(page.title);
         }
     }
 
     let mut sorted: Vec<_> = pattern_counts.iter().collect();
-    sorted.sort_by(|a, b| b.1.0.cmp(&a.1.0));
+    sorted.sort_by_key(|b| std::cmp::Reverse(b.1.0));
 
     println!("Homograph Pattern Analysis");
     println!("({} pages scanned, {} with English sections)", pages_processed, pages_with_english);
@@ -1,13 +1,10 @@
 use std::collections::HashMap;
 use std::error::Error;
-use std::io;
 
 use clap::Parser;
 
-use wikters::quick_xml_reader::QuickXmlReader;
-use wikters::regex_reader::RegexReader;
-use wikters::string_ops_reader::StringOpsReader;
-use wikters::{PageSource, Opts};
+use wikters::wikitext_splitter::{self, HeadingRef};
+use wikters::{DumpArgs, PageFilters, PageSource, Opts, Pipeline, ReaderKind, Recovery, SectionTree};
 
 #[derive(Debug, Parser)]
 #[command(version, about = "Analyze L3 section ordering (Etymology vs Pronunciation ordering)")]
@@ -16,6 +13,9 @@ struct Args {
     #[clap(short, long)]
     limit: Option<u64>,
 
+    #[command(flatten)]
+    dump: DumpArgs,
+
     /// Language to analyze (default: English)
     #[clap(long, default_value = "English")]
     language: String,
@@ -35,46 +35,10 @@ struct Args {
     /// Store examples to markdown file (use - for stdout)
     #[clap(long)]
     output_examples: Option<String>,
-}
 
-fn count_leading_equals(s: &str) -> usize {
-    s.chars().take_while(|c| *c == '=').count()
-}
-
-fn is_valid_heading(line: &str) -> bool {
-    let trimmed = line.trim();
-    let leading = trimmed.chars().take_while(|c| *c == '=').count();
-    let trailing = trimmed.chars().rev().take_while(|c| *c == '=').count();
-    leading >= 2 && leading == trailing && leading * 2 < trimmed.len()
-}
-
-fn get_heading_text(line: &str) -> String {
-    let trimmed = line.trim();
-    let leading = trimmed.chars().take_while(|c| *c == '=').count();
-    let trailing = trimmed.chars().rev().take_while(|c| *c == '=').count();
-    trimmed[leading..trimmed.len() - trailing].trim().to_string()
-}
-
-fn get_language_section(text: &str, language: &str) -> Option<(usize, usize)> {
-    let lines: Vec<_> = text.lines().collect();
-    
-    let start = lines.iter().position(|line| {
-        let trimmed = line.trim();
-        is_valid_heading(trimmed) && 
-        count_leading_equals(trimmed) == 2 &&
-        trimmed.contains(language)
-    })?;
-
-    let end = lines[start + 1..]
-        .iter()
-        .position(|line| {
-            let trimmed = line.trim();
-            is_valid_heading(trimmed) && count_leading_equals(trimmed) == 2
-        })
-        .map(|p| p + start + 1)
-        .unwrap_or(lines.len());
-
-    Some((start, end))
+    /// Worker threads for the per-page analysis (default: one per core)
+    #[clap(short, long)]
+    jobs: Option<usize>,
 }
 
 fn is_etymology_section(text: &str) -> bool {
@@ -109,68 +73,43 @@ enum OrderPattern {
     Other(String),
 }
 
-fn has_nested_l4(lines: &[&str], l3_start: usize, l3_end: usize, section_type: &str) -> bool {
-    for i in l3_start + 1..l3_end {
-        let trimmed = lines[i].trim();
-        if !is_valid_heading(trimmed) {
-            continue;
-        }
-        let level = count_leading_equals(trimmed);
-        if level == 3 {
-            break; // Next L3 section
-        }
-        if level == 4 {
-            let heading_text = get_heading_text(lines[i]);
-            if section_type == "Pronunciation" && is_pronunciation_section(&heading_text) {
-                return true;
-            }
-            if section_type == "Etymology" && is_etymology_section(&heading_text) {
-                return true;
+/// Check if an L4 section directly under the L3 one at `l3_idx` is of this type.
+fn has_nested_l4(tree: &SectionTree<HeadingRef>, l3_idx: usize, section_type: &str) -> bool {
+    tree.find_child(l3_idx, |h| {
+        h.level == 4
+            && match section_type {
+                "Pronunciation" => is_pronunciation_section(h.text),
+                "Etymology" => is_etymology_section(h.text),
+                _ => false,
             }
-        }
-    }
-    false
+    })
+    .is_some()
 }
 
 fn get_l3_order_pattern(text: &str, language: &str) -> OrderPattern {
-    let lines: Vec<_> = text.lines().collect();
-    let (start, end) = match get_language_section(text, language) {
-        Some(range) => range,
-        None => return OrderPattern::Other(format!("no_{}", language.to_lowercase())),
+    let headings: Vec<_> = wikitext_splitter::heading_spans(text).collect();
+    let tree = SectionTree::new(&headings);
+    let Some(language_idx) = tree.find_root(language) else {
+        return OrderPattern::Other(format!("no_{}", language.to_lowercase()));
     };
 
-    let mut l3_sections: Vec<(usize, usize, String)> = Vec::new(); // (line_start, line_end, text)
-
-    for i in start + 1..end {
-        let trimmed = lines[i].trim();
-        
-        if !is_valid_heading(trimmed) || count_leading_equals(trimmed) != 3 {
-            continue;
-        }
-
-        let heading_text = get_heading_text(lines[i]);
-        l3_sections.push((i, 0, heading_text)); // end calculated below
-    }
+    let l3_sections: Vec<usize> = tree
+        .children(language_idx)
+        .iter()
+        .copied()
+        .filter(|&i| tree.heading(i).level == 3)
+        .collect();
 
     if l3_sections.is_empty() {
         return OrderPattern::Other("no_l3".to_string());
     }
 
-    // Calculate end line for each L3 section
-    for i in 0..l3_sections.len() {
-        let next_l3_line = if i + 1 < l3_sections.len() {
-            l3_sections[i + 1].0
-        } else {
-            end
-        };
-        l3_sections[i].1 = next_l3_line;
-    }
-
     let mut etymology_idx = None;
     let mut pronunciation_idx = None;
     let mut pos_idx = None;
 
-    for (idx, (_, _, text)) in l3_sections.iter().enumerate() {
+    for &idx in &l3_sections {
+        let text = tree.heading(idx).text;
         if is_etymology_section(text) && etymology_idx.is_none() {
             etymology_idx = Some(idx);
         }
@@ -185,11 +124,8 @@ fn get_l3_order_pattern(text: &str, language: &str) -> OrderPattern {
     match (etymology_idx, pronunciation_idx) {
         (Some(e), Some(p)) => {
             // Both exist at L3 - check for nesting
-            let (etym_start, etym_end, _) = l3_sections[e];
-            let (pron_start, pron_end, _) = l3_sections[p];
-            
-            let etym_has_nested_pron = has_nested_l4(&lines, etym_start, etym_end, "Pronunciation");
-            let pron_has_nested_etym = has_nested_l4(&lines, pron_start, pron_end, "Etymology");
+            let etym_has_nested_pron = has_nested_l4(&tree, e, "Pronunciation");
+            let pron_has_nested_etym = has_nested_l4(&tree, p, "Etymology");
             
             if e < p {
                 // Etymology before Pronunciation
@@ -209,9 +145,7 @@ fn get_l3_order_pattern(text: &str, language: &str) -> OrderPattern {
         }
         (Some(e), None) => {
             // Only Etymology at L3
-            let (etym_start, etym_end, _) = l3_sections[e];
-            let etym_has_nested_pron = has_nested_l4(&lines, etym_start, etym_end, "Pronunciation");
-            if etym_has_nested_pron {
+            if has_nested_l4(&tree, e, "Pronunciation") {
                 OrderPattern::EtymWithNestedPron
             } else {
                 OrderPattern::EtymOnly
@@ -219,9 +153,7 @@ fn get_l3_order_pattern(text: &str, language: &str) -> OrderPattern {
         }
         (None, Some(p)) => {
             // Only Pronunciation at L3
-            let (pron_start, pron_end, _) = l3_sections[p];
-            let pron_has_nested_etym = has_nested_l4(&lines, pron_start, pron_end, "Etymology");
-            if pron_has_nested_etym {
+            if has_nested_l4(&tree, p, "Etymology") {
                 OrderPattern::PronWithNestedEtym
             } else {
                 OrderPattern::PronOnly
@@ -246,62 +178,60 @@ fn main() -> Result<(), Box<dyn Error>> {
         xml: false,
         no_updates: false,
         sample_rate: None,
+        seed: 0,
         handrolled: args.handrolled,
+        recovery: Recovery::Strict,
     };
 
-    let stdin = io::stdin();
-
-    let source: Box<dyn PageSource> = if args.stringops {
-        Box::new(StringOpsReader::new(stdin.lock()))
+    let kind = if args.stringops {
+        ReaderKind::StringOps
     } else if args.handrolled {
-        Box::new(RegexReader::new(stdin.lock()))
+        ReaderKind::Regex
     } else {
-        Box::new(QuickXmlReader::new(stdin.lock()))
+        ReaderKind::QuickXml
     };
+    let source = args.dump.open_checkpointed(kind)?;
 
     let mut pattern_counts: HashMap<OrderPattern, (u32, Vec<String>)> = HashMap::new();
     let mut pages_processed = 0;
 
-    let mut source = source;
-    loop {
-        if let Some(limit) = opts.limit {
-            if pages_processed >= limit {
-                break;
-            }
-        }
+    let pages = source
+        .pages()
+        .limit(opts.limit.unwrap_or(u64::MAX))
+        .inspect(|_| pages_processed += 1)
+        .articles_only();
+    let mut pipeline = Pipeline::new().preserving_order();
+    if let Some(jobs) = args.jobs {
+        pipeline = pipeline.with_workers(jobs);
+    }
+    pipeline.run(
+        pages,
+        |page| {
+            let mut patterns_found = vec![];
 
-        match source.next_page()? {
-            Some(page) => {
-                pages_processed += 1;
+            // Analyze requested language
+            patterns_found.push(get_l3_order_pattern(&page.rev_text, &args.language));
 
-                if page.ns.unwrap_or(-1) != 0 {
-                    continue;
-                }
+            // Optionally analyze Translingual
+            if args.with_translingual {
+                patterns_found.push(get_l3_order_pattern(&page.rev_text, "Translingual"));
+            }
 
-                let mut patterns_found = vec![];
-                
-                // Analyze requested language
-                patterns_found.push(get_l3_order_pattern(&page.rev_text, &args.language));
-                
-                // Optionally analyze Translingual
-                if args.with_translingual {
-                    patterns_found.push(get_l3_order_pattern(&page.rev_text, "Translingual"));
-                }
-                
-                for pattern in patterns_found {
-                    let entry = pattern_counts.entry(pattern).or_insert((0, Vec::new()));
-                    entry.0 += 1;
-                    if entry.1.len() < 4 {
-                        entry.1.push(page.title.clone());
-                    }
+            (patterns_found, page.title)
+        },
+        |(patterns_found, title)| {
+            for pattern in patterns_found {
+                let entry = pattern_counts.entry(pattern).or_insert((0, Vec::new()));
+                entry.0 += 1;
+                if entry.1.len() < 4 {
+                    entry.1.push(title.clone());
                 }
             }
-            None => break,
-        }
-    }
+        },
+    )?;
 
     let mut sorted: Vec<_> = pattern_counts.iter().collect();
-    sorted.sort_by(|a, b| b.1.0.cmp(&a.1.0));
+    sorted.sort_by_key(|b| std::cmp::Reverse(b.1.0));
 
     println!("L3 Section Order Pattern Analysis");
     println!("Language: {}{}", args.language, if args.with_translingual { " + Translingual" } else { "" });
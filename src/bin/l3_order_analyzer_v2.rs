@@ -1,14 +1,10 @@
 use std::collections::HashMap;
 use std::error::Error;
-use std::io;
 
 use clap::Parser;
 
-use wikters::quick_xml_reader::QuickXmlReader;
-use wikters::regex_reader::RegexReader;
-use wikters::string_ops_reader::StringOpsReader;
 use wikters::wikitext_splitter::{self, Heading};
-use wikters::{PageSource, Opts};
+use wikters::{DumpArgs, PageFilters, PageSource, Opts, ReaderKind, Recovery, SectionTree};
 
 #[derive(Debug, Parser)]
 #[command(version, about = "Analyze L3 section ordering using clean structural parsing")]
@@ -17,6 +13,9 @@ struct Args {
     #[clap(short, long)]
     limit: Option<u64>,
 
+    #[command(flatten)]
+    dump: DumpArgs,
+
     /// Language to analyze (default: English)
     #[clap(long, default_value = "English")]
     language: String,
@@ -58,18 +57,13 @@ fn is_pos(text: &str) -> bool {
 }
 
 /// Analyze the L3 section ordering within a language section.
-fn classify_l3_pattern(headings: &[Heading], section_start: usize, section_end: usize) -> L3Pattern {
+fn classify_l3_pattern(tree: &SectionTree<Heading>, language_idx: usize) -> L3Pattern {
     // Get all L3 headings in this section
-    let l3_indices: Vec<usize> = headings[section_start..section_end]
+    let l3_indices: Vec<usize> = tree
+        .children(language_idx)
         .iter()
-        .enumerate()
-        .filter_map(|(i, h)| {
-            if h.level == 3 {
-                Some(section_start + i)
-            } else {
-                None
-            }
-        })
+        .copied()
+        .filter(|&i| tree.heading(i).level == 3)
         .collect();
 
     if l3_indices.is_empty() {
@@ -82,7 +76,7 @@ fn classify_l3_pattern(headings: &[Heading], section_start: usize, section_end:
     let mut first_pos_idx = None;
 
     for &idx in &l3_indices {
-        let text = &headings[idx].text;
+        let text = &tree.heading(idx).text;
         if heading_matches(text, "etymology") && first_etym_idx.is_none() {
             first_etym_idx = Some(idx);
         } else if heading_matches(text, "pronunciation") && first_pron_idx.is_none() {
@@ -103,7 +97,7 @@ fn classify_l3_pattern(headings: &[Heading], section_start: usize, section_end:
         }
         (Some(e_idx), None) => {
             // Only Etymology at L3 - check if there's nested Pronunciation (L4 under Etymology)
-            let has_nested_pron = has_nested_heading(headings, e_idx, "pronunciation");
+            let has_nested_pron = has_nested_heading(tree, e_idx, "pronunciation");
             if has_nested_pron {
                 L3Pattern::EtymWithNestedPron
             } else {
@@ -112,7 +106,7 @@ fn classify_l3_pattern(headings: &[Heading], section_start: usize, section_end:
         }
         (None, Some(p_idx)) => {
             // Only Pronunciation at L3 - check if there's nested Etymology (L4 under Pronunciation)
-            let has_nested_etym = has_nested_heading(headings, p_idx, "etymology");
+            let has_nested_etym = has_nested_heading(tree, p_idx, "etymology");
             if has_nested_etym {
                 L3Pattern::PronWithNestedEtym
             } else {
@@ -130,23 +124,9 @@ fn classify_l3_pattern(headings: &[Heading], section_start: usize, section_end:
     }
 }
 
-/// Check if there's a heading at the given level within the section starting at `section_idx`.
-/// Looks for L4+ headings under the given L3 heading until the next L3 or end of parent section.
-fn has_nested_heading(headings: &[Heading], section_idx: usize, category: &str) -> bool {
-    let section_level = headings[section_idx].level;
-
-    // Look at all headings after this one
-    for h in &headings[section_idx + 1..] {
-        if h.level <= section_level {
-            // Hit a heading at same level or shallower - stop
-            break;
-        }
-        if h.level == section_level + 1 && heading_matches(&h.text, category) {
-            // Found a matching nested heading
-            return true;
-        }
-    }
-    false
+/// Check if a section directly under the one at `section_idx` matches the category.
+fn has_nested_heading(tree: &SectionTree<Heading>, section_idx: usize, category: &str) -> bool {
+    tree.find_child(section_idx, |h| heading_matches(&h.text, category)).is_some()
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -157,55 +137,45 @@ fn main() -> Result<(), Box<dyn Error>> {
         xml: false,
         no_updates: false,
         sample_rate: None,
+        seed: 0,
         handrolled: args.handrolled,
+        recovery: Recovery::Strict,
     };
 
-    let stdin = io::stdin();
-
-    let source: Box<dyn PageSource> = if args.stringops {
-        Box::new(StringOpsReader::new(stdin.lock()))
+    let kind = if args.stringops {
+        ReaderKind::StringOps
     } else if args.handrolled {
-        Box::new(RegexReader::new(stdin.lock()))
+        ReaderKind::Regex
     } else {
-        Box::new(QuickXmlReader::new(stdin.lock()))
+        ReaderKind::QuickXml
     };
+    let source = args.dump.open_checkpointed(kind)?;
 
     let mut pattern_counts: HashMap<L3Pattern, (u32, Vec<String>)> = HashMap::new();
     let mut pages_processed = 0;
 
-    let mut source = source;
-    loop {
-        if let Some(limit) = opts.limit {
-            if pages_processed >= limit {
-                break;
-            }
-        }
-
-        match source.next_page()? {
-            Some(page) => {
-                pages_processed += 1;
-
-                if page.ns.unwrap_or(-1) != 0 {
-                    continue;
-                }
-
-                let (headings, _content) = wikitext_splitter::split_by_headings(&page.rev_text);
-
-                if let Some((lang_start, lang_end)) = wikitext_splitter::find_language_section(&headings, &args.language) {
-                    let pattern = classify_l3_pattern(&headings, lang_start, lang_end);
-                    let entry = pattern_counts.entry(pattern).or_insert((0, Vec::new()));
-                    entry.0 += 1;
-                    if entry.1.len() < 4 {
-                        entry.1.push(page.title.clone());
-                    }
-                }
+    let pages = source
+        .pages()
+        .limit(opts.limit.unwrap_or(u64::MAX))
+        .inspect(|_| pages_processed += 1)
+        .articles_only();
+    for page in pages {
+        let page = page?;
+
+        let (headings, _content) = wikitext_splitter::split_by_headings(&page.rev_text);
+
+        if let Some((lang_start, _)) = wikitext_splitter::find_language_section(&headings, &args.language) {
+            let pattern = classify_l3_pattern(&SectionTree::new(&headings), lang_start);
+            let entry = pattern_counts.entry(pattern).or_insert((0, Vec::new()));
+            entry.0 += 1;
+            if entry.1.len() < 4 {
+                entry.1.push(page.title.clone());
             }
-            None => break,
         }
     }
 
     let mut sorted: Vec<_> = pattern_counts.iter().collect();
-    sorted.sort_by(|a, b| b.1.0.cmp(&a.1.0));
+    sorted.sort_by_key(|b| std::cmp::Reverse(b.1.0));
 
     let with_language = pattern_counts.values().map(|(c, _)| c).sum::<u32>();
     println!("L3 Section Order Pattern Analysis (v2 - structural)");
@@ -1,13 +1,9 @@
 use std::collections::HashMap;
 use std::error::Error;
-use std::io;
 
 use clap::Parser;
 
-use wikters::quick_xml_reader::QuickXmlReader;
-use wikters::regex_reader::RegexReader;
-use wikters::string_ops_reader::StringOpsReader;
-use wikters::{PageSource, Opts};
+use wikters::{DumpArgs, PageFilters, PageSource, Opts, ReaderKind, Recovery};
 
 #[derive(Debug, Parser)]
 #[command(version, about = "Analyze L4 (====) heading patterns under L3 Etymology/Pronunciation")]
@@ -16,6 +12,9 @@ struct Args {
     #[clap(short, long)]
     limit: Option<u64>,
 
+    #[command(flatten)]
+    dump: DumpArgs,
+
     /// Use regex-based hand-rolled parser
     #[clap(short = 'r', long)]
     handrolled: bool,
@@ -101,8 +100,7 @@ fn analyze_l4_patterns(text: &str) -> Vec<(L4Context, String)> {
     let mut patterns = Vec::new();
     let mut last_l3_type = String::new();
 
-    for i in start + 1..end {
-        let line = lines[i];
+    for line in &lines[start + 1..end] {
         let trimmed = line.trim();
         
         if !is_valid_heading(trimmed) {
@@ -137,48 +135,38 @@ fn main() -> Result<(), Box<dyn Error>> {
         xml: false,
         no_updates: false,
         sample_rate: None,
+        seed: 0,
         handrolled: args.handrolled,
+        recovery: Recovery::Strict,
     };
 
-    let stdin = io::stdin();
-
-    let source: Box<dyn PageSource> = if args.stringops {
-        Box::new(StringOpsReader::new(stdin.lock()))
+    let kind = if args.stringops {
+        ReaderKind::StringOps
     } else if args.handrolled {
-        Box::new(RegexReader::new(stdin.lock()))
+        ReaderKind::Regex
     } else {
-        Box::new(QuickXmlReader::new(stdin.lock()))
+        ReaderKind::QuickXml
     };
+    let source = args.dump.open_checkpointed(kind)?;
 
     let mut l4_counts: HashMap<L4Context, (u32, Vec<String>)> = HashMap::new();
     let mut pages_processed = 0;
 
-    let mut source = source;
-    loop {
-        if let Some(limit) = opts.limit {
-            if pages_processed >= limit {
-                break;
-            }
-        }
-
-        match source.next_page()? {
-            Some(page) => {
-                pages_processed += 1;
-
-                if page.ns.unwrap_or(-1) != 0 {
-                    continue;
-                }
-
-                let patterns = analyze_l4_patterns(&page.rev_text);
-                for (context, _example) in patterns {
-                    let entry = l4_counts.entry(context).or_insert((0, Vec::new()));
-                    entry.0 += 1;
-                    if args.examples && entry.1.len() < 2 {
-                        entry.1.push(page.title.clone());
-                    }
-                }
+    let pages = source
+        .pages()
+        .limit(opts.limit.unwrap_or(u64::MAX))
+        .inspect(|_| pages_processed += 1)
+        .articles_only();
+    for page in pages {
+        let page = page?;
+
+        let patterns = analyze_l4_patterns(&page.rev_text);
+        for (context, _example) in patterns {
+            let entry = l4_counts.entry(context).or_insert((0, Vec::new()));
+            entry.0 += 1;
+            if args.examples && entry.1.len() < 2 {
+                entry.1.push(page.title.clone());
             }
-            None => break,
         }
     }
 
@@ -210,7 +198,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     // Now sort all contexts by frequency
     let mut sorted_contexts: Vec<_> = l4_counts.iter().collect();
-    sorted_contexts.sort_by(|a, b| b.1.0.cmp(&a.1.0));
+    sorted_contexts.sort_by_key(|b| std::cmp::Reverse(b.1.0));
 
     for (context, (count, examples)) in sorted_contexts.iter().take(40) {
         println!("{:20} -> {:20} {:6}", context.parent_type, context.l4_type, count);
@@ -1,13 +1,9 @@
 use std::collections::HashMap;
 use std::error::Error;
-use std::io;
 
 use clap::Parser;
 
-use wikters::quick_xml_reader::QuickXmlReader;
-use wikters::regex_reader::RegexReader;
-use wikters::string_ops_reader::StringOpsReader;
-use wikters::{PageSource, Opts};
+use wikters::{DumpArgs, PageFilters, PageSource, Opts, ReaderKind, Recovery};
 
 #[derive(Debug, Parser)]
 #[command(version, about = "Distinguish top-level vs nested Pronunciation patterns")]
@@ -16,6 +12,9 @@ struct Args {
     #[clap(short, long)]
     limit: Option<u64>,
 
+    #[command(flatten)]
+    dump: DumpArgs,
+
     /// Use regex-based hand-rolled parser
     #[clap(short = 'r', long)]
     handrolled: bool,
@@ -100,8 +99,7 @@ fn analyze_pronunciation_pattern(text: &str) -> PronunciationPattern {
     let mut has_l4_pronunciation_under_etymology = false;
     let mut last_l3_type = String::new();
 
-    for i in start + 1..end {
-        let line = lines[i];
+    for line in &lines[start + 1..end] {
         let trimmed = line.trim();
         
         if !is_valid_heading(trimmed) {
@@ -116,10 +114,8 @@ fn analyze_pronunciation_pattern(text: &str) -> PronunciationPattern {
             if is_pronunciation_section(&heading_text) {
                 has_l3_pronunciation = true;
             }
-        } else if level == 4 && is_pronunciation_section(&heading_text) {
-            if is_etymology_section(&last_l3_type) {
-                has_l4_pronunciation_under_etymology = true;
-            }
+        } else if level == 4 && is_pronunciation_section(&heading_text) && is_etymology_section(&last_l3_type) {
+            has_l4_pronunciation_under_etymology = true;
         }
     }
 
@@ -139,51 +135,41 @@ fn main() -> Result<(), Box<dyn Error>> {
         xml: false,
         no_updates: false,
         sample_rate: None,
+        seed: 0,
         handrolled: args.handrolled,
+        recovery: Recovery::Strict,
     };
 
-    let stdin = io::stdin();
-
-    let source: Box<dyn PageSource> = if args.stringops {
-        Box::new(StringOpsReader::new(stdin.lock()))
+    let kind = if args.stringops {
+        ReaderKind::StringOps
     } else if args.handrolled {
-        Box::new(RegexReader::new(stdin.lock()))
+        ReaderKind::Regex
     } else {
-        Box::new(QuickXmlReader::new(stdin.lock()))
+        ReaderKind::QuickXml
     };
+    let source = args.dump.open_checkpointed(kind)?;
 
     let mut pattern_counts: HashMap<PronunciationPattern, (u32, Vec<String>)> = HashMap::new();
     let mut pages_processed = 0;
 
-    let mut source = source;
-    loop {
-        if let Some(limit) = opts.limit {
-            if pages_processed >= limit {
-                break;
-            }
-        }
-
-        match source.next_page()? {
-            Some(page) => {
-                pages_processed += 1;
-
-                if page.ns.unwrap_or(-1) != 0 {
-                    continue;
-                }
-
-                let pattern = analyze_pronunciation_pattern(&page.rev_text);
-                let entry = pattern_counts.entry(pattern).or_insert((0, Vec::new()));
-                entry.0 += 1;
-                if args.examples && entry.1.len() < 3 {
-                    entry.1.push(page.title.clone());
-                }
-            }
-            None => break,
+    let pages = source
+        .pages()
+        .limit(opts.limit.unwrap_or(u64::MAX))
+        .inspect(|_| pages_processed += 1)
+        .articles_only();
+    for page in pages {
+        let page = page?;
+
+        let pattern = analyze_pronunciation_pattern(&page.rev_text);
+        let entry = pattern_counts.entry(pattern).or_insert((0, Vec::new()));
+        entry.0 += 1;
+        if args.examples && entry.1.len() < 3 {
+            entry.1.push(page.title.clone());
         }
     }
 
     let mut sorted: Vec<_> = pattern_counts.iter().collect();
-    sorted.sort_by(|a, b| b.1.0.cmp(&a.1.0));
+    sorted.sort_by_key(|b| std::cmp::Reverse(b.1.0));
 
     println!("Pronunciation Nesting Pattern Analysis");
     println!("({} pages scanned)", pages_processed);
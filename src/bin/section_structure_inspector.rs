@@ -1,13 +1,10 @@
 use std::collections::HashMap;
 use std::error::Error;
-use std::io;
+use std::fmt;
 
 use clap::Parser;
 
-use wikters::quick_xml_reader::QuickXmlReader;
-use wikters::regex_reader::RegexReader;
-use wikters::string_ops_reader::StringOpsReader;
-use wikters::{PageSource, Opts};
+use wikters::{DumpArgs, PageFilters, PageSource, Opts, ReaderKind, Recovery};
 
 #[derive(Debug, Parser)]
 #[command(version, about = "Analyze heading structure patterns in Wiktionary dump")]
@@ -16,6 +13,9 @@ struct Args {
     #[clap(short, long)]
     limit: Option<u64>,
 
+    #[command(flatten)]
+    dump: DumpArgs,
+
     /// Use regex-based hand-rolled parser
     #[clap(short = 'r', long)]
     handrolled: bool,
@@ -52,13 +52,17 @@ impl HeadingLevel {
         }
     }
 
-    fn to_string(&self) -> String {
-        match self {
-            HeadingLevel::L2 => "L2".to_string(),
-            HeadingLevel::L3 => "L3".to_string(),
-            HeadingLevel::L4 => "L4".to_string(),
-            HeadingLevel::L5 => "L5".to_string(),
-        }
+}
+
+impl fmt::Display for HeadingLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            HeadingLevel::L2 => "L2",
+            HeadingLevel::L3 => "L3",
+            HeadingLevel::L4 => "L4",
+            HeadingLevel::L5 => "L5",
+        };
+        write!(f, "{}", s)
     }
 }
 
@@ -154,7 +158,7 @@ fn analyze_english_structure(english_text: &str) -> String {
             "Other"
         };
 
-        pattern.push(format!("{}({})", label, heading.level.to_string()));
+        pattern.push(format!("{}({})", label, heading.level));
     }
 
     pattern.join(" -> ")
@@ -168,57 +172,45 @@ fn main() -> Result<(), Box<dyn Error>> {
         xml: false,
         no_updates: false,
         sample_rate: None,
+        seed: 0,
         handrolled: args.handrolled,
+        recovery: Recovery::Strict,
     };
 
-    let stdin = io::stdin();
-
-    // Choose reader
-    let source: Box<dyn PageSource> = if args.stringops {
-        Box::new(StringOpsReader::new(stdin.lock()))
+    let kind = if args.stringops {
+        ReaderKind::StringOps
     } else if args.handrolled {
-        Box::new(RegexReader::new(stdin.lock()))
+        ReaderKind::Regex
     } else {
-        Box::new(QuickXmlReader::new(stdin.lock()))
+        ReaderKind::QuickXml
     };
+    let source = args.dump.open_checkpointed(kind)?;
 
     let mut pattern_counts: HashMap<String, u32> = HashMap::new();
     let mut pages_with_english = 0;
     let mut pages_processed = 0;
 
     // Process pages
-    let mut source = source;
-    loop {
-        if let Some(limit) = opts.limit {
-            if pages_processed >= limit {
-                break;
-            }
-        }
-
-        match source.next_page()? {
-            Some(page) => {
-                pages_processed += 1;
-
-                // Skip non-article namespace
-                if page.ns.unwrap_or(-1) != 0 {
-                    continue;
-                }
-
-                // Get English section
-                if let Some(english_text) = get_english_section(&page.rev_text) {
-                    pages_with_english += 1;
-                    
-                    let pattern = analyze_english_structure(&english_text);
-                    *pattern_counts.entry(pattern.clone()).or_insert(0) += 1;
-
-                    if args.verbose && pages_with_english <= 20 {
-                        println!("=== {} ===", page.title);
-                        println!("{}", pattern);
-                        println!();
-                    }
-                }
+    let pages = source
+        .pages()
+        .limit(opts.limit.unwrap_or(u64::MAX))
+        .inspect(|_| pages_processed += 1)
+        .articles_only();
+    for page in pages {
+        let page = page?;
+
+        // Get English section
+        if let Some(english_text) = get_english_section(&page.rev_text) {
+            pages_with_english += 1;
+            
+            let pattern = analyze_english_structure(&english_text);
+            *pattern_counts.entry(pattern.clone()).or_insert(0) += 1;
+
+            if args.verbose && pages_with_english <= 20 {
+                println!("=== {} ===", page.title);
+                println!("{}", pattern);
+                println!();
             }
-            None => break,
         }
     }
 
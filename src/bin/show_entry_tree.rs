@@ -1,11 +1,13 @@
 use std::error::Error;
-use std::io;
+use std::path::PathBuf;
 
 use clap::Parser;
 
+use wikters::dump_input::open_input;
+use wikters::multistream::MultistreamSource;
 use wikters::quick_xml_reader::QuickXmlReader;
-use wikters::wikitext_splitter::{self, Heading};
-use wikters::PageSource;
+use wikters::wikitext_splitter;
+use wikters::{Page, PageSource, SectionTree};
 
 #[derive(Debug, Parser)]
 #[command(version, about = "Show the structural tree of a wiktionary entry")]
@@ -14,6 +16,14 @@ struct Args {
     #[clap(short, long)]
     title: String,
 
+    /// Read from this dump file (.xml, .bz2, .gz or .zst) instead of stdin
+    #[clap(short, long)]
+    input: Option<PathBuf>,
+
+    /// Multistream index file; with a multistream --input, seek straight to the page
+    #[clap(long, requires = "input")]
+    index: Option<PathBuf>,
+
     /// Only show English section (optionally with Translingual)
     #[clap(short, long)]
     main_only: bool,
@@ -26,80 +36,70 @@ struct Args {
 fn main() -> Result<(), Box<dyn Error>> {
     let args = Args::parse();
 
-    let stdin = io::stdin();
-    let source = Box::new(QuickXmlReader::new(stdin.lock()));
-
-    let mut source = source;
-    loop {
-        match source.next_page()? {
-            Some(page) => {
-                if page.title == args.title {
-                    println!("Found: {}", page.title);
-                    println!();
-
-                    let (headings, content_chunks) = wikitext_splitter::split_by_headings(&page.rev_text);
-
-                    if args.main_only {
-                        // Show only English (and optionally Translingual)
-                        let mut sections_shown = false;
-
-                        for (i, heading) in headings.iter().enumerate() {
-                            if heading.level != 2 {
-                                continue;
-                            }
-
-                            let show = heading.text == "English"
-                                || (args.with_translingual && heading.text == "Translingual");
-
-                            if !show {
-                                continue;
-                            }
-
-                            if sections_shown {
-                                println!();
-                            }
-
-                            println!("{}:", heading.text);
-                            println!("==================================================");
-
-                            // Find next L2 section
-                            let next_l2 = headings[i + 1..]
-                                .iter()
-                                .position(|h| h.level == 2)
-                                .map(|p| p + i + 1)
-                                .unwrap_or(headings.len());
-
-                            // Show this section's headings
-                            for j in (i + 1)..next_l2 {
-                                let h = &headings[j];
-                                let indent = if h.level >= 2 { h.level - 2 } else { 0 };
-                                println!("{}{}", "  ".repeat(indent), h);
-                            }
-
-                            sections_shown = true;
-                        }
-                    } else {
-                        // Show full structure
-                        println!("Full structure ({} headings):", headings.len());
-                        println!("==================================================");
-
-                        for heading in headings.iter() {
-                            let indent = if heading.level >= 2 {
-                                heading.level - 2
-                            } else {
-                                0
-                            };
-                            println!("{}{}", "  ".repeat(indent), heading);
-                        }
-                    }
-
-                    return Ok(());
-                }
+    let found = if let (Some(input), Some(index)) = (&args.input, &args.index) {
+        MultistreamSource::open(input, index)?.page_by_title(&args.title)?
+    } else {
+        let input = open_input(args.input.as_deref())?;
+        QuickXmlReader::new(input)
+            .pages()
+            .find(|page| page.as_ref().map_or(true, |page| page.title == args.title))
+            .transpose()?
+    };
+
+    match found {
+        Some(page) => show_entry(&page, &args),
+        None => println!("Entry not found: {}", args.title),
+    }
+
+    Ok(())
+}
+
+fn show_entry(page: &Page, args: &Args) {
+    println!("Found: {}", page.title);
+    println!();
+
+    let (headings, _content_chunks) = wikitext_splitter::split_by_headings(&page.rev_text);
+
+    if args.main_only {
+        // Show only English (and optionally Translingual)
+        let mut sections_shown = false;
+
+        let tree = SectionTree::new(&headings);
+        for &i in tree.roots() {
+            let heading = tree.heading(i);
+            if heading.level != 2 {
+                continue;
             }
-            None => {
-                println!("Entry not found: {}", args.title);
-                return Ok(());
+
+            let show = heading.text == "English" || (args.with_translingual && heading.text == "Translingual");
+
+            if !show {
+                continue;
             }
+
+            if sections_shown {
+                println!();
+            }
+
+            println!("{}:", heading.text);
+            println!("==================================================");
+
+            // Show this section's headings
+            for h in &headings[tree.descendants(i)] {
+                let indent = h.level.saturating_sub(2);
+                println!("{}{}", "  ".repeat(indent), h);
+            }
+
+            sections_shown = true;
+        }
+    } else {
+        // Show full structure
+        println!("Full structure ({} headings):", headings.len());
+        println!("==================================================");
+
+        for heading in headings.iter() {
+            let indent = heading.level.saturating_sub(2);
+            println!("{}{}", "  ".repeat(indent), heading);
         }
     }
 }
@@ -1,13 +1,11 @@
 use std::collections::HashMap;
 use std::error::Error;
-use std::io;
 
 use clap::Parser;
 
-use wikters::quick_xml_reader::QuickXmlReader;
-use wikters::regex_reader::RegexReader;
-use wikters::string_ops_reader::StringOpsReader;
-use wikters::{PageSource, Opts};
+use wikters::extension_tags::mask;
+use wikters::template::parse_templates;
+use wikters::{DumpArgs, PageFilters, PageSource, Opts, ReaderKind, Recovery};
 
 #[derive(Debug, Parser)]
 #[command(version, about = "Analyze template usage in Wiktionary dump")]
@@ -16,6 +14,9 @@ struct Args {
     #[clap(short, long)]
     limit: Option<u64>,
 
+    #[command(flatten)]
+    dump: DumpArgs,
+
     /// Use regex-based hand-rolled parser
     #[clap(short = 'r', long)]
     handrolled: bool,
@@ -41,88 +42,53 @@ fn main() -> Result<(), Box<dyn Error>> {
         xml: false,
         no_updates: false,
         sample_rate: None,
+        seed: 0,
         handrolled: args.handrolled,
+        recovery: Recovery::Strict,
     };
 
-    let stdin = io::stdin();
-
-    // Choose reader
-    let source: Box<dyn PageSource> = if args.stringops {
-        Box::new(StringOpsReader::new(stdin.lock()))
+    let kind = if args.stringops {
+        ReaderKind::StringOps
     } else if args.handrolled {
-        Box::new(RegexReader::new(stdin.lock()))
+        ReaderKind::Regex
     } else {
-        Box::new(QuickXmlReader::new(stdin.lock()))
+        ReaderKind::QuickXml
     };
+    let source = args.dump.open_checkpointed(kind)?;
 
     let mut template_counts: HashMap<String, u32> = HashMap::new();
     let mut template_variants: HashMap<String, Vec<String>> = HashMap::new();
     let mut pages_processed = 0;
 
     // Process pages
-    let mut source = source;
-    loop {
-        if let Some(limit) = opts.limit {
-            if pages_processed >= limit {
-                break;
+    let pages = source
+        .pages()
+        .limit(opts.limit.unwrap_or(u64::MAX))
+        .inspect(|_| pages_processed += 1)
+        .articles_only();
+    for page in pages {
+        let page = page?;
+
+        // Count the templates that start a line (possibly after whitespace or a comment), as in
+        // template definitions and POS sections, whole even when they run over several lines
+        let text = &page.rev_text;
+        let masked = mask(text);
+        for template in parse_templates(text) {
+            let line_start = masked[..template.span.start].rfind('\n').map_or(0, |i| i + 1);
+            if !masked[line_start..template.span.start].trim().is_empty() || template.name.is_empty() {
+                continue;
             }
-        }
 
-        match source.next_page()? {
-            Some(page) => {
-                pages_processed += 1;
+            *template_counts.entry(template.name.clone()).or_insert(0) += 1;
 
-                // Skip non-article namespace
-                if page.ns.unwrap_or(-1) != 0 {
-                    continue;
-                }
-
-                // Extract templates from the text
-                for line in page.rev_text.lines() {
-                    // Only process lines that look like template definitions or POS sections
-                    if !line.contains("{{") {
-                        continue;
-                    }
-
-                    // Look for template starts at the beginning of lines (ignoring whitespace)
-                    if let Some(start) = line.find("{{") {
-                        let before_template = &line[0..start];
-                        // Only count if the line starts with the template (possibly with whitespace)
-                        if !before_template.trim().is_empty() {
-                            continue;
-                        }
-
-                        // Extract template name (up to | or }})
-                        let after_braces = &line[start + 2..];
-                        let end_pos = after_braces
-                            .find("|")
-                            .unwrap_or_else(|| after_braces.find("}}").unwrap_or(after_braces.len()));
-
-                        let template_name = after_braces[0..end_pos].trim().to_string();
-
-                        // Skip empty names
-                        if template_name.is_empty() {
-                            continue;
-                        }
-
-                        *template_counts.entry(template_name.clone()).or_insert(0) += 1;
-
-                        if args.verbose {
-                            // Store first occurrence for variant analysis
-                            let template_end = line[start..]
-                                .find("}}")
-                                .map(|e| start + e + 2)
-                                .unwrap_or(line.len());
-                            let full_template = line[start..template_end].to_string();
-                            template_variants
-                                .entry(template_name)
-                                .or_insert_with(Vec::new)
-                                .push(full_template);
-                        }
-                    }
-                }
+            if args.verbose {
+                // Store each occurrence for variant analysis
+                let full_template = text[template.span.clone()].to_string();
+                template_variants
+                    .entry(template.name)
+                    .or_default()
+                    .push(full_template);
             }
-            None => break,
         }
     }
 
@@ -148,7 +114,7 @@ fn main() -> Result<(), Box<dyn Error>> {
                 let mut variant_list: Vec<_> = unique.iter().cloned().collect();
                 variant_list.sort();
                 for variant in variant_list.iter().take(5) {
-                    println!("  {}", variant);
+                    println!("  {}", variant.replace('\n', "\n  "));
                 }
                 if unique.len() > 5 {
                     println!("  ... and {} more variants", unique.len() - 5);
@@ -0,0 +1,536 @@
+//! Checkpointing long runs over a dump so an interrupted run can carry on where it left off.
+//!
+//! A `Checkpointer` notes the position just after each completed page and every so often writes
+//! it to a checkpoint file. Resuming reopens the dump from that position: plain XML files are
+//! seeked directly, and compressed ones decompressed up to it. Given the dump's multistream index,
+//! a resumed run instead jumps straight to the bzip2 stream holding the last completed page
+//! (`MultistreamSource::resume_after`).
+//!
+//! `DumpArgs` gives `wikters` and the analysis tools the same `--input`, `--index` and
+//! `--checkpoint` options and opens the dump accordingly; tools that just iterate over the pages
+//! wrap the source in a `CheckpointedSource`.
+//!
+//! Pages completed after the last save are processed again on resume, so a run's output may
+//! repeat up to `Checkpointer::DEFAULT_INTERVAL` pages.
+
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, Cursor, Read};
+use std::path::{Path, PathBuf};
+
+use crate::dump_input::{self, DumpInput, InputMeter};
+use crate::multistream::{MultistreamIndex, MultistreamSource};
+use crate::{new_page_source, Page, PageSource, ReaderKind, Recovery, SiteInfo};
+
+/// Where a run got to
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Checkpoint {
+    /// Offset in the decompressed dump just past the last completed page, for readers that track it
+    pub offset: Option<u64>,
+    /// Id of the last completed page
+    pub page_id: Option<i32>,
+}
+
+impl Checkpoint {
+    /// Read a checkpoint file, or None if there isn't one yet
+    pub fn load(path: &Path) -> Result<Option<Checkpoint>, Box<dyn Error>> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("{}: {}", path.display(), e).into()),
+        };
+
+        let mut checkpoint = Checkpoint::default();
+        for line in contents.lines().filter(|line| !line.is_empty()) {
+            match line.split_once(' ') {
+                Some(("offset", offset)) => checkpoint.offset = Some(offset.parse()?),
+                Some(("page_id", page_id)) => checkpoint.page_id = Some(page_id.parse()?),
+                _ => return Err(format!("{}: malformed checkpoint line {:?}", path.display(), line).into()),
+            }
+        }
+        Ok(Some(checkpoint))
+    }
+
+    /// Write the checkpoint file, replacing it in one step so an interruption can't leave it half written
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn Error>> {
+        let mut contents = String::new();
+        if let Some(offset) = self.offset {
+            contents.push_str(&format!("offset {}\n", offset));
+        }
+        if let Some(page_id) = self.page_id {
+            contents.push_str(&format!("page_id {}\n", page_id));
+        }
+
+        let mut temp = path.as_os_str().to_owned();
+        temp.push(".tmp");
+        fs::write(&temp, contents)?;
+        fs::rename(&temp, path)?;
+        Ok(())
+    }
+}
+
+/// Keeps a run's checkpoint file up to date as pages are completed.
+pub struct Checkpointer {
+    path: PathBuf,
+    interval: u64,
+    /// The checkpoint the run started from, if it's a resumed one
+    resumed_from: Option<Checkpoint>,
+    checkpoint: Checkpoint,
+    unsaved: u64,
+}
+
+impl Checkpointer {
+    pub const DEFAULT_INTERVAL: u64 = 1000;
+
+    /// Checkpoint to `path`, picking up any checkpoint already saved there
+    pub fn open(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let resumed_from = Checkpoint::load(path)?;
+        Ok(Checkpointer {
+            path: path.to_path_buf(),
+            interval: Self::DEFAULT_INTERVAL,
+            checkpoint: resumed_from.clone().unwrap_or_default(),
+            resumed_from,
+            unsaved: 0,
+        })
+    }
+
+    /// Save every `interval` pages rather than the default
+    pub fn with_interval(mut self, interval: u64) -> Self {
+        self.interval = interval.max(1);
+        self
+    }
+
+    /// The checkpoint to carry on from, if an earlier run left one
+    pub fn resumed_from(&self) -> Option<&Checkpoint> {
+        self.resumed_from.as_ref()
+    }
+
+    pub fn checkpoint(&self) -> &Checkpoint {
+        &self.checkpoint
+    }
+
+    /// Note that `page`, just returned by `source`, has been dealt with
+    pub fn page_done(&mut self, source: &dyn PageSource, page: &Page) -> Result<(), Box<dyn Error>> {
+        self.completed(Checkpoint {
+            offset: source.byte_offset(),
+            page_id: page.id,
+        })
+    }
+
+    fn completed(&mut self, checkpoint: Checkpoint) -> Result<(), Box<dyn Error>> {
+        self.checkpoint = checkpoint;
+        self.unsaved += 1;
+        if self.unsaved >= self.interval {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    pub fn save(&mut self) -> Result<(), Box<dyn Error>> {
+        self.unsaved = 0;
+        self.checkpoint.save(&self.path)
+    }
+
+    /// The whole dump has been read, so remove the checkpoint file and let the next run start afresh
+    pub fn finish(self) -> Result<(), Box<dyn Error>> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Command line options for reading a dump that a run can checkpoint and resume. Tools take them
+/// with `#[command(flatten)]`.
+#[derive(Debug, Clone, Default, clap::Args)]
+#[command(about = None, long_about = None)]
+pub struct DumpArgs {
+    /// Read from this dump file (.xml, .bz2, .gz or .zst) instead of stdin
+    #[clap(short, long)]
+    pub input: Option<PathBuf>,
+
+    /// Multistream index of --input, so resuming from --checkpoint jumps straight to the right stream
+    #[clap(long, requires = "input")]
+    pub index: Option<PathBuf>,
+
+    /// Save progress to this file, and carry on from it if an earlier run was interrupted
+    #[clap(long, requires = "input")]
+    pub checkpoint: Option<PathBuf>,
+}
+
+impl DumpArgs {
+    /// The checkpointer for `--checkpoint`, holding the checkpoint an earlier run left if there is one
+    pub fn checkpointer(&self) -> Result<Option<Checkpointer>, Box<dyn Error>> {
+        self.checkpoint.as_deref().map(Checkpointer::open).transpose()
+    }
+
+    /// Open the input with the chosen reader, along with a meter for progress reports. If the
+    /// checkpointer holds an earlier run's checkpoint, the source carries on just after its last
+    /// completed page.
+    pub fn open(
+        &self,
+        kind: ReaderKind,
+        checkpointer: Option<&Checkpointer>,
+    ) -> Result<(Box<dyn PageSource>, InputMeter), Box<dyn Error>> {
+        match (checkpointer.and_then(Checkpointer::resumed_from), &self.input) {
+            (Some(checkpoint), Some(input)) => {
+                eprintln!("Resuming after page id {:?}", checkpoint.page_id);
+                match &self.index {
+                    Some(index) => resume_multistream(input, index, checkpoint),
+                    None => resume_page_source(kind, input, checkpoint),
+                }
+            }
+            _ => {
+                let (input, meter) = dump_input::open_input_metered(self.input.as_deref())?;
+                Ok((new_page_source(kind, input), meter))
+            }
+        }
+    }
+
+    /// Open the input like `open`, for a tool that just iterates over the pages
+    pub fn open_checkpointed(&self, kind: ReaderKind) -> Result<CheckpointedSource, Box<dyn Error>> {
+        let checkpointer = self.checkpointer()?;
+        let (source, _) = self.open(kind, checkpointer.as_ref())?;
+        Ok(CheckpointedSource::new(source, checkpointer))
+    }
+}
+
+/// Open a dump file with the chosen reader, carrying on just after a checkpoint's last completed page.
+///
+/// The reader is handed the dump's header (through `</siteinfo>`) followed by the rest of the dump
+/// from the checkpoint, so it reads the siteinfo as usual. Its byte offsets are still offsets into
+/// the whole dump. The meter counts from the start of the file, as if it had all been read.
+pub fn resume_page_source(
+    kind: ReaderKind,
+    path: &Path,
+    checkpoint: &Checkpoint,
+) -> Result<(Box<dyn PageSource>, InputMeter), Box<dyn Error>> {
+    let offset = checkpoint
+        .offset
+        .ok_or("the checkpoint has no byte offset to resume from")?;
+    let header = read_header(dump_input::open_dump(path)?)?;
+    if offset < header.len() as u64 {
+        return Err(format!("checkpoint offset {} is inside the dump's header", offset).into());
+    }
+
+    let header_len = header.len() as u64;
+    let (rest, meter) = dump_input::open_dump_at(path, offset)?;
+    let input: DumpInput = Box::new(Cursor::new(header).chain(rest));
+    let source = Box::new(ResumedSource {
+        source: new_page_source(kind, input),
+        skipped: offset - header_len,
+    });
+    Ok((source, meter))
+}
+
+/// Open a multistream dump, carrying on just after a checkpoint's last completed page.
+///
+/// Only the bzip2 stream holding that page is decompressed to find it. The streams are read with
+/// quick-xml whichever reader was chosen, and without byte offsets, so the checkpoints saved from
+/// then on hold just the page id and can only be resumed from with the index.
+pub fn resume_multistream(
+    path: &Path,
+    index_path: &Path,
+    checkpoint: &Checkpoint,
+) -> Result<(Box<dyn PageSource>, InputMeter), Box<dyn Error>> {
+    let page_id = checkpoint
+        .page_id
+        .ok_or("the checkpoint has no page id to resume from")?;
+    let (dump, meter) = dump_input::open_file_metered(path)?;
+    let mut source = MultistreamSource::new(dump, MultistreamIndex::load(index_path)?);
+    source.resume_after(page_id)?;
+    Ok((Box::new(source), meter))
+}
+
+/// Everything before the first `<page>`
+fn read_header(mut input: DumpInput) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut header = Vec::new();
+    loop {
+        let start = header.len();
+        if input.read_until(b'\n', &mut header)? == 0 {
+            return Err("no <page> in the dump".into());
+        }
+        if let Some(at) = header[start..].windows(6).position(|w| w == b"<page>") {
+            header.truncate(start + at);
+            return Ok(header);
+        }
+    }
+}
+
+/// A reader started part way through a dump, whose byte offsets are shifted to match the whole dump
+struct ResumedSource {
+    source: Box<dyn PageSource>,
+    /// Bytes of the dump between the end of its header and where the reader picked up
+    skipped: u64,
+}
+
+impl PageSource for ResumedSource {
+    fn next_page(&mut self) -> Result<Option<Page>, Box<dyn Error>> {
+        self.source.next_page()
+    }
+
+    fn site_info(&self) -> Option<&SiteInfo> {
+        self.source.site_info()
+    }
+
+    fn set_all_revisions(&mut self, all: bool) {
+        self.source.set_all_revisions(all)
+    }
+
+    fn byte_offset(&self) -> Option<u64> {
+        self.source.byte_offset().map(|offset| offset + self.skipped)
+    }
+
+    fn recover(&mut self, recovery: Recovery) -> Result<bool, Box<dyn Error>> {
+        self.source.recover(recovery)
+    }
+}
+
+/// Keeps a checkpointer up to date for a tool that iterates over a source's pages.
+///
+/// A page counts as completed once the page after it is asked for. The checkpoint is saved when
+/// the source is dropped before the end of the dump, and removed once the dump has been read through.
+pub struct CheckpointedSource {
+    source: Box<dyn PageSource>,
+    checkpointer: Option<Checkpointer>,
+    /// Where the source got to with the page it returned last, which the tool may still be working on
+    returned: Option<Checkpoint>,
+}
+
+impl CheckpointedSource {
+    pub fn new(source: Box<dyn PageSource>, checkpointer: Option<Checkpointer>) -> Self {
+        CheckpointedSource {
+            source,
+            checkpointer,
+            returned: None,
+        }
+    }
+}
+
+impl PageSource for CheckpointedSource {
+    fn next_page(&mut self) -> Result<Option<Page>, Box<dyn Error>> {
+        let Some(checkpointer) = &mut self.checkpointer else {
+            return self.source.next_page();
+        };
+        if let Some(checkpoint) = self.returned.take() {
+            checkpointer.completed(checkpoint)?;
+        }
+
+        let page = self.source.next_page()?;
+        match &page {
+            Some(page) => {
+                self.returned = Some(Checkpoint {
+                    offset: self.source.byte_offset(),
+                    page_id: page.id,
+                })
+            }
+            None => {
+                if let Some(checkpointer) = self.checkpointer.take() {
+                    checkpointer.finish()?;
+                }
+            }
+        }
+        Ok(page)
+    }
+
+    fn site_info(&self) -> Option<&SiteInfo> {
+        self.source.site_info()
+    }
+
+    fn set_all_revisions(&mut self, all: bool) {
+        self.source.set_all_revisions(all)
+    }
+
+    fn byte_offset(&self) -> Option<u64> {
+        self.source.byte_offset()
+    }
+
+    fn recover(&mut self, recovery: Recovery) -> Result<bool, Box<dyn Error>> {
+        self.source.recover(recovery)
+    }
+}
+
+impl Drop for CheckpointedSource {
+    fn drop(&mut self) {
+        // Stopped short of the end, so the next run can carry on from here
+        if let Some(checkpointer) = &mut self.checkpointer {
+            if checkpointer.unsaved > 0 {
+                if let Err(e) = checkpointer.save() {
+                    eprintln!("Couldn't save the checkpoint: {}", e);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::conformance::read_all;
+
+    const DUMP: &str = include_str!("../fixtures/conformance/redirects.xml");
+
+    /// A fresh path in the temp directory, removed along with anything saved beside it on drop
+    struct TempPath(PathBuf);
+
+    impl TempPath {
+        fn new(name: &str) -> Self {
+            TempPath(std::env::temp_dir().join(format!("wikters-{}-{}", std::process::id(), name)))
+        }
+    }
+
+    impl Drop for TempPath {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = TempPath::new("checkpoint");
+        assert_eq!(Checkpoint::load(&path.0).unwrap(), None);
+
+        let checkpoint = Checkpoint {
+            offset: Some(12345),
+            page_id: Some(-2),
+        };
+        checkpoint.save(&path.0).unwrap();
+        assert_eq!(Checkpoint::load(&path.0).unwrap(), Some(checkpoint));
+
+        fs::write(&path.0, "offset twelve\n").unwrap();
+        assert!(Checkpoint::load(&path.0).is_err());
+    }
+
+    #[test]
+    fn test_checkpointer() {
+        let path = TempPath::new("checkpointer");
+        let mut source = new_page_source(ReaderKind::Qwikt, Box::new(DUMP.as_bytes()));
+        let mut checkpointer = Checkpointer::open(&path.0).unwrap().with_interval(2);
+        assert!(checkpointer.resumed_from().is_none());
+
+        let page = source.next_page().unwrap().unwrap();
+        checkpointer.page_done(&source, &page).unwrap();
+        assert!(!path.0.exists(), "saved before the interval was up");
+        let page = source.next_page().unwrap().unwrap();
+        checkpointer.page_done(&source, &page).unwrap();
+        let expected = Checkpoint {
+            offset: source.byte_offset(),
+            page_id: Some(702),
+        };
+        assert_eq!(Checkpoint::load(&path.0).unwrap().as_ref(), Some(&expected));
+
+        let reopened = Checkpointer::open(&path.0).unwrap();
+        assert_eq!(reopened.resumed_from(), Some(&expected));
+        reopened.finish().unwrap();
+        assert!(!path.0.exists());
+    }
+
+    #[test]
+    fn test_resume_page_source() {
+        let plain = TempPath::new("resume.xml");
+        fs::write(&plain.0, DUMP).unwrap();
+        let gzipped = TempPath::new("resume.xml.gz");
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(DUMP.as_bytes()).unwrap();
+        fs::write(&gzipped.0, gz.finish().unwrap()).unwrap();
+
+        for kind in ReaderKind::ALL {
+            let mut source = new_page_source(kind, Box::new(DUMP.as_bytes()));
+            let first = source.next_page().unwrap().unwrap();
+            let checkpoint = Checkpoint {
+                offset: source.byte_offset(),
+                page_id: first.id,
+            };
+            let rest = read_all(&mut source).unwrap();
+            assert_eq!(rest.len(), 1);
+
+            for path in [&plain, &gzipped] {
+                let (mut resumed, _) = resume_page_source(kind, &path.0, &checkpoint).unwrap();
+                assert_eq!(read_all(&mut resumed).unwrap(), rest, "{}", kind.name());
+                assert_eq!(resumed.site_info(), source.site_info(), "{}", kind.name());
+                assert_eq!(resumed.byte_offset(), source.byte_offset(), "{}", kind.name());
+            }
+        }
+
+        let past_the_end = Checkpoint {
+            offset: Some(DUMP.len() as u64 + 1),
+            page_id: None,
+        };
+        assert!(resume_page_source(ReaderKind::QuickXml, &plain.0, &past_the_end).is_err());
+        assert!(resume_page_source(ReaderKind::QuickXml, &gzipped.0, &past_the_end).is_err());
+    }
+
+    #[test]
+    fn test_checkpointed_source() {
+        let dump = TempPath::new("checkpointed.xml");
+        fs::write(&dump.0, DUMP).unwrap();
+        let path = TempPath::new("checkpointed");
+        let args = DumpArgs {
+            input: Some(dump.0.clone()),
+            index: None,
+            checkpoint: Some(path.0.clone()),
+        };
+
+        for kind in ReaderKind::ALL {
+            // The first page is done once the second is asked for, which the tool is still on when it stops
+            let mut source = args.open_checkpointed(kind).unwrap();
+            source.next_page().unwrap().unwrap();
+            source.next_page().unwrap().unwrap();
+            drop(source);
+            assert_eq!(Checkpoint::load(&path.0).unwrap().unwrap().page_id, Some(701), "{}", kind.name());
+
+            let mut source = args.open_checkpointed(kind).unwrap();
+            let titles: Vec<_> = read_all(&mut source).unwrap().into_iter().map(|page| page.title).collect();
+            assert_eq!(titles, ["Appendix:Colours"], "{}", kind.name());
+            assert!(!path.0.exists(), "{}", kind.name());
+        }
+    }
+
+    #[test]
+    fn test_resume_multistream() {
+        // A header stream, one stream per page and a footer stream, as in a multistream dump
+        let bz = |xml: &str| {
+            let mut encoder = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+            encoder.write_all(xml.as_bytes()).unwrap();
+            encoder.finish().unwrap()
+        };
+        let header_len = read_header(Box::new(DUMP.as_bytes())).unwrap().len();
+        let footer_start = DUMP.find("</mediawiki>").unwrap();
+        let mut multistream = bz(&DUMP[..header_len]);
+        let mut index = String::new();
+        let pages = read_all(&mut new_page_source(ReaderKind::QuickXml, Box::new(DUMP.as_bytes()))).unwrap();
+        for (page, xml) in pages.iter().zip(DUMP[header_len..footer_start].split_inclusive("</page>\n")) {
+            index.push_str(&format!("{}:{}:{}\n", multistream.len(), page.id.unwrap(), page.title));
+            multistream.extend(bz(xml));
+        }
+        multistream.extend(bz(&DUMP[footer_start..]));
+
+        let dump = TempPath::new("resume-multistream.xml.bz2");
+        fs::write(&dump.0, multistream).unwrap();
+        let index_path = TempPath::new("resume-multistream-index.txt");
+        fs::write(&index_path.0, index).unwrap();
+        let path = TempPath::new("resume-multistream");
+        let checkpoint = Checkpoint {
+            offset: None,
+            page_id: Some(701),
+        };
+        checkpoint.save(&path.0).unwrap();
+
+        let args = DumpArgs {
+            input: Some(dump.0.clone()),
+            index: Some(index_path.0.clone()),
+            checkpoint: Some(path.0.clone()),
+        };
+        let checkpointer = args.checkpointer().unwrap();
+        let (mut source, _) = args.open(ReaderKind::Regex, checkpointer.as_ref()).unwrap();
+        let titles: Vec<_> = read_all(&mut source).unwrap().into_iter().map(|page| page.title).collect();
+        assert_eq!(titles, ["Appendix:Colours"]);
+        assert_eq!(source.site_info().unwrap().sitename, "Wiktionary");
+
+        // Without the index the checkpoint's missing byte offset can't be resumed from
+        let args = DumpArgs { index: None, ..args };
+        assert!(args.open(ReaderKind::QuickXml, checkpointer.as_ref()).is_err());
+    }
+}
@@ -0,0 +1,187 @@
+//! Checking that the `PageSource` implementations agree with each other.
+//!
+//! The readers are written quite differently (quick-xml events, per-line regexes and string
+//! searches, qwikt's strict element-by-element matching), so each fixture in `fixtures/conformance/` is run
+//! through all of them and compared page by page against the quick-xml reader. The
+//! `compare_readers` binary does the same over a real dump.
+
+use std::error::Error;
+use std::fmt::Debug;
+
+use crate::{Page, PageSource};
+
+/// Describe the first field in which two pages differ, or None if they're identical.
+pub fn first_difference(expected: &Page, actual: &Page) -> Option<String> {
+    let (a, b) = (&expected.revision, &actual.revision);
+    field("title", &expected.title, &actual.title)
+        .or_else(|| field("ns", &expected.ns, &actual.ns))
+        .or_else(|| field("id", &expected.id, &actual.id))
+        .or_else(|| field("redirect", &expected.redirect, &actual.redirect))
+        .or_else(|| field("revision.id", &a.id, &b.id))
+        .or_else(|| field("revision.parent_id", &a.parent_id, &b.parent_id))
+        .or_else(|| field("revision.timestamp", &a.timestamp, &b.timestamp))
+        .or_else(|| field("revision.contributor", &a.contributor, &b.contributor))
+        .or_else(|| field("revision.comment", &a.comment, &b.comment))
+        .or_else(|| field("revision.minor", &a.minor, &b.minor))
+        .or_else(|| field("revision.origin", &a.origin, &b.origin))
+        .or_else(|| field("revision.model", &a.model, &b.model))
+        .or_else(|| field("revision.format", &a.format, &b.format))
+        .or_else(|| field("revision.sha1", &a.sha1, &b.sha1))
+        .or_else(|| field("revision.bytes", &a.bytes, &b.bytes))
+        .or_else(|| text_difference(&expected.rev_text, &actual.rev_text))
+}
+
+fn field<T: PartialEq + Debug>(name: &str, expected: &T, actual: &T) -> Option<String> {
+    (expected != actual).then(|| format!("{}: expected {:?}, got {:?}", name, expected, actual))
+}
+
+/// Report where two texts first differ, with a little context from each
+fn text_difference(expected: &str, actual: &str) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+    let at = expected
+        .bytes()
+        .zip(actual.bytes())
+        .position(|(a, b)| a != b)
+        .unwrap_or(expected.len().min(actual.len()));
+    Some(format!(
+        "rev_text differs at byte {} (lengths {} and {}): expected {:?}, got {:?}",
+        at,
+        expected.len(),
+        actual.len(),
+        context(expected, at),
+        context(actual, at)
+    ))
+}
+
+fn context(text: &str, at: usize) -> &str {
+    let mut start = at.saturating_sub(20).min(text.len());
+    while !text.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (at + 20).min(text.len());
+    while !text.is_char_boundary(end) {
+        end += 1;
+    }
+    &text[start..end]
+}
+
+/// Read every page from a source, stopping at the first error.
+pub fn read_all(source: &mut dyn PageSource) -> Result<Vec<Page>, Box<dyn Error>> {
+    let mut pages = Vec::new();
+    while let Some(page) = source.next_page()? {
+        pages.push(page);
+    }
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{new_page_source, Contributor, ReaderKind, SiteInfo};
+
+    const FIXTURES: &[(&str, &str)] = &[
+        ("empty_text", include_str!("../fixtures/conformance/empty_text.xml")),
+        ("text_deleted", include_str!("../fixtures/conformance/text_deleted.xml")),
+        ("redirects", include_str!("../fixtures/conformance/redirects.xml")),
+        ("minor_ip", include_str!("../fixtures/conformance/minor_ip.xml")),
+        ("entities", include_str!("../fixtures/conformance/entities.xml")),
+        ("cdata_like", include_str!("../fixtures/conformance/cdata_like.xml")),
+        ("multiline_title", include_str!("../fixtures/conformance/multiline_title.xml")),
+        ("export_0_10", include_str!("../fixtures/conformance/export_0_10.xml")),
+        ("full_history", include_str!("../fixtures/conformance/full_history.xml")),
+        ("optional_fields", include_str!("../fixtures/conformance/optional_fields.xml")),
+    ];
+
+    /// Run one reader over a fixture, describing the first way it differs from the reference
+    fn divergence(kind: ReaderKind, xml: &'static str, expected: &[Page], site_info: Option<&SiteInfo>) -> Option<String> {
+        let mut source = new_page_source(kind, Box::new(xml.as_bytes()));
+        let pages = match read_all(&mut source) {
+            Ok(pages) => pages,
+            Err(e) => return Some(format!("error: {}", e)),
+        };
+        for (i, (expected, actual)) in expected.iter().zip(&pages).enumerate() {
+            if let Some(diff) = first_difference(expected, actual) {
+                return Some(format!("page {}: {}", i, diff));
+            }
+        }
+        if pages.len() != expected.len() {
+            return Some(format!("expected {} pages, got {}", expected.len(), pages.len()));
+        }
+        field("site_info", &site_info, &source.site_info())
+    }
+
+    #[test]
+    fn test_conformance() {
+        let mut failures = Vec::new();
+        for &(name, xml) in FIXTURES {
+            let mut reference = new_page_source(ReaderKind::QuickXml, Box::new(xml.as_bytes()));
+            let expected =
+                read_all(&mut reference).unwrap_or_else(|e| panic!("{}: reference reader failed: {}", name, e));
+            assert!(!expected.is_empty(), "{}: no pages", name);
+            let site_info = reference.site_info();
+            assert!(site_info.is_some_and(|s| !s.namespaces.is_empty()), "{}: no siteinfo", name);
+
+            for kind in ReaderKind::ALL.into_iter().skip(1) {
+                if let Some(diff) = divergence(kind, xml, &expected, site_info) {
+                    failures.push(format!("{} / {}: {}", name, kind.name(), diff));
+                }
+            }
+        }
+        assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+    }
+
+    #[test]
+    fn test_entities_decoded() {
+        let entities = FIXTURES.iter().find(|(name, _)| *name == "entities").unwrap().1;
+        let cdata_like = FIXTURES.iter().find(|(name, _)| *name == "cdata_like").unwrap().1;
+        for kind in ReaderKind::ALL {
+            let pages = read_all(&mut new_page_source(kind, Box::new(entities.as_bytes()))).unwrap();
+            let page = &pages[0];
+            assert_eq!(page.title, "AT&T", "{}", kind.name());
+            assert_eq!(page.redirect.as_deref(), Some("Q&A \"quoted\""), "{}", kind.name());
+            assert_eq!(page.revision.comment.as_deref(), Some("fix <ref> & quotes"), "{}", kind.name());
+            assert_eq!(
+                page.revision.contributor,
+                Some(Contributor::User {
+                    username: "O'Brien".to_string(),
+                    id: Some(80)
+                }),
+                "{}",
+                kind.name()
+            );
+            assert_eq!(
+                page.rev_text,
+                "==English==\nFish & chips.<ref>\"Cod\" 'n' chips</ref>\n☺ ☃ < >",
+                "{}",
+                kind.name()
+            );
+
+            let pages = read_all(&mut new_page_source(kind, Box::new(cdata_like.as_bytes()))).unwrap();
+            assert!(
+                pages[0].rev_text.starts_with("==English==\n<![CDATA[ not a real section ]]>\n</text>\n<page>\n"),
+                "{}",
+                kind.name()
+            );
+        }
+    }
+
+    #[test]
+    fn test_first_difference() {
+        let a = Page {
+            title: "cat".to_string(),
+            rev_text: "==English==\n===Noun===".to_string(),
+            ..Page::new()
+        };
+        assert_eq!(first_difference(&a, &a.clone()), None);
+
+        let mut b = a.clone();
+        b.rev_text = "==English==\n===Verb===".to_string();
+        let diff = first_difference(&a, &b).unwrap();
+        assert!(diff.starts_with("rev_text differs at byte 15"), "{}", diff);
+
+        b.id = Some(1);
+        assert_eq!(first_difference(&a, &b).unwrap(), "id: expected None, got Some(1)");
+    }
+}
@@ -0,0 +1,225 @@
+//! Opening dump files for the readers, decompressing in-process.
+//!
+//! Wiktionary dumps are distributed as `.bz2`, and people also keep `.gz`, `.zst` or plain `.xml`
+//! copies around. Rather than trust the file extension we sniff the magic bytes at the start of
+//! the file, so a renamed or extensionless dump still opens correctly.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+
+/// Boxed input stream handed to the readers. `Send` so a source can be moved to a worker thread.
+pub type DumpInput = Box<dyn BufRead + Send>;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Compression {
+    None,
+    Bzip2,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    /// Identify the compression format from the first few bytes of a file.
+    pub fn from_magic(magic: &[u8]) -> Self {
+        if magic.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else if magic.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+}
+
+/// How much of an opened dump has been read, before decompression, for progress reports. It is
+/// shared with the input stream, so it can be checked while a reader is consuming it.
+#[derive(Debug, Clone, Default)]
+pub struct InputMeter {
+    read: Arc<AtomicU64>,
+    size: Option<u64>,
+}
+
+impl InputMeter {
+    /// Bytes of the file (or stdin) read so far
+    pub fn bytes_read(&self) -> u64 {
+        self.read.load(Ordering::Relaxed)
+    }
+
+    /// Size of the file, if the input is one
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+}
+
+/// Open a dump file, sniffing its compression and wrapping it in the matching decoder.
+///
+/// Multistream bzip2 and concatenated gzip members are both read through to the end.
+pub fn open_dump(path: &Path) -> Result<DumpInput, Box<dyn Error>> {
+    open_dump_metered(path).map(|(input, _)| input)
+}
+
+/// Open a dump file like `open_dump`, along with a meter of how much of it has been read.
+pub fn open_dump_metered(path: &Path) -> Result<(DumpInput, InputMeter), Box<dyn Error>> {
+    let (mut buffered, meter) = open_file(path)?;
+    let compression = Compression::from_magic(buffered.fill_buf()?);
+
+    Ok((wrap_decoder(buffered, compression)?, meter))
+}
+
+/// Open a dump file `offset` bytes into its decompressed contents, for resuming a run.
+///
+/// Plain XML files are seeked; compressed ones have to be decompressed up to that point.
+pub fn open_dump_at(path: &Path, offset: u64) -> Result<(DumpInput, InputMeter), Box<dyn Error>> {
+    let (mut buffered, meter) = open_file(path)?;
+    let compression = Compression::from_magic(buffered.fill_buf()?);
+
+    if compression == Compression::None {
+        if Some(offset) > meter.size() {
+            return Err(format!("{}: ends before byte {}", path.display(), offset).into());
+        }
+        buffered.seek(SeekFrom::Start(offset))?;
+        return Ok((Box::new(buffered), meter));
+    }
+
+    let mut input = wrap_decoder(buffered, compression)?;
+    if io::copy(&mut (&mut input).take(offset), &mut io::sink())? < offset {
+        return Err(format!("{}: ends before byte {}", path.display(), offset).into());
+    }
+    Ok((input, meter))
+}
+
+/// Open a file as it is, without decompressing it, to seek around in (as for a multistream dump),
+/// along with a meter of where in it reading has got to.
+pub fn open_file_metered(path: &Path) -> Result<(impl BufRead + Seek + Send, InputMeter), Box<dyn Error>> {
+    open_file(path)
+}
+
+/// Open the given dump file, or fall back to stdin when no path is given.
+pub fn open_input(path: Option<&Path>) -> Result<DumpInput, Box<dyn Error>> {
+    open_input_metered(path).map(|(input, _)| input)
+}
+
+/// Open the given dump file or stdin like `open_input`, along with a meter of how much of it has been read.
+pub fn open_input_metered(path: Option<&Path>) -> Result<(DumpInput, InputMeter), Box<dyn Error>> {
+    match path {
+        Some(path) => open_dump_metered(path),
+        None => {
+            let meter = InputMeter::default();
+            let stdin = Metered {
+                inner: io::stdin(),
+                read: meter.read.clone(),
+            };
+            Ok((Box::new(BufReader::new(stdin)), meter))
+        }
+    }
+}
+
+type MeteredFile = BufReader<Metered<File>>;
+
+fn open_file(path: &Path) -> Result<(MeteredFile, InputMeter), Box<dyn Error>> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let meter = InputMeter {
+        read: Arc::default(),
+        size: Some(file.metadata()?.len()),
+    };
+    let file = Metered {
+        inner: file,
+        read: meter.read.clone(),
+    };
+    Ok((BufReader::with_capacity(1 << 16, file), meter))
+}
+
+/// Counts the bytes read through it into an `InputMeter`
+struct Metered<R> {
+    inner: R,
+    read: Arc<AtomicU64>,
+}
+
+impl<R: Read> Read for Metered<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for Metered<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = self.inner.seek(pos)?;
+        self.read.store(position, Ordering::Relaxed);
+        Ok(position)
+    }
+}
+
+fn wrap_decoder<R: BufRead + Send + 'static>(reader: R, compression: Compression) -> io::Result<DumpInput> {
+    Ok(match compression {
+        Compression::None => Box::new(reader),
+        Compression::Bzip2 => Box::new(BufReader::with_capacity(1 << 16, MultiBzDecoder::new(reader))),
+        Compression::Gzip => Box::new(BufReader::with_capacity(1 << 16, MultiGzDecoder::new(reader))),
+        Compression::Zstd => Box::new(BufReader::with_capacity(1 << 16, zstd::Decoder::with_buffer(reader)?)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_magic() {
+        assert_eq!(Compression::from_magic(b"BZh91AY&SY"), Compression::Bzip2);
+        assert_eq!(Compression::from_magic(&[0x1f, 0x8b, 0x08]), Compression::Gzip);
+        assert_eq!(Compression::from_magic(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]), Compression::Zstd);
+        assert_eq!(Compression::from_magic(b"<?xml version"), Compression::None);
+        assert_eq!(Compression::from_magic(b""), Compression::None);
+    }
+
+    #[test]
+    fn test_decompress_each_format() {
+        use std::io::{Read, Write};
+
+        let xml = b"<mediawiki>\n</mediawiki>\n";
+
+        let mut bz = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        bz.write_all(xml).unwrap();
+        let bz = bz.finish().unwrap();
+
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(xml).unwrap();
+        let gz = gz.finish().unwrap();
+
+        let zst = zstd::encode_all(&xml[..], 0).unwrap();
+
+        for data in [xml.to_vec(), bz, gz, zst] {
+            let compression = Compression::from_magic(&data);
+            let mut input = wrap_decoder(io::Cursor::new(data), compression).unwrap();
+            let mut out = Vec::new();
+            input.read_to_end(&mut out).unwrap();
+            assert_eq!(out, xml);
+        }
+    }
+
+    #[test]
+    fn test_meter() {
+        let path = std::env::temp_dir().join(format!("wikters-{}-meter.xml", std::process::id()));
+        let xml = b"<mediawiki>\n</mediawiki>\n";
+        std::fs::write(&path, xml).unwrap();
+
+        let (mut input, meter) = open_dump_metered(&path).unwrap();
+        assert_eq!(meter.size(), Some(xml.len() as u64));
+        io::copy(&mut input, &mut io::sink()).unwrap();
+        assert_eq!(meter.bytes_read(), xml.len() as u64);
+
+        let (_, meter) = open_dump_at(&path, 12).unwrap();
+        assert_eq!(meter.bytes_read(), 12);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,121 @@
+//! Decoding XML entity and character references.
+//!
+//! The hand-rolled readers copy titles, text and attribute values straight out of the dump, so
+//! they still hold `&amp;`, `&lt;`, `&#039;` and so on. Dumps only use the five predefined
+//! entities and numeric references, which is all we decode. Anything else is left as written.
+//! `escape` goes the other way, for writing our own XML output.
+
+use std::borrow::Cow;
+
+/// The character for an entity name as it appears between `&` and `;`, e.g. `amp`, `#39` or `#x263A`.
+pub fn entity_char(name: &str) -> Option<char> {
+    match name {
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "amp" => Some('&'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => {
+            let number = name.strip_prefix('#')?;
+            let code = match number.strip_prefix('x') {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => number.parse::<u32>().ok()?,
+            };
+            char::from_u32(code).filter(|&c| c != '\0')
+        }
+    }
+}
+
+/// Replace entity and character references with the characters they stand for.
+///
+/// Borrows the input unchanged when it has no `&`, which is the case for most lines of wikitext.
+pub fn unescape(s: &str) -> Cow<'_, str> {
+    let Some(first) = s.find('&') else {
+        return Cow::Borrowed(s);
+    };
+
+    let mut out = String::with_capacity(s.len());
+    out.push_str(&s[..first]);
+    let mut rest = &s[first..];
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        // Entity names are short, so don't scan far for the semicolon
+        let decoded = rest[1..]
+            .bytes()
+            .take(10)
+            .position(|b| b == b';')
+            .and_then(|semi| Some((entity_char(&rest[1..1 + semi])?, semi + 2)));
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+/// Escape the characters that can't appear as written in XML text or a double-quoted attribute value.
+pub fn escape(s: &str) -> Cow<'_, str> {
+    if !s.contains(['&', '<', '>', '"']) {
+        return Cow::Borrowed(s);
+    }
+
+    let mut out = String::with_capacity(s.len() + 8);
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(c),
+        }
+    }
+    Cow::Owned(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_predefined_entities() {
+        assert_eq!(unescape("&lt;ref&gt;"), "<ref>");
+        assert_eq!(unescape("AT&amp;T"), "AT&T");
+        assert_eq!(unescape("&quot;quoted&quot;"), "\"quoted\"");
+        assert_eq!(unescape("O&apos;Brien"), "O'Brien");
+        assert_eq!(unescape("&amp;lt;"), "&lt;");
+    }
+
+    #[test]
+    fn test_numeric_references() {
+        assert_eq!(unescape("&#039;n&#39;"), "'n'");
+        assert_eq!(unescape("&#x263A; &#x263a; &#9731;"), "☺ ☺ ☃");
+        assert_eq!(unescape("&#x1F600;"), "😀");
+        assert_eq!(unescape("&#128512;"), "😀");
+    }
+
+    #[test]
+    fn test_left_as_written() {
+        assert!(matches!(unescape("==English=="), Cow::Borrowed(_)));
+        assert_eq!(unescape("fish & chips"), "fish & chips");
+        assert_eq!(unescape("&nbsp;"), "&nbsp;");
+        assert_eq!(unescape("&amp"), "&amp");
+        assert_eq!(unescape("&#xD800; &#0; &#x; &#12a;"), "&#xD800; &#0; &#x; &#12a;");
+        assert_eq!(unescape("&&amp;;"), "&&;");
+        assert_eq!(unescape("tail &"), "tail &");
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("plain"), "plain");
+        assert_eq!(escape("<ref name=\"a\">AT&T</ref>"), "&lt;ref name=&quot;a&quot;&gt;AT&amp;T&lt;/ref&gt;");
+        assert_eq!(unescape(&escape("a < b && \"c\"")), "a < b && \"c\"");
+    }
+}
@@ -0,0 +1,433 @@
+//! A page as data: its languages, the etymologies in each, and the parts of speech under them.
+//!
+//! Wiktionary lays a language section out in several ways (see DISCOVERIES.md). Etymology and
+//! Pronunciation can be flat, level-3 sections followed by level-3 parts of speech, or a numbered
+//! `Etymology N` can hold its own Pronunciation and parts of speech at level 4. `parse_entry` reads
+//! all of them into one shape: every part of speech sits in an `EtymologyGroup`, together with the
+//! etymology and the pronunciations that apply to it.
+//!
+//! | Layout                 | Headings under the language                        | Groups                                          |
+//! |------------------------|----------------------------------------------------|-------------------------------------------------|
+//! | `PosOnly`              | `Noun`                                             | one, with no etymology text                     |
+//! | `EtymOnly`             | `Etymology`, `Noun`                                | one                                             |
+//! | `PronOnly`             | `Pronunciation`, `Noun`                            | one, with no etymology text                     |
+//! | `EtymFlatThenPronFlat` | `Etymology`, `Pronunciation`, `Noun`               | one                                             |
+//! | `PronFlatThenEtymFlat` | `Pronunciation`, `Etymology 1`, `Noun`, `Etymology 2`, `Verb` | one per etymology, each with the pronunciation |
+//! | `EtymWithNestedPron`   | `Etymology 1` > `Pronunciation`, `Noun`; `Etymology 2` > ... | one per etymology, each with its own pronunciation |
+//! | `PronWithNestedEtym`   | `Pronunciation` > `Etymology`; `Noun`              | one per nested etymology, with the pronunciation |
+//! | `Other`                | only `Anagrams` and the like, or no headings       | none                                            |
+//!
+//! Put generally: a part of speech belongs to the etymology before it at its own level, or the one
+//! it's nested in, and gets a group with no etymology text when there's neither. A Pronunciation
+//! belongs to the etymology it's nested in, or the flat etymology before it; one that comes before
+//! any etymology, or after an etymology that nests its parts of speech, is shared by every group
+//! in the language. A Pronunciation that nests parts of speech (`Pronunciation 1` > `Noun`) starts
+//! a group of its own. Other sections, such as Alternative forms, Translations and Anagrams, are
+//! left out.
+
+use std::ops::Range;
+
+use crate::section_tree::SectionTree;
+use crate::senses::{self, Sense};
+use crate::template::{self, Template};
+use crate::wikitext_splitter::{self, Chunk, HeadingRef};
+use crate::wikitext_tree::{self, NodeKind};
+use crate::Page;
+
+/// Part-of-speech headings, as Wiktionary's entry layout guide lists them
+pub const POS_HEADINGS: &[&str] = &[
+    "Adjective", "Adverb", "Affix", "Ambiposition", "Article", "Circumfix", "Circumposition", "Classifier",
+    "Combining form", "Conjunction", "Contraction", "Counter", "Determiner", "Diacritical mark", "Han character",
+    "Hanja", "Hanzi", "Ideophone", "Infix", "Interfix", "Interjection", "Kanji", "Letter", "Noun", "Number",
+    "Numeral", "Participle", "Particle", "Phrase", "Postposition", "Prefix", "Preposition",
+    "Prepositional phrase", "Pronoun", "Proper noun", "Proverb", "Punctuation mark", "Romanization", "Root",
+    "Suffix", "Syllable", "Symbol", "Verb",
+];
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Entry {
+    pub title: String,
+    /// The level-2 language sections, in page order
+    pub languages: Vec<LanguageSection>,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct LanguageSection {
+    /// The heading, e.g. `English`
+    pub name: String,
+    /// The language code of the first head template, e.g. `en` from `{{en-noun}}` or `mul` from
+    /// `{{head|mul|symbol}}`
+    pub code: Option<String>,
+    pub etymologies: Vec<EtymologyGroup>,
+    /// Byte range of everything under the heading, up to the next language
+    pub span: Range<usize>,
+}
+
+/// Parts of speech with the etymology and pronunciations they share
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct EtymologyGroup {
+    /// The etymology as wikitext, trimmed; empty if the parts of speech have none
+    pub text: String,
+    /// The `*` lines of the Pronunciation sections that apply, after the list markers, shared
+    /// ones first
+    pub pronunciations: Vec<String>,
+    pub pos_blocks: Vec<PosBlock>,
+}
+
+/// A part-of-speech section
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct PosBlock {
+    /// The heading, e.g. `Noun`
+    pub pos: String,
+    /// The line starting with the head template, e.g. `{{en-noun|-}}`, running on to the line the
+    /// template ends on if it's split over several; empty if there's none
+    pub head: String,
+    pub senses: Vec<Sense>,
+    /// Byte range of the text under the heading, up to the next heading. The senses' spans are
+    /// relative to its start.
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum SectionKind {
+    Etymology,
+    Pronunciation,
+    Pos,
+    Other,
+}
+
+impl SectionKind {
+    fn of(heading: &str) -> Self {
+        // Numbered as in `Etymology 2` or `Pronunciation 1`
+        let name = heading.trim_end_matches(|c: char| c.is_ascii_digit()).trim_end();
+        if name == "Etymology" {
+            SectionKind::Etymology
+        } else if name == "Pronunciation" {
+            SectionKind::Pronunciation
+        } else if POS_HEADINGS.iter().any(|pos| pos.eq_ignore_ascii_case(name)) {
+            SectionKind::Pos
+        } else {
+            SectionKind::Other
+        }
+    }
+}
+
+/// Read a page into languages, etymologies and parts of speech. Spans are byte ranges in the
+/// page's text.
+pub fn parse_entry(page: &Page) -> Entry {
+    let (headings, chunks) = wikitext_splitter::split_spans(&page.rev_text);
+    let tree = SectionTree::new(&headings);
+    let languages = (0..headings.len())
+        .filter(|&i| headings[i].level == 2)
+        .map(|i| language_section(&tree, &chunks, i))
+        .collect();
+    Entry {
+        title: page.title.clone(),
+        languages,
+    }
+}
+
+fn language_section(tree: &SectionTree<HeadingRef>, chunks: &[Chunk], index: usize) -> LanguageSection {
+    let mut groups = Groups {
+        tree,
+        chunks,
+        groups: Vec::new(),
+        shared: Vec::new(),
+        open: false,
+    };
+    for &child in tree.children(index) {
+        groups.section(child);
+    }
+    let Groups { mut groups, shared, .. } = groups;
+    for group in &mut groups {
+        group.pronunciations.splice(0..0, shared.iter().cloned());
+    }
+
+    let section_chunks = tree.section_chunks(index, chunks);
+    let code = groups
+        .iter()
+        .flat_map(|group| &group.pos_blocks)
+        .find_map(|block| template::parse_templates(&block.head).first().and_then(head_code));
+    LanguageSection {
+        name: tree.heading(index).text.to_string(),
+        code,
+        etymologies: groups,
+        span: section_chunks[0].span.start..section_chunks[section_chunks.len() - 1].span.end,
+    }
+}
+
+/// The groups of a language section, built up section by section
+struct Groups<'t, 'a> {
+    tree: &'t SectionTree<'a, HeadingRef<'a>>,
+    chunks: &'t [Chunk<'a>],
+    groups: Vec<EtymologyGroup>,
+    /// Pronunciations for every group
+    shared: Vec<String>,
+    /// Whether the last group takes the flat sections that follow it
+    open: bool,
+}
+
+impl<'a> Groups<'_, 'a> {
+    /// Sort a section directly under the language into the groups
+    fn section(&mut self, index: usize) {
+        let tree = self.tree;
+        match SectionKind::of(tree.heading(index).text) {
+            SectionKind::Etymology => self.group(index, self.chunk_text(index), Vec::new()),
+            SectionKind::Pronunciation => {
+                let lines = pronunciation_lines(self.chunk_text(index));
+                let children = tree.children(index);
+                let kinds: Vec<_> = children.iter().map(|&i| SectionKind::of(tree.heading(i).text)).collect();
+                if kinds.contains(&SectionKind::Etymology) {
+                    for (&child, kind) in children.iter().zip(kinds) {
+                        if kind == SectionKind::Etymology {
+                            self.group(child, self.chunk_text(child), lines.clone());
+                        }
+                    }
+                } else if kinds.contains(&SectionKind::Pos) {
+                    self.group(index, "", lines);
+                } else if self.open {
+                    self.groups.last_mut().unwrap().pronunciations.extend(lines);
+                } else {
+                    self.shared.extend(lines);
+                }
+            }
+            SectionKind::Pos => {
+                if !self.open {
+                    self.groups.push(EtymologyGroup::default());
+                    self.open = true;
+                }
+                let block = self.pos_block(index);
+                self.groups.last_mut().unwrap().pos_blocks.push(block);
+            }
+            SectionKind::Other => {}
+        }
+    }
+
+    /// Add a group for the section at `index`, with what's nested in it. One whose parts of speech
+    /// aren't nested takes those that follow.
+    fn group(&mut self, index: usize, text: &str, pronunciations: Vec<String>) {
+        let mut group = EtymologyGroup {
+            text: text.trim().to_string(),
+            pronunciations,
+            pos_blocks: Vec::new(),
+        };
+        for &child in self.tree.children(index) {
+            match SectionKind::of(self.tree.heading(child).text) {
+                SectionKind::Pronunciation => group.pronunciations.extend(pronunciation_lines(self.chunk_text(child))),
+                SectionKind::Pos => group.pos_blocks.push(self.pos_block(child)),
+                _ => {}
+            }
+        }
+        self.open = group.pos_blocks.is_empty();
+        self.groups.push(group);
+    }
+
+    fn pos_block(&self, index: usize) -> PosBlock {
+        let chunk = &self.chunks[index + 1];
+        PosBlock {
+            pos: self.tree.heading(index).text.to_string(),
+            head: head_line(chunk.text).to_string(),
+            senses: senses::parse_senses(chunk.text),
+            span: chunk.span.clone(),
+        }
+    }
+
+    /// The text between a section's heading and the next heading
+    fn chunk_text(&self, index: usize) -> &'a str {
+        self.chunks[index + 1].text
+    }
+}
+
+/// The list lines of a Pronunciation section, without their markers
+fn pronunciation_lines(text: &str) -> Vec<String> {
+    let tree = wikitext_tree::parse(text);
+    tree.children
+        .iter()
+        .filter(|node| node.kind == NodeKind::ListItem)
+        .map(|item| text[item.children[0].span.end..item.span.end].trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// The line a head template starts, to the end of the line it ends on
+fn head_line(text: &str) -> &str {
+    for template in template::parse_templates(text) {
+        let start = template.span.start;
+        if !text[..start].is_empty() && !text[..start].ends_with('\n') || head_code(&template).is_none() {
+            continue;
+        }
+        let end = text[template.span.end..].find('\n').map_or(text.len(), |n| template.span.end + n);
+        return text[start..end].strip_suffix('\r').unwrap_or(&text[start..end]);
+    }
+    ""
+}
+
+/// The language a head template is for: the first parameter of `{{head}}`, or the code a
+/// language's own head templates start with, like `en` in `{{en-noun}}`. Request templates like
+/// `{{rfv-sense}}` look like the latter but aren't head templates.
+fn head_code(template: &Template) -> Option<String> {
+    if template.name == "head" {
+        let code = template.positional.first()?.text();
+        let code = code.trim();
+        return (!code.is_empty()).then(|| code.to_string());
+    }
+    let (code, _) = template.name.split_once('-')?;
+    let is_code = (2..=3).contains(&code.len())
+        && code.bytes().all(|b| b.is_ascii_lowercase())
+        && !code.starts_with("rf");
+    is_code.then(|| code.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(text: &str) -> Entry {
+        let mut page = Page::new();
+        page.title = "word".to_string();
+        page.rev_text = text.to_string();
+        parse_entry(&page)
+    }
+
+    /// Each group as its etymology text, pronunciations and parts of speech
+    fn groups(text: &str) -> Vec<(String, Vec<String>, Vec<String>)> {
+        let entry = entry(text);
+        assert_eq!(entry.languages.len(), 1);
+        entry.languages[0]
+            .etymologies
+            .iter()
+            .map(|group| {
+                let pos = group.pos_blocks.iter().map(|block| block.pos.clone()).collect();
+                (group.text.clone(), group.pronunciations.clone(), pos)
+            })
+            .collect()
+    }
+
+    fn group(text: &str, pronunciations: &[&str], pos: &[&str]) -> (String, Vec<String>, Vec<String>) {
+        let strings = |items: &[&str]| items.iter().map(|item| item.to_string()).collect();
+        (text.to_string(), strings(pronunciations), strings(pos))
+    }
+
+    #[test]
+    fn test_entry() {
+        let text = "{{also|Word}}
+==English==
+===Etymology===
+From {{inh|en|enm|word}}.
+
+===Pronunciation===
+* {{IPA|en|/wɜːd/}}
+* {{audio|en|word.ogg}}
+
+===Noun===
+{{en-noun}}
+
+# A [[unit]] of language.
+#: {{ux|en|Say a word.}}
+
+====Translations====
+* French: {{t|fr|mot}}
+
+===Verb===
+{{en-verb}}
+
+# To [[phrase]].
+
+==Translingual==
+===Symbol===
+{{head|mul|symbol}}
+# A symbol.
+";
+        let entry = entry(text);
+        assert_eq!(entry.title, "word");
+        assert_eq!(entry.languages.len(), 2);
+
+        let english = &entry.languages[0];
+        assert_eq!(english.name, "English");
+        assert_eq!(english.code.as_deref(), Some("en"));
+        assert_eq!(&text[english.span.clone()], &text[text.find("===Etym").unwrap()..text.find("==Translingual").unwrap()]);
+        assert_eq!(english.etymologies.len(), 1);
+
+        let group = &english.etymologies[0];
+        assert_eq!(group.text, "From {{inh|en|enm|word}}.");
+        assert_eq!(group.pronunciations, ["{{IPA|en|/wɜːd/}}", "{{audio|en|word.ogg}}"]);
+        assert_eq!(group.pos_blocks.len(), 2);
+        let noun = &group.pos_blocks[0];
+        assert_eq!((noun.pos.as_str(), noun.head.as_str()), ("Noun", "{{en-noun}}"));
+        assert_eq!(noun.senses.len(), 1);
+        assert_eq!(noun.senses[0].gloss, "A [[unit]] of language.");
+        assert_eq!(noun.senses[0].examples.len(), 1);
+        assert_eq!(&text[noun.span.start + noun.senses[0].gloss_span.start..][..2], "# ");
+        assert_eq!(group.pos_blocks[1].head, "{{en-verb}}");
+
+        let translingual = &entry.languages[1];
+        assert_eq!(translingual.code.as_deref(), Some("mul"));
+        assert_eq!(translingual.etymologies[0].pos_blocks[0].head, "{{head|mul|symbol}}");
+    }
+
+    #[test]
+    fn test_request_templates_before_head() {
+        let entry = entry("==French==\n===Noun===\n{{rfc-header|Noun|lang=fr}}\n{{fr-noun|m}}\n# A word.\n");
+        let language = &entry.languages[0];
+        assert_eq!(language.code.as_deref(), Some("fr"));
+        assert_eq!(language.etymologies[0].pos_blocks[0].head, "{{fr-noun|m}}");
+    }
+
+    #[test]
+    fn test_layouts() {
+        // PosOnly
+        assert_eq!(groups("==English==\n===Noun===\n# A.\n===Anagrams===\n"), [group("", &[], &["Noun"])]);
+        // EtymOnly
+        assert_eq!(groups("==English==\n===Etymology===\nE.\n===Noun===\n"), [group("E.", &[], &["Noun"])]);
+        // PronOnly
+        assert_eq!(groups("==English==\n===Pronunciation===\n* P\n===Noun===\n"), [group("", &["P"], &["Noun"])]);
+        // EtymFlatThenPronFlat
+        assert_eq!(
+            groups("==English==\n===Etymology===\nE.\n===Pronunciation===\n* P\n===Noun===\n===Verb===\n"),
+            [group("E.", &["P"], &["Noun", "Verb"])]
+        );
+        // PronFlatThenEtymFlat, with numbered etymologies sharing the pronunciation
+        assert_eq!(
+            groups("==English==\n===Pronunciation===\n* P\n===Etymology 1===\nE1.\n===Noun===\n===Etymology 2===\nE2.\n===Verb===\n"),
+            [group("E1.", &["P"], &["Noun"]), group("E2.", &["P"], &["Verb"])]
+        );
+        // EtymWithNestedPron, and a shared pronunciation after etymologies that nest their parts of speech
+        let text = "==English==
+===Etymology 1===
+E1.
+====Pronunciation====
+* P1
+====Noun====
+===Etymology 2===
+E2.
+====Verb====
+====Noun====
+===Pronunciation===
+* P
+";
+        assert_eq!(groups(text), [group("E1.", &["P", "P1"], &["Noun"]), group("E2.", &["P"], &["Verb", "Noun"])]);
+        // PronWithNestedEtym
+        assert_eq!(
+            groups("==English==\n===Pronunciation===\n* P\n====Etymology====\nE.\n===Noun===\n"),
+            [group("E.", &["P"], &["Noun"])]
+        );
+        // A pronunciation with its own parts of speech
+        assert_eq!(
+            groups("==English==\n===Pronunciation 1===\n* P1\n====Noun====\n===Pronunciation 2===\n* P2\n====Verb====\n"),
+            [group("", &["P1"], &["Noun"]), group("", &["P2"], &["Verb"])]
+        );
+        // Other
+        assert_eq!(groups("==English==\n===Anagrams===\n* drow\n"), []);
+        assert_eq!(groups("==English==\nNo sections.\n"), []);
+        assert!(entry("Not an entry.").languages.is_empty());
+    }
+
+    #[test]
+    fn test_head_line() {
+        assert_eq!(head_line("{{wikipedia}}\n{{en-noun|s\n|es}} [[x]]\r\n# A."), "{{en-noun|s\n|es}} [[x]]");
+        assert_eq!(head_line("{{head|de|noun|g=m}}"), "{{head|de|noun|g=m}}");
+        assert_eq!(head_line("See {{en-noun}}\n# A."), "");
+        assert_eq!(head_line("{{rfc-header|Noun}}\n{{rfv-sense}}\n{{fr-noun|m}}\n# A."), "{{fr-noun|m}}");
+    }
+}
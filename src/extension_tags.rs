@@ -0,0 +1,262 @@
+//! Finding comments and extension tags, whose content isn't page structure.
+//!
+//! `<!-- ==Noun== -->` isn't a heading, `<nowiki>{{l}}</nowiki>` isn't a template, and neither is
+//! a `{{` in `<math>` or in a `<ref>` citation, but text scanned line by line or brace by brace
+//! takes them for real structure. MediaWiki sets these regions aside before anything else, and so
+//! does this module: `regions` finds them the way MediaWiki's preprocessor does, and `mask` blanks
+//! them out so headings and templates can be found in what's left. Masking keeps every byte
+//! offset, so spans found in the masked text are spans in the original, where the content of
+//! a region (a ref's citation, say) is still there to be parsed on its own.
+//!
+//! As in MediaWiki, an extension tag ends at the first closing tag of its name, whatever is in
+//! between; one that's never closed is just text. A comment that's never closed runs to the end.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// Tags whose content MediaWiki hands to an extension rather than parsing as part of the page
+pub const EXTENSION_TAGS: &[&str] = &[
+    "nowiki", "pre", "math", "chem", "ce", "ref", "references", "gallery", "source", "syntaxhighlight",
+    "templatedata", "templatestyles", "score", "timeline", "hiero", "poem", "categorytree", "inputbox", "section",
+];
+
+/// The key for comments among the tags `RegionFinder` remembers as unclosed
+const COMMENT: &str = "!--";
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RegionKind {
+    Comment,
+    /// An extension tag, by its name in `EXTENSION_TAGS`
+    Tag(&'static str),
+}
+
+/// A comment or extension tag, borrowed from the wikitext
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Region<'a> {
+    pub kind: RegionKind,
+    /// Attributes of the opening tag, e.g. ` name="a"`, empty for comments
+    pub attrs: &'a str,
+    /// What's between the opening and closing tag, or inside the comment; empty if self-closing
+    pub content: &'a str,
+    /// Byte range of the whole region, tags included
+    pub span: Range<usize>,
+    /// Byte range of `content`
+    pub content_span: Range<usize>,
+}
+
+impl Region<'_> {
+    /// Whether this is an extension tag of this (lowercase) name
+    pub fn is_tag(&self, name: &str) -> bool {
+        matches!(self.kind, RegionKind::Tag(tag) if tag == name)
+    }
+}
+
+/// The comment or extension tag starting at `pos`, if one does
+pub fn region_at(wikitext: &str, pos: usize) -> Option<Region<'_>> {
+    RegionFinder::new(wikitext).region_at(pos)
+}
+
+/// Finds regions like `region_at`, many times over the same wikitext. Like MediaWiki, it remembers
+/// where a tag has no closing tag after, so text full of unclosed tags isn't searched to the end
+/// for each of them.
+#[derive(Debug, Clone)]
+pub struct RegionFinder<'a> {
+    wikitext: &'a str,
+    /// For tags (and comments) found unclosed, the earliest point they were searched for from
+    unclosed: HashMap<&'static str, usize>,
+}
+
+impl<'a> RegionFinder<'a> {
+    pub fn new(wikitext: &'a str) -> Self {
+        RegionFinder {
+            wikitext,
+            unclosed: HashMap::new(),
+        }
+    }
+
+    /// The comment or extension tag starting at `pos`, if one does
+    pub fn region_at(&mut self, pos: usize) -> Option<Region<'a>> {
+        let wikitext = self.wikitext;
+        let rest = &wikitext[pos..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            let end = match self.closed_after(COMMENT, pos) {
+                true => comment.find("-->"),
+                false => None,
+            };
+            let (content_len, len) = match end {
+                Some(end) => (end, 4 + end + 3),
+                None => {
+                    self.set_unclosed(COMMENT, pos);
+                    (comment.len(), rest.len())
+                }
+            };
+            let content_span = pos + 4..pos + 4 + content_len;
+            return Some(Region {
+                kind: RegionKind::Comment,
+                attrs: "",
+                content: &wikitext[content_span.clone()],
+                span: pos..pos + len,
+                content_span,
+            });
+        }
+
+        let after_lt = rest.strip_prefix('<')?;
+        let name_len = after_lt.bytes().take_while(u8::is_ascii_alphanumeric).count();
+        let name = EXTENSION_TAGS.iter().find(|tag| tag.eq_ignore_ascii_case(&after_lt[..name_len]))?;
+        let after_name = &after_lt[name_len..];
+        if !after_name.starts_with(|c: char| c == '>' || c == '/' || c.is_ascii_whitespace()) {
+            return None;
+        }
+        let close = after_name.find('>')?;
+        let open_len = 1 + name_len + close + 1;
+        let (attrs, self_closing) = match after_name[..close].strip_suffix('/') {
+            Some(attrs) => (attrs, true),
+            None => (&after_name[..close], false),
+        };
+        let content_start = pos + open_len;
+        if self_closing {
+            return Some(Region {
+                kind: RegionKind::Tag(name),
+                attrs,
+                content: "",
+                span: pos..content_start,
+                content_span: content_start..content_start,
+            });
+        }
+
+        if !self.closed_after(name, content_start) {
+            return None;
+        }
+        let Some((content_len, close_len)) = find_closing_tag(&wikitext[content_start..], name) else {
+            self.set_unclosed(name, content_start);
+            return None;
+        };
+        let content_span = content_start..content_start + content_len;
+        Some(Region {
+            kind: RegionKind::Tag(name),
+            attrs,
+            content: &wikitext[content_span.clone()],
+            span: pos..content_span.end + close_len,
+            content_span,
+        })
+    }
+
+    /// Whether a closing tag (or `-->`) might still come after `pos`
+    fn closed_after(&self, name: &str, pos: usize) -> bool {
+        self.unclosed.get(name).is_none_or(|&from| pos < from)
+    }
+
+    fn set_unclosed(&mut self, name: &'static str, pos: usize) {
+        let from = self.unclosed.entry(name).or_insert(pos);
+        *from = pos.min(*from);
+    }
+}
+
+/// Every comment and extension tag in the wikitext, in order, not counting those inside another
+pub fn regions(wikitext: &str) -> impl Iterator<Item = Region<'_>> {
+    let mut pos = 0;
+    let mut finder = RegionFinder::new(wikitext);
+    std::iter::from_fn(move || {
+        while let Some(lt) = wikitext[pos..].find('<') {
+            let start = pos + lt;
+            match finder.region_at(start) {
+                Some(region) => {
+                    pos = region.span.end;
+                    return Some(region);
+                }
+                None => pos = start + 1,
+            }
+        }
+        pos = wikitext.len();
+        None
+    })
+}
+
+/// The wikitext with every comment and extension tag blanked out to spaces, keeping line breaks
+/// so lines stay lines, and every byte where it was:
+///
+/// ```
+/// # use wikters::extension_tags::mask;
+/// let text = "<!-- ==Noun==\n -->{{en-noun}}";
+/// assert_eq!(mask(text), "             \n    {{en-noun}}");
+/// assert_eq!(mask(text).find("{{"), text.find("{{"));
+/// ```
+pub fn mask(wikitext: &str) -> Cow<'_, str> {
+    let mut regions = regions(wikitext).peekable();
+    if regions.peek().is_none() {
+        return Cow::Borrowed(wikitext);
+    }
+
+    let mut masked = wikitext.as_bytes().to_vec();
+    for region in regions {
+        for byte in &mut masked[region.span] {
+            if *byte != b'\n' {
+                *byte = b' ';
+            }
+        }
+    }
+    // Only whole characters were replaced, with ASCII
+    Cow::Owned(String::from_utf8(masked).expect("masking keeps UTF-8 valid"))
+}
+
+/// Where the first `</name>` (any case, maybe with spaces before the `>`) is in the text, and
+/// its length
+fn find_closing_tag(text: &str, name: &str) -> Option<(usize, usize)> {
+    let mut from = 0;
+    while let Some(found) = find_ignore_case(&text[from..], &format!("</{}", name)) {
+        let at = from + found;
+        let after = &text[at + 2 + name.len()..];
+        let spaces = after.len() - after.trim_start().len();
+        if after[spaces..].starts_with('>') {
+            return Some((at, 2 + name.len() + spaces + 1));
+        }
+        from = at + 2;
+    }
+    None
+}
+
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regions() {
+        let text = "a<!-- c -->b<REF name=\"x\">{{cite|</ref}}</ref >c<ref name=y/><math>{{</math><br><nowiki>x";
+        let found: Vec<_> = regions(text).map(|r| (r.kind, r.attrs, r.content)).collect();
+        assert_eq!(
+            found,
+            [
+                (RegionKind::Comment, "", " c "),
+                (RegionKind::Tag("ref"), " name=\"x\"", "{{cite|</ref}}"),
+                (RegionKind::Tag("ref"), " name=y", ""),
+                (RegionKind::Tag("math"), "", "{{"),
+            ]
+        );
+        let region = region_at(text, 12).unwrap();
+        assert_eq!(&text[region.span], "<REF name=\"x\">{{cite|</ref}}</ref >");
+        assert_eq!(&text[region.content_span], "{{cite|</ref}}");
+
+        // Unclosed: a tag is text, a comment runs to the end
+        assert_eq!(region_at("<ref>x", 0), None);
+        assert_eq!(region_at("<!-- x\ny", 0).unwrap().content, " x\ny");
+        assert_eq!(region_at("<references/>", 0).unwrap().kind, RegionKind::Tag("references"));
+        assert_eq!(region_at("<refx>a</refx>", 0), None);
+    }
+
+    #[test]
+    fn test_mask() {
+        let text = "==A==\n<!--\n==B==\n-->é<math>é</math>\n{{x}}";
+        let masked = mask(text);
+        assert_eq!(masked, "==A==\n    \n     \n   é               \n{{x}}");
+        assert_eq!(masked.len(), text.len());
+        assert!(matches!(mask("no regions"), Cow::Borrowed(_)));
+    }
+}
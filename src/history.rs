@@ -0,0 +1,171 @@
+//! Reading full-history dumps (`pages-meta-history`), where each page carries every revision.
+//!
+//! A source in all-revisions mode yields one `Page` per `<revision>`, repeating the page-level
+//! fields on each. `PageHistories` groups those back together so every revision of an entry can
+//! be run through the same per-page code (splitting, section analysis) to see how it changed.
+
+use std::error::Error;
+
+use crate::{Page, PageSource};
+
+/// Every revision of one page, oldest first, as they appear in the dump.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PageHistory {
+    pub revisions: Vec<Page>,
+}
+
+impl PageHistory {
+    pub fn title(&self) -> &str {
+        &self.latest().title
+    }
+
+    pub fn id(&self) -> Option<i32> {
+        self.latest().id
+    }
+
+    pub fn latest(&self) -> &Page {
+        self.revisions.last().expect("a page history has at least one revision")
+    }
+
+    pub fn len(&self) -> usize {
+        self.revisions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.revisions.is_empty()
+    }
+}
+
+/// Adapts a `PageSource` to yield one `PageHistory` per page.
+pub struct PageHistories<S: PageSource> {
+    source: S,
+    next: Option<Page>,
+    finished: bool,
+}
+
+impl<S: PageSource> PageHistories<S> {
+    /// Switches the source into all-revisions mode. With a source that doesn't have one, each
+    /// history holds just the latest revision.
+    pub fn new(mut source: S) -> Self {
+        source.set_all_revisions(true);
+        PageHistories {
+            source,
+            next: None,
+            finished: false,
+        }
+    }
+
+    pub fn next_history(&mut self) -> Result<Option<PageHistory>, Box<dyn Error>> {
+        let first = match self.next.take() {
+            Some(page) => page,
+            None if self.finished => return Ok(None),
+            None => match self.source.next_page()? {
+                Some(page) => page,
+                None => {
+                    self.finished = true;
+                    return Ok(None);
+                }
+            },
+        };
+
+        let mut revisions = vec![first];
+        loop {
+            let Some(page) = self.source.next_page()? else {
+                // Not every source can be polled again after reaching the end
+                self.finished = true;
+                break;
+            };
+            let current = &revisions[0];
+            if page.id != current.id || page.title != current.title {
+                self.next = Some(page);
+                break;
+            }
+            revisions.push(page);
+        }
+        Ok(Some(PageHistory { revisions }))
+    }
+
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+}
+
+impl<S: PageSource> Iterator for PageHistories<S> {
+    type Item = Result<PageHistory, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_history().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::read_all;
+    use crate::quick_xml_reader::QuickXmlReader;
+    use crate::{new_page_source, ReaderKind};
+
+    const DUMP: &str = include_str!("../fixtures/conformance/full_history.xml");
+
+    fn source(kind: ReaderKind) -> Box<dyn PageSource> {
+        new_page_source(kind, Box::new(DUMP.as_bytes()))
+    }
+
+    fn histories(source: impl PageSource) -> Vec<PageHistory> {
+        PageHistories::new(source).map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn test_all_revisions_in_order() {
+        let expected = histories(source(ReaderKind::QuickXml));
+        assert_eq!(expected.len(), 2);
+
+        let cat = &expected[0];
+        assert_eq!(cat.title(), "cat");
+        assert_eq!(cat.len(), 2);
+        let ids: Vec<_> = cat.revisions.iter().map(|p| p.revision.id).collect();
+        assert_eq!(ids, [Some(100), Some(200)]);
+        assert!(cat.revisions.iter().all(|p| p.id == Some(6)));
+        assert_eq!(cat.revisions[0].rev_text, "==English==");
+        assert_eq!(cat.latest().rev_text, "==English==\n===Etymology===\n===Noun===");
+        assert_eq!(cat.latest().revision.parent_id, Some(100));
+        assert_eq!(expected[1].title(), "dog");
+        assert_eq!(expected[1].len(), 1);
+
+        for kind in ReaderKind::ALL {
+            assert_eq!(histories(source(kind)), expected, "{}", kind.name());
+        }
+    }
+
+    #[test]
+    fn test_source_without_all_revisions() {
+        // Only implements `next_page`, so it keeps yielding the latest revision of each page
+        struct Latest(QuickXmlReader<&'static [u8]>);
+
+        impl PageSource for Latest {
+            fn next_page(&mut self) -> Result<Option<Page>, Box<dyn Error>> {
+                self.0.next_page()
+            }
+        }
+
+        let latest = histories(Latest(QuickXmlReader::from_bytes(DUMP.as_bytes())));
+        let ids: Vec<_> = latest.iter().map(|history| (history.len(), history.latest().revision.id)).collect();
+        assert_eq!(ids, [(1, Some(200)), (1, Some(300))]);
+    }
+
+    #[test]
+    fn test_default_mode_keeps_latest_revision() {
+        let expected = read_all(&mut source(ReaderKind::QuickXml)).unwrap();
+        assert_eq!(expected.len(), 2);
+        let cat = &expected[0];
+        assert_eq!(cat.revision.id, Some(200));
+        // Nothing carries over from the earlier revision
+        assert!(!cat.revision.minor);
+        assert_eq!(cat.revision.comment, None);
+        assert_eq!(cat.rev_text, "==English==\n===Etymology===\n===Noun===");
+
+        for kind in ReaderKind::ALL {
+            assert_eq!(read_all(&mut source(kind)).unwrap(), expected, "{}", kind.name());
+        }
+    }
+}
@@ -1,17 +1,145 @@
 use std::error::Error;
+use std::path::Path;
 
+#[allow(dead_code)]
 mod heading_and_template_lists;
 
+pub mod checkpoint;
+pub mod conformance;
+pub mod dump_input;
+pub mod entities;
+pub mod entry;
+pub mod extension_tags;
+pub mod history;
+pub mod multistream;
+pub mod pages;
+pub mod pipeline;
+pub mod progress;
 pub mod regex_reader;
+pub mod revision;
+pub mod site_info;
 pub mod string_ops_reader;
+pub mod template;
+pub mod wikilink;
 pub mod quick_xml_reader;
 pub mod qwikt_reader;
+pub mod recovery;
+pub mod redirects;
+pub mod section_tree;
+pub mod senses;
 pub mod wikitext_parser;
 pub mod wikitext_splitter;
+pub mod wikitext_tree;
+
+pub use checkpoint::{Checkpoint, CheckpointedSource, Checkpointer, DumpArgs};
+pub use dump_input::InputMeter;
+pub use entry::Entry;
+pub use pages::{PageFilters, Pages, Sampler};
+pub use pipeline::Pipeline;
+pub use progress::Progress;
+pub use recovery::{RecoveringSource, Recovery, SkippedPage};
+pub use revision::{Contributor, Revision};
+pub use section_tree::SectionTree;
+pub use senses::Sense;
+pub use site_info::{Namespace, SiteInfo};
+pub use template::Template;
+pub use wikilink::{LinkKind, WikiLink};
 
 /// Trait for XML dump readers - produces pages from MediaWiki XML
 pub trait PageSource {
     fn next_page(&mut self) -> Result<Option<Page>, Box<dyn Error>>;
+
+    /// The dump's `<siteinfo>` header, available once it has been read (after the first `next_page`)
+    fn site_info(&self) -> Option<&SiteInfo> {
+        None
+    }
+
+    /// Yield every `<revision>` of a page as its own `Page`, oldest first, rather than one `Page`
+    /// holding only the latest revision. For full-history dumps; see `history::PageHistories`.
+    /// Readers that can't split a page up by revision ignore it and keep yielding the latest.
+    fn set_all_revisions(&mut self, _all: bool) {}
+
+    /// Bytes of (decompressed) input consumed so far, for readers that track it. Straight after
+    /// `next_page` returns a page this is just past its `</page>`, which is where a run resumed from
+    /// a checkpoint carries on.
+    fn byte_offset(&self) -> Option<u64> {
+        None
+    }
+
+    /// Discard the rest of a page after `next_page` failed on it, so reading can carry on with the
+    /// next one as `recovery` says. Returns false if the input ends first, or if the reader can't
+    /// recover, which is the default: then reading stops at the first error, as with
+    /// `Recovery::Strict`. See `recovery::RecoveringSource`.
+    fn recover(&mut self, _recovery: Recovery) -> Result<bool, Box<dyn Error>> {
+        Ok(false)
+    }
+
+    /// Iterate over the pages, ending after the first error. `PageFilters` adds filters to narrow them down.
+    fn pages(self) -> Pages<Self>
+    where
+        Self: Sized,
+    {
+        Pages::new(self)
+    }
+}
+
+impl<P: PageSource + ?Sized> PageSource for Box<P> {
+    fn next_page(&mut self) -> Result<Option<Page>, Box<dyn Error>> {
+        (**self).next_page()
+    }
+
+    fn site_info(&self) -> Option<&SiteInfo> {
+        (**self).site_info()
+    }
+
+    fn set_all_revisions(&mut self, all: bool) {
+        (**self).set_all_revisions(all)
+    }
+
+    fn byte_offset(&self) -> Option<u64> {
+        (**self).byte_offset()
+    }
+
+    fn recover(&mut self, recovery: Recovery) -> Result<bool, Box<dyn Error>> {
+        (**self).recover(recovery)
+    }
+}
+
+/// Which XML reader implementation to use
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ReaderKind {
+    QuickXml,
+    Regex,
+    StringOps,
+    Qwikt,
+}
+
+impl ReaderKind {
+    pub const ALL: [ReaderKind; 4] = [ReaderKind::QuickXml, ReaderKind::Regex, ReaderKind::StringOps, ReaderKind::Qwikt];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ReaderKind::QuickXml => "quick-xml",
+            ReaderKind::Regex => "regex",
+            ReaderKind::StringOps => "string-ops",
+            ReaderKind::Qwikt => "qwikt",
+        }
+    }
+}
+
+/// Open a PageSource over a dump file (any supported compression), or stdin if no path is given
+pub fn open_page_source(kind: ReaderKind, input: Option<&Path>) -> Result<Box<dyn PageSource>, Box<dyn Error>> {
+    Ok(new_page_source(kind, dump_input::open_input(input)?))
+}
+
+/// Wrap an already opened input in the chosen reader
+pub fn new_page_source(kind: ReaderKind, input: dump_input::DumpInput) -> Box<dyn PageSource> {
+    match kind {
+        ReaderKind::QuickXml => Box::new(quick_xml_reader::QuickXmlReader::new(input)),
+        ReaderKind::Regex => Box::new(regex_reader::RegexReader::new(input)),
+        ReaderKind::StringOps => Box::new(string_ops_reader::StringOpsReader::new(input)),
+        ReaderKind::Qwikt => Box::new(qwikt_reader::QwiktReader::new(input)),
+    }
 }
 
 #[derive(Debug)]
@@ -20,14 +148,31 @@ pub struct Opts {
     pub xml: bool,
     pub no_updates: bool,
     pub sample_rate: Option<u64>,
+    /// Seed for picking the pages `sample_rate` keeps, so a sample can be repeated
+    pub seed: u64,
     pub handrolled: bool,
+    pub recovery: Recovery,
 }
 
-/// Process pages from a PageSource, applying wikitext parsing to each
-pub fn process_pages(opts: &Opts, mut source: Box<dyn PageSource>) -> Result<(), Box<dyn Error>> {
+/// Process pages from a PageSource, applying wikitext parsing to each and recording progress with
+/// the checkpointer if there is one. The meter tracks the input for progress reports.
+pub fn process_pages(
+    opts: &Opts,
+    source: Box<dyn PageSource>,
+    meter: InputMeter,
+    mut checkpointer: Option<Checkpointer>,
+) -> Result<(), Box<dyn Error>> {
+    let mut source = RecoveringSource::new(source, opts.recovery);
+    let mut sampler = opts.sample_rate.map(|n| Sampler::new(n, opts.seed));
+    let mut progress = (!opts.no_updates).then(|| Progress::new(meter));
+    let mut pages_read = 0;
     let mut page_num = 0;
     let mut section_num = 0;
-    
+
+    if opts.xml {
+        println!("<pages>");
+    }
+
     loop {
         if let Some(limit) = opts.limit {
             if page_num >= limit {
@@ -37,23 +182,59 @@ pub fn process_pages(opts: &Opts, mut source: Box<dyn PageSource>) -> Result<(),
         
         match source.next_page()? {
             Some(page) => {
-                wikitext_parser::parse_page_wikitext(&page, &mut page_num, &mut section_num);
+                pages_read += 1;
+                if sampler.as_mut().is_none_or(Sampler::pick) {
+                    wikitext_parser::parse_page_wikitext(&page, &mut page_num, &mut section_num, opts.xml);
+                }
+                if let Some(checkpointer) = &mut checkpointer {
+                    checkpointer.page_done(&source, &page)?;
+                }
+                if let Some(progress) = &mut progress {
+                    progress.update(pages_read, page_num);
+                }
+            }
+            None => {
+                if let Some(checkpointer) = checkpointer.take() {
+                    checkpointer.finish()?;
+                }
+                break;
             }
-            None => break,
         }
     }
-    
+
+    if opts.xml {
+        println!("</pages>");
+    }
+
+    // Stopped at the limit, so the next run can carry on from here
+    if let Some(checkpointer) = &mut checkpointer {
+        checkpointer.save()?;
+    }
+
+    if let Some(progress) = &progress {
+        progress.finish(pages_read, page_num);
+    }
+
+    let skipped = source.skipped_pages();
+    if !skipped.is_empty() {
+        eprintln!("Skipped {} unreadable pages:", skipped.len());
+        for page in skipped {
+            eprintln!("  {}", page);
+        }
+    }
+
     Ok(())
 }
 
 
-
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
 pub struct Page {
     pub title: String,
     pub ns: Option<i32>,
     pub id: Option<i32>,
-    pub rev_id: Option<i32>,
-    pub rev_contrib_id: Option<i32>,
+    /// Target title if this page is a redirect
+    pub redirect: Option<String>,
+    pub revision: Revision,
     pub rev_text: String,
 }
 
@@ -63,8 +244,8 @@ impl Page {
             title: String::new(),
             ns: None,
             id: None,
-            rev_id: None,
-            rev_contrib_id: None,
+            redirect: None,
+            revision: Revision::new(),
             rev_text: String::new(),
         }
     }
@@ -73,3 +254,29 @@ impl Page {
 
 
 
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn test_process_pages_without_ns() {
+        // `<ns>` is optional, and a page without it isn't in the main namespace
+        let xml = include_str!("../fixtures/conformance/redirects.xml").replace("<ns>0</ns>", "").replace("<ns>100</ns>", "");
+        let opts = Opts {
+            limit: None,
+            xml: false,
+            no_updates: true,
+            sample_rate: None,
+            seed: 0,
+            handrolled: false,
+            recovery: Recovery::Strict,
+        };
+        for kind in ReaderKind::ALL {
+            let source = new_page_source(kind, Box::new(Cursor::new(xml.clone().into_bytes())));
+            assert!(process_pages(&opts, source, InputMeter::default(), None).is_ok(), "{}", kind.name());
+        }
+    }
+}
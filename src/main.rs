@@ -1,14 +1,9 @@
 use std::error::Error;
-use std::io;
 
 use clap::Parser;
 
-use wikters::regex_reader::RegexReader;
-use wikters::string_ops_reader::StringOpsReader;
-use wikters::quick_xml_reader::QuickXmlReader;
-use wikters::qwikt_reader::QwiktReader;
 use wikters::process_pages;
-use wikters::Opts;
+use wikters::{DumpArgs, Opts, ReaderKind, Recovery};
 
 #[derive(Debug, Parser)]
 #[command(version, about)]
@@ -17,6 +12,9 @@ pub struct Args {
     #[clap(short, long)]
     pub limit: Option<u64>,
 
+    #[command(flatten)]
+    pub dump: DumpArgs,
+
     /// Output in lightweight XML format.
     #[clap(short, long)]
     pub xml: bool,
@@ -29,17 +27,25 @@ pub struct Args {
     #[clap(short, long)]
     pub sample_rate: Option<u64>,
 
+    /// Seed for the sample rate's random picks. The same seed picks the same entries.
+    #[clap(long, default_value = "0")]
+    pub seed: u64,
+
     /// Use regex-based hand-rolled parser instead of quick-xml.
     #[clap(short = 'r', long)]
     pub handrolled: bool,
 
-    /// Use string-ops hand-rolled parser instead of quick-xml.
-    #[clap(short = 's', long)]
+    /// Use string-ops hand-rolled parser instead of quick-xml. No short flag: -s is --sample-rate.
+    #[clap(long)]
     pub stringops: bool,
 
     /// Use qwikt deterministic streaming parser instead of quick-xml.
     #[clap(short = 'q', long)]
     pub qwikt: bool,
+
+    /// What to do with pages that can't be read: strict, skip-page or resync.
+    #[clap(long, default_value = "strict")]
+    pub recovery: Recovery,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -50,23 +56,25 @@ fn main() -> Result<(), Box<dyn Error>> {
         xml: args.xml,
         no_updates: args.no_updates,
         sample_rate: args.sample_rate,
+        seed: args.seed,
         handrolled: args.handrolled,
+        recovery: args.recovery,
     };
 
-    let stdin = io::stdin();
-
     // Choose reader implementation based on command line argument
-    let source: Box<dyn wikters::PageSource> = if args.qwikt {
-        Box::new(QwiktReader::new(stdin.lock()))
+    let kind = if args.qwikt {
+        ReaderKind::Qwikt
     } else if args.stringops {
-        Box::new(StringOpsReader::new(stdin.lock()))
+        ReaderKind::StringOps
     } else if args.handrolled {
-        Box::new(RegexReader::new(stdin.lock()))
+        ReaderKind::Regex
     } else {
-        Box::new(QuickXmlReader::new(stdin.lock()))
+        ReaderKind::QuickXml
     };
 
-    process_pages(&opts, source)?;
+    let checkpointer = args.dump.checkpointer()?;
+    let (source, meter) = args.dump.open(kind, checkpointer.as_ref())?;
+    process_pages(&opts, source, meter, checkpointer)?;
 
     Ok(())
 }
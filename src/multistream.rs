@@ -0,0 +1,336 @@
+//! Random access into `pages-articles-multistream` dumps.
+//!
+//! A multistream dump is a concatenation of independent bzip2 streams, each holding up to 100
+//! `<page>` elements (the first stream holds just the `<mediawiki>` header and siteinfo). The
+//! companion `-multistream-index.txt.bz2` has one `offset:page_id:title` line per page, where
+//! offset is the byte position of the stream containing that page. With the index loaded we can
+//! seek straight to one stream and decompress only that, instead of streaming the whole dump.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use bzip2::read::BzDecoder;
+
+use crate::dump_input;
+use crate::quick_xml_reader::QuickXmlReader;
+use crate::{Page, PageSource, Recovery, SiteInfo};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct IndexEntry {
+    pub offset: u64,
+    pub page_id: i32,
+    pub title: String,
+}
+
+/// The parsed `-multistream-index.txt` file
+pub struct MultistreamIndex {
+    entries: Vec<IndexEntry>,
+    by_title: HashMap<String, usize>,
+    by_id: HashMap<i32, usize>,
+}
+
+impl MultistreamIndex {
+    /// Load an index file, compressed or not.
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        Self::from_reader(dump_input::open_dump(path)?)
+    }
+
+    pub fn from_reader<R: BufRead>(reader: R) -> Result<Self, Box<dyn Error>> {
+        let mut entries = Vec::new();
+        for (line_num, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.is_empty() {
+                continue;
+            }
+            // Titles can contain colons, so only split off the first two fields
+            let mut fields = line.splitn(3, ':');
+            let (Some(offset), Some(page_id), Some(title)) = (fields.next(), fields.next(), fields.next()) else {
+                return Err(format!("Malformed index line {}: {:?}", line_num + 1, line).into());
+            };
+            entries.push(IndexEntry {
+                offset: offset.parse()?,
+                page_id: page_id.parse()?,
+                title: title.to_string(),
+            });
+        }
+
+        let mut by_title = HashMap::with_capacity(entries.len());
+        let mut by_id = HashMap::with_capacity(entries.len());
+        for (i, entry) in entries.iter().enumerate() {
+            by_title.insert(entry.title.clone(), i);
+            by_id.insert(entry.page_id, i);
+        }
+
+        Ok(MultistreamIndex {
+            entries,
+            by_title,
+            by_id,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn entries(&self) -> &[IndexEntry] {
+        &self.entries
+    }
+
+    pub fn find_title(&self, title: &str) -> Option<&IndexEntry> {
+        self.by_title.get(title).map(|&i| &self.entries[i])
+    }
+
+    pub fn find_id(&self, page_id: i32) -> Option<&IndexEntry> {
+        self.by_id.get(&page_id).map(|&i| &self.entries[i])
+    }
+
+    /// Distinct stream offsets in dump order.
+    pub fn stream_offsets(&self) -> Vec<u64> {
+        let mut offsets: Vec<u64> = self.entries.iter().map(|e| e.offset).collect();
+        offsets.sort_unstable();
+        offsets.dedup();
+        offsets
+    }
+}
+
+/// A multistream dump plus its index, supporting lookups by title or page id.
+///
+/// Also implements `PageSource`, walking the streams in order, so it can stand in for the
+/// sequential readers.
+pub struct MultistreamSource<R: Read + Seek> {
+    dump: R,
+    index: MultistreamIndex,
+    stream_offsets: Vec<u64>,
+    next_stream: usize,
+    pending: std::vec::IntoIter<Page>,
+    site_info: Option<SiteInfo>,
+    all_revisions: bool,
+}
+
+impl MultistreamSource<BufReader<File>> {
+    pub fn open(dump_path: &Path, index_path: &Path) -> Result<Self, Box<dyn Error>> {
+        let dump = File::open(dump_path).map_err(|e| format!("{}: {}", dump_path.display(), e))?;
+        let index = MultistreamIndex::load(index_path)?;
+        Ok(Self::new(BufReader::new(dump), index))
+    }
+}
+
+impl<R: Read + Seek> MultistreamSource<R> {
+    pub fn new(dump: R, index: MultistreamIndex) -> Self {
+        let stream_offsets = index.stream_offsets();
+        MultistreamSource {
+            dump,
+            index,
+            stream_offsets,
+            next_stream: 0,
+            pending: Vec::new().into_iter(),
+            site_info: None,
+            all_revisions: false,
+        }
+    }
+
+    pub fn index(&self) -> &MultistreamIndex {
+        &self.index
+    }
+
+    fn decompress_stream(&mut self, offset: u64) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.dump.seek(SeekFrom::Start(offset))?;
+        let mut xml = Vec::new();
+        BzDecoder::new(&mut self.dump).read_to_end(&mut xml)?;
+        Ok(xml)
+    }
+
+    /// Read the siteinfo header from the dump's first stream, which holds no pages.
+    pub fn read_site_info(&mut self) -> Result<Option<&SiteInfo>, Box<dyn Error>> {
+        if self.site_info.is_none() {
+            let mut reader = QuickXmlReader::new(Cursor::new(self.decompress_stream(0)?));
+            while reader.next_page()?.is_some() {}
+            self.site_info = reader.site_info().cloned();
+        }
+        Ok(self.site_info.as_ref())
+    }
+
+    /// Decompress the single bzip2 stream starting at `offset` and parse its pages.
+    pub fn pages_in_stream(&mut self, offset: u64) -> Result<Vec<Page>, Box<dyn Error>> {
+        let mut reader = QuickXmlReader::new(Cursor::new(self.decompress_stream(offset)?));
+        reader.set_all_revisions(self.all_revisions);
+        let mut pages = Vec::new();
+        while let Some(page) = reader.next_page()? {
+            pages.push(page);
+        }
+        Ok(pages)
+    }
+
+    /// Carry on reading from just after the page with this id, as when resuming from a checkpoint:
+    /// jump straight to the stream holding it and drop the pages before it there.
+    pub fn resume_after(&mut self, page_id: i32) -> Result<(), Box<dyn Error>> {
+        let offset = self
+            .index
+            .find_id(page_id)
+            .map(|e| e.offset)
+            .ok_or_else(|| format!("Page id {} is not in the index", page_id))?;
+        self.read_site_info()?;
+
+        let mut pages = self.pages_in_stream(offset)?;
+        let done = pages.iter().rposition(|p| p.id == Some(page_id)).map_or(0, |i| i + 1);
+        pages.drain(..done);
+        self.pending = pages.into_iter();
+        self.next_stream = self.stream_offsets.iter().position(|&o| o == offset).map_or(0, |i| i + 1);
+        Ok(())
+    }
+
+    pub fn page_by_id(&mut self, page_id: i32) -> Result<Option<Page>, Box<dyn Error>> {
+        let Some(offset) = self.index.find_id(page_id).map(|e| e.offset) else {
+            return Ok(None);
+        };
+        let pages = self.pages_in_stream(offset)?;
+        Ok(pages.into_iter().find(|p| p.id == Some(page_id)))
+    }
+
+    pub fn page_by_title(&mut self, title: &str) -> Result<Option<Page>, Box<dyn Error>> {
+        // Match on the id rather than the title, which is XML-escaped in the stream
+        match self.index.find_title(title).map(|e| e.page_id) {
+            Some(page_id) => self.page_by_id(page_id),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<R: Read + Seek> PageSource for MultistreamSource<R> {
+    fn next_page(&mut self) -> Result<Option<Page>, Box<dyn Error>> {
+        if self.next_stream == 0 {
+            self.read_site_info()?;
+        }
+        loop {
+            if let Some(page) = self.pending.next() {
+                return Ok(Some(page));
+            }
+            let Some(&offset) = self.stream_offsets.get(self.next_stream) else {
+                return Ok(None);
+            };
+            self.next_stream += 1;
+            self.pending = self.pages_in_stream(offset)?.into_iter();
+        }
+    }
+
+    fn site_info(&self) -> Option<&SiteInfo> {
+        self.site_info.as_ref()
+    }
+
+    fn set_all_revisions(&mut self, all: bool) {
+        self.all_revisions = all;
+    }
+
+    /// Streams are read whole, so either policy drops the rest of the stream that failed and
+    /// carries on with the next one.
+    fn recover(&mut self, recovery: Recovery) -> Result<bool, Box<dyn Error>> {
+        self.pending = Vec::new().into_iter();
+        Ok(recovery != Recovery::Strict && self.next_stream < self.stream_offsets.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::read_all;
+    use std::io::Write;
+
+    fn bz(data: &str) -> Vec<u8> {
+        let mut enc = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        enc.write_all(data.as_bytes()).unwrap();
+        enc.finish().unwrap()
+    }
+
+    fn page_xml(title: &str, id: i32, text: &str) -> String {
+        format!(
+            "  <page>\n    <title>{}</title>\n    <ns>0</ns>\n    <id>{}</id>\n    <revision>\n      <id>{}</id>\n      <text>{}</text>\n    </revision>\n  </page>\n",
+            title,
+            id,
+            id * 10,
+            text
+        )
+    }
+
+    /// Build a small multistream dump and its index: a header stream, two page streams, a footer.
+    fn sample() -> (Vec<u8>, String) {
+        let mut dump = bz("<mediawiki>\n  <siteinfo>\n    <sitename>Wiktionary</sitename>\n    <namespaces>\n      <namespace key=\"0\" case=\"case-sensitive\" />\n    </namespaces>\n  </siteinfo>\n");
+        let mut index = String::new();
+
+        let streams = [vec![("cat", 1, "==English=="), ("dog", 2, "==French==")], vec![("a:b", 3, "colon")]];
+        for stream in streams {
+            let offset = dump.len();
+            let mut xml = String::new();
+            for (title, id, text) in stream {
+                xml.push_str(&page_xml(title, id, text));
+                index.push_str(&format!("{}:{}:{}\n", offset, id, title));
+            }
+            dump.extend(bz(&xml));
+        }
+        dump.extend(bz("</mediawiki>\n"));
+        (dump, index)
+    }
+
+    #[test]
+    fn test_index_parsing() {
+        let index = MultistreamIndex::from_reader("600:12:Appendix:Glossary\n600:13:cat\n".as_bytes()).unwrap();
+        assert_eq!(index.len(), 2);
+        assert_eq!(index.find_title("Appendix:Glossary").unwrap().page_id, 12);
+        assert_eq!(index.find_id(13).unwrap().title, "cat");
+        assert_eq!(index.stream_offsets(), vec![600]);
+
+        assert!(MultistreamIndex::from_reader("600:12\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_lookup() {
+        let (dump, index) = sample();
+        let index = MultistreamIndex::from_reader(index.as_bytes()).unwrap();
+        let mut source = MultistreamSource::new(Cursor::new(dump), index);
+
+        let dog = source.page_by_title("dog").unwrap().unwrap();
+        assert_eq!(dog.id, Some(2));
+        assert_eq!(dog.rev_text, "==French==");
+
+        let ab = source.page_by_id(3).unwrap().unwrap();
+        assert_eq!(ab.title, "a:b");
+
+        assert!(source.page_by_title("mouse").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_sequential() {
+        let (dump, index) = sample();
+        let index = MultistreamIndex::from_reader(index.as_bytes()).unwrap();
+        let mut source = MultistreamSource::new(Cursor::new(dump), index);
+
+        let titles: Vec<_> = read_all(&mut source).unwrap().into_iter().map(|page| page.title).collect();
+        assert_eq!(titles, ["cat", "dog", "a:b"]);
+
+        let site_info = source.site_info().unwrap();
+        assert_eq!(site_info.sitename, "Wiktionary");
+        assert_eq!(site_info.namespaces.len(), 1);
+    }
+
+    #[test]
+    fn test_resume_after() {
+        let (dump, index) = sample();
+        let open = || MultistreamSource::new(Cursor::new(dump.clone()), MultistreamIndex::from_reader(index.as_bytes()).unwrap());
+
+        for (page_id, expected) in [(1, vec!["dog", "a:b"]), (2, vec!["a:b"]), (3, vec![])] {
+            let mut source = open();
+            source.resume_after(page_id).unwrap();
+            let titles: Vec<_> = read_all(&mut source).unwrap().into_iter().map(|page| page.title).collect();
+            assert_eq!(titles, expected);
+            assert_eq!(source.site_info().unwrap().sitename, "Wiktionary");
+        }
+
+        assert!(open().resume_after(4).is_err());
+    }
+}
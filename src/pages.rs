@@ -0,0 +1,198 @@
+//! Iterating over a `PageSource`'s pages and narrowing them down with filters.
+//!
+//! `PageSource::pages` turns a source into an iterator of `Result<Page>`, and `PageFilters`
+//! adds combinators to it, so a tool can say what it wants declaratively:
+//!
+//! ```no_run
+//! # use wikters::{open_page_source, PageFilters, PageSource, ReaderKind};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let source = open_page_source(ReaderKind::QuickXml, None)?;
+//! for page in source.pages().limit(1000).articles_only().with_language("English") {
+//!     println!("{}", page?.title);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Errors are passed through every filter untouched, so they still reach the caller.
+
+use std::error::Error;
+
+use regex::Regex;
+
+use crate::wikitext_splitter::has_language_section;
+use crate::{Page, PageSource};
+
+pub type PageResult = Result<Page, Box<dyn Error>>;
+
+/// Iterator over the pages of a `PageSource`, ending after the first error.
+pub struct Pages<S: PageSource> {
+    source: S,
+    finished: bool,
+}
+
+impl<S: PageSource> Pages<S> {
+    pub fn new(source: S) -> Self {
+        Pages {
+            source,
+            finished: false,
+        }
+    }
+
+    pub fn source(&self) -> &S {
+        &self.source
+    }
+
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+}
+
+impl<S: PageSource> Iterator for Pages<S> {
+    type Item = PageResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let result = self.source.next_page();
+        // Not every source can be polled again after reaching the end or failing
+        self.finished = !matches!(result, Ok(Some(_)));
+        result.transpose()
+    }
+}
+
+/// Filters over an iterator of pages. Each one passes errors through untouched.
+pub trait PageFilters: Iterator<Item = PageResult> + Sized {
+    /// Only pages in the main namespace (ns 0), where dictionary entries live
+    fn articles_only(self) -> impl Iterator<Item = PageResult> {
+        self.filter(|result| keep(result, |page| page.ns == Some(0)))
+    }
+
+    /// Only pages with a `==Language==` section for this language
+    fn with_language(self, language: &str) -> impl Iterator<Item = PageResult> {
+        self.filter(move |result| keep(result, |page| has_language_section(&page.rev_text, language)))
+    }
+
+    /// Only pages whose title the regex matches
+    fn title_matches(self, regex: Regex) -> impl Iterator<Item = PageResult> {
+        self.filter(move |result| keep(result, |page| regex.is_match(&page.title)))
+    }
+
+    /// Keep each page with a 1 in `n` chance. The same seed picks the same pages from the same input.
+    fn sample(self, n: u64, seed: u64) -> impl Iterator<Item = PageResult> {
+        let mut sampler = Sampler::new(n, seed);
+        self.filter(move |result| keep(result, |_| sampler.pick()))
+    }
+
+    /// At most `n` items
+    fn limit(self, n: u64) -> impl Iterator<Item = PageResult> {
+        self.take(usize::try_from(n).unwrap_or(usize::MAX))
+    }
+}
+
+impl<I: Iterator<Item = PageResult>> PageFilters for I {}
+
+fn keep(result: &PageResult, predicate: impl FnOnce(&Page) -> bool) -> bool {
+    result.as_ref().map_or(true, predicate)
+}
+
+/// Picks things with a 1 in `n` chance. The same seed makes the same picks, so a sample can be repeated.
+pub struct Sampler {
+    n: u64,
+    rng: SplitMix64,
+}
+
+impl Sampler {
+    pub fn new(n: u64, seed: u64) -> Self {
+        Sampler {
+            n,
+            rng: SplitMix64(seed),
+        }
+    }
+
+    pub fn pick(&mut self) -> bool {
+        self.n <= 1 || self.rng.next().is_multiple_of(self.n)
+    }
+}
+
+/// Small seedable generator for `Sampler`, so picking a sample needs no extra dependency
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quick_xml_reader::QuickXmlReader;
+    use crate::qwikt_reader::QwiktReader;
+
+    fn page(title: &str, ns: i32, rev_text: &str) -> PageResult {
+        Ok(Page {
+            title: title.to_string(),
+            ns: Some(ns),
+            rev_text: rev_text.to_string(),
+            ..Page::new()
+        })
+    }
+
+    fn titles(pages: impl Iterator<Item = PageResult>) -> Vec<String> {
+        pages.map(|page| page.map_or_else(|e| format!("error: {}", e), |page| page.title)).collect()
+    }
+
+    fn sample_pages() -> Vec<PageResult> {
+        vec![
+            page("cat", 0, "==English==\n===Noun===\n# feline"),
+            page("Talk:cat", 1, "==English==\nDiscussion"),
+            page("chat", 0, "==French==\n===Noun===\n# cat"),
+            Err("broken page".into()),
+            page("dog", 0, "==English==\n==French==\n"),
+        ]
+    }
+
+    #[test]
+    fn test_filters() {
+        assert_eq!(titles(sample_pages().into_iter().articles_only()), ["cat", "chat", "error: broken page", "dog"]);
+        assert_eq!(
+            titles(sample_pages().into_iter().articles_only().with_language("English")),
+            ["cat", "error: broken page", "dog"]
+        );
+        assert_eq!(
+            titles(sample_pages().into_iter().title_matches(Regex::new("^c").unwrap())),
+            ["cat", "chat", "error: broken page"]
+        );
+        assert_eq!(titles(sample_pages().into_iter().limit(2)), ["cat", "Talk:cat"]);
+        assert_eq!(titles(sample_pages().into_iter().with_language("Engl")), ["error: broken page"]);
+    }
+
+    #[test]
+    fn test_sample() {
+        let pages = || (0..1000).map(|i| page(&i.to_string(), 0, ""));
+        let first = titles(pages().sample(10, 42));
+        assert!((50..150).contains(&first.len()), "{} of 1000 sampled", first.len());
+        assert_eq!(titles(pages().sample(10, 42)), first);
+        assert_ne!(titles(pages().sample(10, 43)), first);
+        assert_eq!(pages().sample(1, 42).count(), 1000);
+    }
+
+    #[test]
+    fn test_pages_from_source() {
+        let xml = include_str!("../fixtures/conformance/redirects.xml");
+        let pages = QuickXmlReader::from_bytes(xml.as_bytes()).pages();
+        assert_eq!(titles(pages), ["colour", "Appendix:Colours"]);
+
+        // A source that fails is not polled again
+        let broken = xml.replacen("<model>", "<modle>", 1);
+        let pages: Vec<_> = QwiktReader::new(broken.as_bytes()).pages().collect();
+        assert_eq!(pages.len(), 1);
+        assert!(pages[0].is_err());
+    }
+}
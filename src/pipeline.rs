@@ -0,0 +1,283 @@
+//! Running per-page work on all cores while the dump is read.
+//!
+//! Reading the XML has to be sequential, but what's done with each page afterwards (splitting,
+//! regex scans, classifying sections) doesn't depend on any other page. A `Pipeline` reads pages
+//! on the calling thread and hands them through a bounded channel to worker threads, then passes
+//! each worker's result back to the caller's sink, on the calling thread again, so the sink can
+//! tally into plain local variables:
+//!
+//! ```no_run
+//! # use std::collections::HashMap;
+//! # use wikters::{open_page_source, PageFilters, PageSource, Pipeline, ReaderKind};
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! let source = open_page_source(ReaderKind::QuickXml, None)?;
+//! let mut counts = HashMap::new();
+//! Pipeline::new().run(
+//!     source.pages().articles_only(),
+//!     |page| wikters::wikitext_splitter::split_by_headings(&page.rev_text).0.len(),
+//!     |headings| *counts.entry(headings).or_insert(0) += 1,
+//! )?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Results reach the sink in whatever order the workers finish them, or in page order with
+//! `preserving_order`, for output that's the same from run to run.
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+
+use crate::pages::PageResult;
+use crate::Page;
+
+/// Spreads per-page work over worker threads.
+pub struct Pipeline {
+    workers: usize,
+    capacity: usize,
+    ordered: bool,
+}
+
+impl Pipeline {
+    pub const DEFAULT_CAPACITY: usize = 256;
+
+    /// One worker per core, results in the order they're finished
+    pub fn new() -> Self {
+        Pipeline {
+            workers: thread::available_parallelism().map_or(1, |n| n.get()),
+            capacity: Self::DEFAULT_CAPACITY,
+            ordered: false,
+        }
+    }
+
+    /// Use `workers` threads rather than one per core
+    pub fn with_workers(mut self, workers: usize) -> Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Queue up to `capacity` pages for the workers rather than the default
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Pass results to the sink in the order their pages were read
+    pub fn preserving_order(mut self) -> Self {
+        self.ordered = true;
+        self
+    }
+
+    /// Run `work` on each page on the worker threads and pass what it returns to `sink`.
+    ///
+    /// Stops reading at the first error, which is returned once the pages read before it have
+    /// been through `work` and `sink`. A panic in `work` also stops reading, and is passed on to
+    /// the caller once the workers have wound down.
+    pub fn run<R: Send>(
+        &self,
+        pages: impl IntoIterator<Item = PageResult>,
+        work: impl Fn(Page) -> R + Sync,
+        mut sink: impl FnMut(R),
+    ) -> Result<(), Box<dyn Error>> {
+        let (page_tx, page_rx) = mpsc::sync_channel(self.capacity);
+        let (result_tx, result_rx) = mpsc::channel();
+        let page_rx = Mutex::new(page_rx);
+        let mut output = Output::new(self.ordered);
+        // In order mode, finished results wait for any slow page before them, so limit how far
+        // reading can get ahead of the sink
+        let max_in_flight = (2 * self.capacity + self.workers) as u64;
+
+        let error = thread::scope(|scope| {
+            for _ in 0..self.workers {
+                let (page_rx, work, result_tx) = (&page_rx, &work, result_tx.clone());
+                scope.spawn(move || loop {
+                    let received = page_rx.lock().unwrap().recv();
+                    let Ok((seq, page)) = received else {
+                        break;
+                    };
+                    // Hand a panic back rather than dying with it, or in order mode the reader
+                    // would wait forever for the page's result
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| work(page)));
+                    if result_tx.send((seq, result)).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(result_tx);
+
+            let mut error = None;
+            'reading: for (seq, page) in (0u64..).zip(pages) {
+                let page = match page {
+                    Ok(page) => page,
+                    Err(e) => {
+                        error = Some(e);
+                        break;
+                    }
+                };
+                while seq - output.delivered >= max_in_flight {
+                    let Ok((done, result)) = result_rx.recv() else {
+                        break;
+                    };
+                    output.receive(done, result, &mut sink);
+                    if output.panicked.is_some() {
+                        break 'reading;
+                    }
+                }
+                // Only fails if every worker has panicked, which the scope passes on when it ends
+                if page_tx.send((seq, page)).is_err() {
+                    break;
+                }
+                for (done, result) in result_rx.try_iter() {
+                    output.receive(done, result, &mut sink);
+                }
+                if output.panicked.is_some() {
+                    break;
+                }
+            }
+
+            // Let the workers finish what's queued, then wind down
+            drop(page_tx);
+            for (done, result) in result_rx.iter() {
+                output.receive(done, result, &mut sink);
+            }
+            error
+        });
+
+        if let Some(payload) = output.panicked {
+            panic::resume_unwind(payload);
+        }
+        error.map_or(Ok(()), Err)
+    }
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Hands results on to the sink, holding back any that finished ahead of an earlier page if the
+/// order is being preserved
+struct Output<R> {
+    ordered: bool,
+    /// Results handed on so far, which in order mode is also the number of the next one due
+    delivered: u64,
+    pending: BTreeMap<u64, R>,
+    /// What the first panic in `work` was raised with; results after it are dropped
+    panicked: Option<Box<dyn Any + Send>>,
+}
+
+impl<R> Output<R> {
+    fn new(ordered: bool) -> Self {
+        Output {
+            ordered,
+            delivered: 0,
+            pending: BTreeMap::new(),
+            panicked: None,
+        }
+    }
+
+    fn receive(&mut self, seq: u64, result: thread::Result<R>, sink: &mut impl FnMut(R)) {
+        match result {
+            Ok(result) if self.panicked.is_none() => self.push(seq, result, sink),
+            Ok(_) => {}
+            Err(payload) => {
+                self.panicked.get_or_insert(payload);
+            }
+        }
+    }
+
+    fn push(&mut self, seq: u64, result: R, sink: &mut impl FnMut(R)) {
+        if !self.ordered {
+            self.delivered += 1;
+            sink(result);
+            return;
+        }
+
+        self.pending.insert(seq, result);
+        while let Some(result) = self.pending.remove(&self.delivered) {
+            self.delivered += 1;
+            sink(result);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn pages(n: i32) -> impl Iterator<Item = PageResult> {
+        (0..n).map(|i| {
+            Ok(Page {
+                title: i.to_string(),
+                id: Some(i),
+                ..Page::new()
+            })
+        })
+    }
+
+    /// Slow down some pages so the workers finish them out of order
+    fn work(page: Page) -> i32 {
+        let id = page.id.unwrap();
+        if id % 7 == 0 {
+            thread::sleep(Duration::from_millis(2));
+        }
+        id * 2
+    }
+
+    #[test]
+    fn test_preserving_order() {
+        let mut results = Vec::new();
+        let pipeline = Pipeline::new().with_workers(4).with_capacity(3).preserving_order();
+        pipeline.run(pages(200), work, |result| results.push(result)).unwrap();
+        assert_eq!(results, (0..200).map(|i| i * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_unordered() {
+        let mut results = Vec::new();
+        Pipeline::new().with_workers(4).run(pages(200), work, |result| results.push(result)).unwrap();
+        results.sort();
+        assert_eq!(results, (0..200).map(|i| i * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_error() {
+        let failing = pages(10).chain([Err("broken page".into())]).chain(pages(10));
+        let mut results = Vec::new();
+        let pipeline = Pipeline::new().with_workers(3).preserving_order();
+        let error = pipeline.run(failing, work, |result| results.push(result)).unwrap_err();
+        assert_eq!(error.to_string(), "broken page");
+        assert_eq!(results, (0..10).map(|i| i * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_panic_in_work() {
+        for pipeline in [Pipeline::new(), Pipeline::new().preserving_order()] {
+            let pipeline = pipeline.with_workers(2).with_capacity(2);
+            let mut results = Vec::new();
+            let run = panic::catch_unwind(AssertUnwindSafe(|| {
+                pipeline.run(
+                    pages(100),
+                    |page| {
+                        assert!(page.id.unwrap() % 40 != 3, "can't work on page {}", page.title);
+                        work(page)
+                    },
+                    |result| results.push(result),
+                )
+            }));
+            assert!(run.is_err());
+            assert!(results.len() < 100);
+
+            let run = panic::catch_unwind(AssertUnwindSafe(|| {
+                pipeline.run(pages(100), |page| -> i32 { panic!("can't work on page {}", page.title) }, |_| {})
+            }));
+            assert!(run.is_err());
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Progress reports on stderr during long runs over a dump.
+//!
+//! Rates and the ETA come from how much of the input file has been read, before decompression,
+//! since that's the only size known up front. Reading from stdin there's no ETA.
+
+use std::time::{Duration, Instant};
+
+use crate::dump_input::InputMeter;
+
+/// Reports how a run is going every few seconds
+pub struct Progress {
+    meter: InputMeter,
+    start: Instant,
+    next_report: Instant,
+}
+
+impl Progress {
+    pub const INTERVAL: Duration = Duration::from_secs(5);
+
+    pub fn new(meter: InputMeter) -> Self {
+        let start = Instant::now();
+        Progress {
+            meter,
+            start,
+            next_report: start + Self::INTERVAL,
+        }
+    }
+
+    /// Note the pages read so far, reporting them if it's time to
+    pub fn update(&mut self, pages: u64, matched_pages: u64) {
+        let now = Instant::now();
+        if now >= self.next_report {
+            self.next_report = now + Self::INTERVAL;
+            eprintln!("{}", self.report(pages, matched_pages, now));
+        }
+    }
+
+    /// Report the totals at the end of the run
+    pub fn finish(&self, pages: u64, matched_pages: u64) {
+        let report = format_report(pages, matched_pages, self.meter.bytes_read(), None, self.start.elapsed());
+        eprintln!("Done: {}", report);
+    }
+
+    fn report(&self, pages: u64, matched_pages: u64, now: Instant) -> String {
+        format_report(
+            pages,
+            matched_pages,
+            self.meter.bytes_read(),
+            self.meter.size(),
+            now - self.start,
+        )
+    }
+}
+
+fn format_report(pages: u64, matched_pages: u64, bytes: u64, size: Option<u64>, elapsed: Duration) -> String {
+    let secs = elapsed.as_secs_f64().max(0.001);
+    let mut report = format!(
+        "{} pages ({:.0}/s), {:.1} MB read ({:.1} MB/s), {} pages with English/Translingual",
+        pages,
+        pages as f64 / secs,
+        bytes as f64 / 1e6,
+        bytes as f64 / 1e6 / secs,
+        matched_pages,
+    );
+
+    if let Some(size) = size.filter(|&size| size > 0 && bytes > 0) {
+        let remaining = size.saturating_sub(bytes) as f64 / (bytes as f64 / secs);
+        report.push_str(&format!(
+            ", {:.0}% ETA {}",
+            bytes as f64 * 100.0 / size as f64,
+            format_duration(Duration::from_secs_f64(remaining)),
+        ));
+    }
+    report
+}
+
+/// As h:mm:ss
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_report() {
+        let elapsed = Duration::from_secs(10);
+        assert_eq!(
+            format_report(5000, 1200, 25_000_000, None, elapsed),
+            "5000 pages (500/s), 25.0 MB read (2.5 MB/s), 1200 pages with English/Translingual"
+        );
+        assert_eq!(
+            format_report(5000, 1200, 25_000_000, Some(100_000_000), elapsed),
+            "5000 pages (500/s), 25.0 MB read (2.5 MB/s), 1200 pages with English/Translingual, 25% ETA 0:00:30"
+        );
+        assert_eq!(format_duration(Duration::from_secs(2 * 3600 + 5 * 60 + 9)), "2:05:09");
+    }
+}
@@ -1,5 +1,7 @@
 use std::error::Error;
-use std::io::StdinLock;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
 
 use quick_xml::{
     events::{BytesStart, Event},
@@ -7,52 +9,115 @@ use quick_xml::{
     reader::Reader,
 };
 
-use crate::{Page, PageSource};
+use crate::entities::entity_char;
+use crate::revision::parse_timestamp;
+use crate::{Contributor, Namespace, Page, PageSource, Recovery, Revision, SiteInfo};
 
-pub struct QuickXmlReader {
-    reader: Reader<StdinLock<'static>>,
+pub struct QuickXmlReader<R: BufRead> {
+    reader: Reader<R>,
     buffer: Vec<u8>,
     last_text_content: Option<String>,
-    ns_key: Option<i32>,
+    namespace: Option<Namespace>,
+    site_info: Option<SiteInfo>,
+    in_page: bool,
+    in_revision: bool,
+    in_contributor: bool,
+    all_revisions: bool,
     page: Page,
 }
 
-impl QuickXmlReader {
-    pub fn new(stdin: StdinLock<'static>) -> Self {
+impl<R: BufRead> QuickXmlReader<R> {
+    pub fn new(reader: R) -> Self {
         QuickXmlReader {
-            reader: Reader::from_reader(stdin),
+            reader: Reader::from_reader(reader),
             buffer: Vec::new(),
             last_text_content: None,
-            ns_key: None,
+            namespace: None,
+            site_info: None,
+            in_page: false,
+            in_revision: false,
+            in_contributor: false,
+            all_revisions: false,
             page: Page::new(),
         }
     }
 }
 
-impl PageSource for QuickXmlReader {
+impl QuickXmlReader<BufReader<File>> {
+    /// Read an uncompressed XML file. For compressed dumps go through `dump_input::open_dump`.
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<'a> QuickXmlReader<&'a [u8]> {
+    pub fn from_bytes(bytes: &'a [u8]) -> Self {
+        Self::new(bytes)
+    }
+}
+
+impl<R: BufRead> PageSource for QuickXmlReader<R> {
     fn next_page(&mut self) -> Result<Option<Page>, Box<dyn Error>> {
         loop {
             match self.reader.read_event_into(&mut self.buffer) {
                 Ok(Event::Start(node)) => match node.name().as_ref() {
-                    b"namespace" => start_namespace(&node, &mut self.ns_key, &mut self.last_text_content),
-                    b"page" => self.page = Page::new(),
-                    b"title" => self.last_text_content = None,
+                    b"namespace" => self.namespace = start_namespace(&node, &mut self.last_text_content),
+                    b"siteinfo" => self.site_info = Some(SiteInfo::new()),
+                    b"page" => {
+                        self.in_page = true;
+                        self.page = Page::new();
+                    }
+                    b"revision" => {
+                        self.in_revision = true;
+                        self.page.revision = Revision::new();
+                        self.page.rev_text = String::new();
+                    }
+                    b"contributor" => self.in_contributor = true,
                     b"ns" => {
                         self.page.ns = None;
                         self.last_text_content = None;
                     }
-                    b"id" => self.last_text_content = None,
-                    b"text" => self.last_text_content = None,
+                    b"text" => {
+                        self.page.revision.bytes = attribute(&node, b"bytes").and_then(|b| b.parse().ok());
+                        self.last_text_content = None;
+                    }
+                    b"title" | b"id" | b"parentid" | b"timestamp" | b"username" | b"ip" | b"comment" | b"origin"
+                    | b"model" | b"format" | b"sha1" => self.last_text_content = None,
+                    b"sitename" | b"dbname" | b"base" | b"generator" | b"case" => self.last_text_content = None,
                     _ => {}
                 },
-                Ok(Event::Empty(node)) => {
-                    if node.name().as_ref() == b"namespace" {
-                        start_namespace(&node, &mut self.ns_key, &mut self.last_text_content);
-                        end_namespace(self.ns_key, &self.last_text_content);
+                Ok(Event::Empty(node)) => match node.name().as_ref() {
+                    b"namespace" => {
+                        self.namespace = start_namespace(&node, &mut self.last_text_content);
+                        end_namespace(self.namespace.take(), &mut self.last_text_content, &mut self.site_info);
                     }
-                }
+                    b"minor" => self.page.revision.minor = true,
+                    b"redirect" => self.page.redirect = attribute(&node, b"title"),
+                    b"contributor" if attribute(&node, b"deleted").is_some() => {
+                        self.page.revision.contributor = Some(Contributor::Deleted);
+                    }
+                    b"text" => {
+                        self.page.revision.bytes = attribute(&node, b"bytes").and_then(|b| b.parse().ok());
+                        self.page.rev_text = String::new();
+                    }
+                    _ => {}
+                },
                 Ok(Event::End(node)) => match node.name().as_ref() {
-                    b"namespace" => end_namespace(self.ns_key, &self.last_text_content),
+                    b"namespace" => {
+                        end_namespace(self.namespace.take(), &mut self.last_text_content, &mut self.site_info)
+                    }
+                    b"sitename" | b"dbname" | b"base" | b"generator" | b"case" => {
+                        let value = self.last_text_content.take().unwrap_or_default();
+                        if let Some(site_info) = &mut self.site_info {
+                            match node.name().as_ref() {
+                                b"sitename" => site_info.sitename = value,
+                                b"dbname" => site_info.dbname = value,
+                                b"base" => site_info.base = value,
+                                b"generator" => site_info.generator = value,
+                                _ => site_info.case = value,
+                            }
+                        }
+                    }
                     b"title" => {
                         self.page.title = self.last_text_content.take().unwrap_or_default();
                     }
@@ -63,32 +128,89 @@ impl PageSource for QuickXmlReader {
                     b"id" => {
                         let id_str = self.last_text_content.take().unwrap_or_default();
                         let id = id_str.parse::<i32>()?;
-                        if self.page.id.is_none() {
+                        if self.in_contributor {
+                            self.page.revision.set_contributor_id(Some(id));
+                        } else if self.in_revision {
+                            self.page.revision.id = Some(id);
+                        } else {
                             self.page.id = Some(id);
-                        } else if self.page.rev_id.is_none() {
-                            self.page.rev_id = Some(id);
-                        } else if self.page.rev_contrib_id.is_none() {
-                            self.page.rev_contrib_id = Some(id);
                         }
                     }
+                    b"revision" => {
+                        self.in_revision = false;
+                        if self.all_revisions {
+                            let revision = std::mem::take(&mut self.page.revision);
+                            let rev_text = std::mem::take(&mut self.page.rev_text);
+                            self.buffer.clear();
+                            return Ok(Some(Page {
+                                revision,
+                                rev_text,
+                                ..self.page.clone()
+                            }));
+                        }
+                    }
+                    b"contributor" => self.in_contributor = false,
+                    b"parentid" => {
+                        let id_str = self.last_text_content.take().unwrap_or_default();
+                        self.page.revision.parent_id = Some(id_str.parse::<i32>()?);
+                    }
+                    b"timestamp" => {
+                        let ts = self.last_text_content.take().unwrap_or_default();
+                        self.page.revision.timestamp = parse_timestamp(&ts);
+                    }
+                    b"username" => {
+                        let username = self.last_text_content.take().unwrap_or_default();
+                        self.page.revision.set_username(username);
+                    }
+                    b"ip" => {
+                        let ip = self.last_text_content.take().unwrap_or_default();
+                        self.page.revision.contributor = Some(Contributor::Ip(ip));
+                    }
+                    b"comment" => self.page.revision.comment = self.last_text_content.take(),
+                    b"origin" => {
+                        let origin = self.last_text_content.take().unwrap_or_default();
+                        self.page.revision.origin = origin.parse::<i32>().ok();
+                    }
+                    b"model" => self.page.revision.model = self.last_text_content.take(),
+                    b"format" => self.page.revision.format = self.last_text_content.take(),
+                    b"sha1" => self.page.revision.sha1 = self.last_text_content.take(),
                     b"text" => {
                         self.page.rev_text = self.last_text_content.take().unwrap_or_default();
                     }
                     b"page" => {
+                        self.in_page = false;
                         let page = std::mem::replace(&mut self.page, Page::new());
-                        self.buffer.clear();
-                        return Ok(Some(page));
+                        if !self.all_revisions {
+                            self.buffer.clear();
+                            return Ok(Some(page));
+                        }
                     }
                     _ => {}
                 },
                 Ok(Event::Text(text)) => {
-                    let s = String::from_utf8(text.to_vec())?;
-                    if let Some(ref mut last_text_content) = self.last_text_content {
-                        last_text_content.push_str(&s);
-                    } else {
-                        self.last_text_content = Some(s);
+                    let s = std::str::from_utf8(&text)?;
+                    self.last_text_content.get_or_insert_with(String::new).push_str(s);
+                }
+                Ok(Event::CData(text)) => {
+                    let s = std::str::from_utf8(&text)?;
+                    self.last_text_content.get_or_insert_with(String::new).push_str(s);
+                }
+                Ok(Event::GeneralRef(entity)) => {
+                    // quick-xml reports each `&...;` separately from the text around it
+                    let name = std::str::from_utf8(&entity)?;
+                    let text = self.last_text_content.get_or_insert_with(String::new);
+                    match entity_char(name) {
+                        Some(c) => text.push(c),
+                        None => {
+                            text.push('&');
+                            text.push_str(name);
+                            text.push(';');
+                        }
                     }
                 }
+                Ok(Event::Eof) if self.in_page => {
+                    return Err(format!("Unexpected EOF inside <page> at byte {}", self.reader.buffer_position()).into());
+                }
                 Ok(Event::Eof) => {
                     return Ok(None);
                 }
@@ -100,20 +222,123 @@ impl PageSource for QuickXmlReader {
             self.buffer.clear();
         }
     }
-}
 
-fn start_namespace(node: &BytesStart, ns_key: &mut Option<i32>, last_text_content: &mut Option<String>) {
-    if let Some(att) = node.attributes().find(|a| a.as_ref().unwrap().key == QName(b"key")) {
-        *ns_key = Some(
-            String::from_utf8(att.unwrap().value.to_vec())
-                .unwrap()
-                .parse::<i32>()
-                .unwrap(),
-        );
+    fn site_info(&self) -> Option<&SiteInfo> {
+        self.site_info.as_ref()
     }
+
+    fn set_all_revisions(&mut self, all: bool) {
+        self.all_revisions = all;
+    }
+
+    fn byte_offset(&self) -> Option<u64> {
+        Some(self.reader.buffer_position())
+    }
+
+    fn recover(&mut self, recovery: Recovery) -> Result<bool, Box<dyn Error>> {
+        self.page = Page::new();
+        self.namespace = None;
+        self.last_text_content = None;
+        self.in_page = false;
+        self.in_revision = false;
+        self.in_contributor = false;
+        if recovery == Recovery::Strict {
+            return Ok(false);
+        }
+
+        loop {
+            self.buffer.clear();
+            let position = self.reader.buffer_position();
+            match self.reader.read_event_into(&mut self.buffer) {
+                Ok(Event::Eof) => return Ok(false),
+                Ok(Event::End(node)) if recovery == Recovery::SkipPage && node.name().as_ref() == b"page" => {
+                    return Ok(true);
+                }
+                Ok(Event::Start(node)) if recovery == Recovery::Resync && node.name().as_ref() == b"page" => {
+                    self.in_page = true;
+                    return Ok(true);
+                }
+                // An error that doesn't get past the bad input would just repeat
+                Err(e) if self.reader.buffer_position() == position => return Err(Box::new(e)),
+                Ok(_) | Err(_) => {}
+            }
+        }
+    }
+}
+
+fn start_namespace(node: &BytesStart, last_text_content: &mut Option<String>) -> Option<Namespace> {
     *last_text_content = None;
+    Some(Namespace {
+        key: attribute(node, b"key")?.parse::<i32>().ok()?,
+        case: attribute(node, b"case").unwrap_or_default(),
+        name: String::new(),
+    })
 }
 
-fn end_namespace(_ns_key: Option<i32>, _last_text_content: &Option<String>) {
+fn attribute(node: &BytesStart, key: &[u8]) -> Option<String> {
+    node.attributes()
+        .flatten()
+        .find(|a| a.key == QName(key))
+        .map(|a| match a.unescape_value() {
+            Ok(value) => value.into_owned(),
+            Err(_) => String::from_utf8_lossy(&a.value).into_owned(),
+        })
+}
+
+fn end_namespace(namespace: Option<Namespace>, last_text_content: &mut Option<String>, site_info: &mut Option<SiteInfo>) {
     // The default namespace, 0, has no name
+    if let (Some(mut namespace), Some(site_info)) = (namespace, site_info) {
+        namespace.name = last_text_content.take().unwrap_or_default();
+        site_info.namespaces.push(namespace);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::read_all;
+
+    const SAMPLE: &[u8] = include_bytes!("../test_sample.xml");
+
+    #[test]
+    fn test_sample_pages() {
+        let pages = read_all(&mut QuickXmlReader::from_bytes(SAMPLE)).unwrap();
+
+        let titles: Vec<_> = pages.iter().map(|p| p.title.as_str()).collect();
+        assert_eq!(titles, ["test", "run", "bank"]);
+        assert!(pages.iter().all(|p| p.ns == Some(0)));
+
+        assert!(pages[0].rev_text.starts_with("Some prologue text here.\n\n==English==\n"));
+        assert!(pages[1].rev_text.contains("===Etymology 2===\n\n====Noun====\nA ladder rung."));
+        assert!(pages[2].rev_text.ends_with("==German==\nSome content.\n"));
+    }
+
+    #[test]
+    fn test_from_file_matches_from_bytes() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("test_sample.xml");
+        let mut from_file = QuickXmlReader::from_file(&path).unwrap();
+        let mut from_bytes = QuickXmlReader::from_bytes(SAMPLE);
+        loop {
+            match (from_file.next_page().unwrap(), from_bytes.next_page().unwrap()) {
+                (Some(a), Some(b)) => {
+                    assert_eq!(a.title, b.title);
+                    assert_eq!(a.rev_text, b.rev_text);
+                }
+                (None, None) => break,
+                _ => panic!("readers disagree on page count"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_ids() {
+        let xml = b"<mediawiki><page><title>a</title><ns>0</ns><id>7</id>\
+            <revision><id>70</id><contributor><username>U</username><id>700</id></contributor>\
+            <text>x</text></revision></page></mediawiki>";
+        let page = QuickXmlReader::from_bytes(xml).next_page().unwrap().unwrap();
+        assert_eq!(page.id, Some(7));
+        assert_eq!(page.revision.id, Some(70));
+        assert_eq!(page.revision.contributor_id(), Some(700));
+        assert_eq!(page.rev_text, "x");
+    }
 }
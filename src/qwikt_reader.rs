@@ -1,8 +1,24 @@
 use std::error::Error;
-use std::io::Read;
 use std::fmt;
+use std::io::{BufRead, BufReader, Read};
 
-use crate::{Page, PageSource};
+use memchr::{memchr, memchr_iter, memrchr};
+
+use crate::entities::unescape;
+use crate::revision::parse_timestamp;
+use crate::{Contributor, Namespace, Page, PageSource, Recovery, Revision, SiteInfo};
+
+/// Export schema versions whose `<mediawiki xmlns=...>` is accepted
+const EXPORT_VERSIONS: &[&str] = &["0.10", "0.11"];
+
+/// The children each element may have, in the order the export schema puts them. Any may be missing.
+const SITEINFO_CHILDREN: &[&[u8]] = &[b"sitename", b"dbname", b"base", b"generator", b"case", b"namespaces"];
+const PAGE_CHILDREN: &[&[u8]] = &[b"title", b"ns", b"id", b"redirect", b"restrictions"];
+const REVISION_CHILDREN: &[&[u8]] = &[
+    b"id", b"parentid", b"timestamp", b"contributor", b"minor", b"comment", b"origin", b"model", b"format", b"text",
+    b"sha1",
+];
+const CONTRIBUTOR_CHILDREN: &[&[u8]] = &[b"username", b"id", b"ip"];
 
 #[derive(Debug)]
 struct QwiktError(String);
@@ -19,7 +35,8 @@ impl Error for QwiktError {}
 struct Position {
     off: usize,
     line: usize,
-    col: usize,
+    /// Offset of the first byte of the current line, from which the column is worked out
+    line_start: usize,
 }
 
 impl Position {
@@ -27,59 +44,127 @@ impl Position {
         Position {
             off: 0,
             line: 1,
-            col: 1,
+            line_start: 0,
         }
     }
 
-    fn advance(&mut self, byte: u8) {
-        self.off += 1;
-        if byte == b'\n' {
-            self.line += 1;
-            self.col = 1;
-        } else {
-            self.col += 1;
+    fn col(&self) -> usize {
+        self.off - self.line_start + 1
+    }
+
+    fn advance(&mut self, bytes: &[u8]) {
+        if let Some(last) = memrchr(b'\n', bytes) {
+            self.line += memchr_iter(b'\n', bytes).count();
+            self.line_start = self.off + last + 1;
         }
+        self.off += bytes.len();
+    }
+
+    fn error(&self, message: &str) -> Box<dyn Error> {
+        Box::new(QwiktError(format!(
+            "{} at byte {} (line {}, col {})",
+            message,
+            self.off,
+            self.line,
+            self.col()
+        )))
     }
 }
 
+/// A start tag, with its attribute values decoded
+struct Tag {
+    name: Vec<u8>,
+    attributes: Vec<(Vec<u8>, String)>,
+    /// Written `<name ... />`, so there's no content or end tag to read
+    empty: bool,
+    /// Where the tag's `<` is, for reporting errors about it
+    position: Position,
+}
+
+impl Tag {
+    fn attribute(&self, name: &[u8]) -> Option<&str> {
+        self.attributes.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+
+    fn is(&self, name: &[u8]) -> bool {
+        self.name == name
+    }
+
+    fn unexpected_in(&self, parent: &str) -> Box<dyn Error> {
+        self.position.error(&format!(
+            "Unexpected <{}> in <{}>",
+            String::from_utf8_lossy(&self.name),
+            parent
+        ))
+    }
+
+    /// Check this tag is one of `parent`'s `children`, coming after the child last seen at `*next - 1`
+    fn check_order(&self, parent: &str, children: &[&[u8]], next: &mut usize) -> Result<(), Box<dyn Error>> {
+        match children[*next..].iter().position(|&child| self.name == child) {
+            Some(i) => {
+                *next += i + 1;
+                Ok(())
+            }
+            None => Err(self.unexpected_in(parent)),
+        }
+    }
+}
+
+/// Exact matching and delimiter scanning over a buffered input, a buffer's worth at a time
 struct StreamReader<R: Read> {
-    reader: R,
+    reader: BufReader<R>,
     position: Position,
 }
 
 impl<R: Read> StreamReader<R> {
     fn new(reader: R) -> Self {
         StreamReader {
-            reader,
+            reader: BufReader::with_capacity(1 << 16, reader),
             position: Position::new(),
         }
     }
 
-    fn read_byte(&mut self) -> Result<u8, Box<dyn Error>> {
-        let mut buf = [0u8; 1];
-        match self.reader.read(&mut buf)? {
-            1 => {
-                self.position.advance(buf[0]);
-                Ok(buf[0])
-            }
-            _ => Err(Box::new(QwiktError("Unexpected EOF".to_string()))),
+    /// The buffered input, refilling it if it's been used up. Empty only at EOF.
+    fn buffer(&mut self) -> Result<&[u8], Box<dyn Error>> {
+        let buf = self.reader.fill_buf()?;
+        if buf.is_empty() {
+            return Err(self.position.error("Unexpected EOF"));
         }
+        Ok(buf)
     }
 
-    fn match_exact(&mut self, expected: &[u8]) -> Result<(), Box<dyn Error>> {
-        for &expected_byte in expected {
-            let actual = self.read_byte()?;
-            if actual != expected_byte {
-                return Err(Box::new(QwiktError(
-                    format!(
+    fn consume(&mut self, len: usize) {
+        let buf = self.reader.buffer();
+        self.position.advance(&buf[..len]);
+        self.reader.consume(len);
+    }
+
+    fn peek_byte(&mut self) -> Result<u8, Box<dyn Error>> {
+        Ok(self.buffer()?[0])
+    }
+
+    fn match_exact(&mut self, mut expected: &[u8]) -> Result<(), Box<dyn Error>> {
+        while !expected.is_empty() {
+            let buf = self.buffer()?;
+            let len = buf.len().min(expected.len());
+            match buf[..len].iter().zip(expected).position(|(a, e)| a != e) {
+                None => {
+                    self.consume(len);
+                    expected = &expected[len..];
+                }
+                Some(i) => {
+                    let actual = buf[i];
+                    // Report the position just past the offending byte
+                    self.consume(i + 1);
+                    return Err(Box::new(QwiktError(format!(
                         "Mismatch at byte {} (line {}, col {}): expected {:?}, got {:?}",
                         self.position.off,
                         self.position.line,
-                        self.position.col,
-                        expected_byte as char,
+                        self.position.col(),
+                        expected[i] as char,
                         actual as char
-                    )
-                )));
+                    ))));
+                }
             }
         }
         Ok(())
@@ -88,18 +173,171 @@ impl<R: Read> StreamReader<R> {
     fn read_until(&mut self, delimiter: u8) -> Result<Vec<u8>, Box<dyn Error>> {
         let mut result = Vec::new();
         loop {
-            let byte = self.read_byte()?;
-            if byte == delimiter {
-                return Ok(result);
+            let buf = self.buffer()?;
+            match memchr(delimiter, buf) {
+                Some(i) => {
+                    result.extend_from_slice(&buf[..i]);
+                    self.consume(i + 1);
+                    return Ok(result);
+                }
+                None => {
+                    let len = buf.len();
+                    result.extend_from_slice(buf);
+                    self.consume(len);
+                }
+            }
+        }
+    }
+
+    /// Skip any whitespace, returning false if the input ends first
+    fn skip_whitespace(&mut self) -> Result<bool, Box<dyn Error>> {
+        loop {
+            let buf = self.reader.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(false);
+            }
+            match buf.iter().position(|b| !b.is_ascii_whitespace()) {
+                Some(i) => {
+                    self.consume(i);
+                    return Ok(true);
+                }
+                None => {
+                    let len = buf.len();
+                    self.consume(len);
+                }
+            }
+        }
+    }
+
+    /// Skip past the next occurrence of `pattern`, returning false if the input ends first
+    fn skip_past(&mut self, pattern: &[u8]) -> Result<bool, Box<dyn Error>> {
+        let mut matched = 0;
+        while matched < pattern.len() {
+            let buf = self.reader.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(false);
             }
-            result.push(byte);
+            if matched == 0 {
+                match memchr(pattern[0], buf) {
+                    Some(i) => {
+                        self.consume(i + 1);
+                        matched = 1;
+                    }
+                    None => {
+                        let len = buf.len();
+                        self.consume(len);
+                    }
+                }
+            } else if buf[0] == pattern[matched] {
+                self.consume(1);
+                matched += 1;
+            } else {
+                // Leave the byte, which may itself start the pattern
+                matched = 0;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Read a tag or attribute name, up to whitespace, `=`, `/` or `>`
+    fn read_name(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut name = Vec::new();
+        loop {
+            let buf = self.buffer()?;
+            match buf.iter().position(|b| b.is_ascii_whitespace() || b"=/>".contains(b)) {
+                Some(i) => {
+                    name.extend_from_slice(&buf[..i]);
+                    self.consume(i);
+                    break;
+                }
+                None => {
+                    let len = buf.len();
+                    name.extend_from_slice(buf);
+                    self.consume(len);
+                }
+            }
+        }
+        if name.is_empty() {
+            let byte = self.peek_byte()?;
+            return Err(self.position.error(&format!("Expected a name, got {:?}", byte as char)));
         }
+        Ok(name)
+    }
+
+    /// Read the rest of a start tag whose name has just been read: its attributes and `>` or `/>`
+    fn read_tag(&mut self, name: Vec<u8>, position: Position) -> Result<Tag, Box<dyn Error>> {
+        let mut attributes = Vec::new();
+        loop {
+            self.skip_whitespace()?;
+            match self.peek_byte()? {
+                b'>' => {
+                    self.consume(1);
+                    return Ok(Tag {
+                        name,
+                        attributes,
+                        empty: false,
+                        position,
+                    });
+                }
+                b'/' => {
+                    self.consume(1);
+                    self.match_exact(b">")?;
+                    return Ok(Tag {
+                        name,
+                        attributes,
+                        empty: true,
+                        position,
+                    });
+                }
+                _ => {
+                    let attribute = self.read_name()?;
+                    self.match_exact(b"=\"")?;
+                    let value = self.read_until(b'"')?;
+                    attributes.push((attribute, decode_text(&value)));
+                }
+            }
+        }
+    }
+
+    /// Read the next child element's start tag, or None after reading `parent`'s end tag instead
+    fn next_child(&mut self, parent: &[u8]) -> Result<Option<Tag>, Box<dyn Error>> {
+        self.skip_whitespace()?;
+        let position = self.position;
+        self.match_exact(b"<")?;
+        if self.peek_byte()? == b'/' {
+            self.consume(1);
+            self.match_exact(parent)?;
+            self.skip_whitespace()?;
+            self.match_exact(b">")?;
+            return Ok(None);
+        }
+        let name = self.read_name()?;
+        Ok(Some(self.read_tag(name, position)?))
+    }
+
+    /// The raw text content of an element whose start tag has just been read, through its end tag
+    fn read_text(&mut self, tag: &Tag) -> Result<Vec<u8>, Box<dyn Error>> {
+        if tag.empty {
+            return Ok(Vec::new());
+        }
+        let text = self.read_until(b'<')?;
+        self.match_exact(b"/")?;
+        self.match_exact(&tag.name)?;
+        self.skip_whitespace()?;
+        self.match_exact(b">")?;
+        Ok(text)
     }
 }
 
 pub struct QwiktReader<R: Read> {
     stream: StreamReader<R>,
     initialized: bool,
+    site_info: Option<SiteInfo>,
+    all_revisions: bool,
+    /// Page-level fields of the page whose revisions are being read
+    page: Option<Page>,
+    /// Set when recovery has already read the next page's `<page>` tag
+    resynced: bool,
 }
 
 impl<R: Read> QwiktReader<R> {
@@ -107,225 +345,367 @@ impl<R: Read> QwiktReader<R> {
         QwiktReader {
             stream: StreamReader::new(reader),
             initialized: false,
+            site_info: None,
+            all_revisions: false,
+            page: None,
+            resynced: false,
         }
     }
 
+    /// Read the optional `<?xml ...?>` declaration and the `<mediawiki>` start tag
     fn init_header(&mut self) -> Result<(), Box<dyn Error>> {
-        // mediawiki element with its attrs
-        self.stream.match_exact(b"<mediawiki xmlns=\"http://www.mediawiki.org/xml/export-0.11/\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" xsi:schemaLocation=\"http://www.mediawiki.org/xml/export-0.11/ http://www.mediawiki.org/xml/export-0.11.xsd\" version=\"0.11\" xml:lang=\"")?;
-        let _lang_code = self.stream.read_until(b'"')?;
-        self.stream.match_exact(b">\n")?;
+        self.stream.skip_whitespace()?;
+        let mut position = self.stream.position;
+        self.stream.match_exact(b"<")?;
+        if self.stream.peek_byte()? == b'?' {
+            self.stream.read_until(b'>')?;
+            self.stream.skip_whitespace()?;
+            position = self.stream.position;
+            self.stream.match_exact(b"<")?;
+        }
 
-        // siteinfo lasts until the first page
-        self.stream.match_exact(b"  <siteinfo>\n    <sitename>")?;
-        let _sitename = self.stream.read_until(b'<')?;
-        self.stream.match_exact(b"/sitename>\n    <dbname>")?;
+        self.stream.match_exact(b"mediawiki")?;
+        let tag = self.stream.read_tag(b"mediawiki".to_vec(), position)?;
+        let xmlns = tag.attribute(b"xmlns").unwrap_or_default();
+        let supported = EXPORT_VERSIONS
+            .iter()
+            .any(|version| xmlns == format!("http://www.mediawiki.org/xml/export-{}/", version));
+        if !supported {
+            return Err(position.error(&format!("Unsupported export schema {:?}", xmlns)));
+        }
+        Ok(())
+    }
 
-        let _dbname = self.stream.read_until(b'<')?;
-        self.stream.match_exact(b"/dbname>\n    <base>")?;
+    /// Read a `<siteinfo>` element whose start tag has just been read
+    fn read_site_info(&mut self) -> Result<SiteInfo, Box<dyn Error>> {
+        let mut site_info = SiteInfo::new();
+        let mut next = 0;
+
+        while let Some(tag) = self.stream.next_child(b"siteinfo")? {
+            tag.check_order("siteinfo", SITEINFO_CHILDREN, &mut next)?;
+            if tag.is(b"namespaces") {
+                if !tag.empty {
+                    self.read_namespaces(&mut site_info)?;
+                }
+                continue;
+            }
 
-        let _base_url = self.stream.read_until(b'<')?;
+            let value = decode_text(&self.stream.read_text(&tag)?);
+            match tag.name.as_slice() {
+                b"sitename" => site_info.sitename = value,
+                b"dbname" => site_info.dbname = value,
+                b"base" => site_info.base = value,
+                b"generator" => site_info.generator = value,
+                _ => site_info.case = value,
+            }
+        }
 
-        self.stream.match_exact(b"/base>\n    <generator>MediaWiki ")?;
+        Ok(site_info)
+    }
 
-        let _gen_ver = self.stream.read_until(b'<')?;
+    fn read_namespaces(&mut self, site_info: &mut SiteInfo) -> Result<(), Box<dyn Error>> {
+        while let Some(tag) = self.stream.next_child(b"namespaces")? {
+            if !tag.is(b"namespace") {
+                return Err(tag.unexpected_in("namespaces"));
+            }
+            let key = tag
+                .attribute(b"key")
+                .and_then(|key| key.parse::<i32>().ok())
+                .ok_or_else(|| tag.position.error("Missing or invalid namespace key"))?;
+            site_info.namespaces.push(Namespace {
+                key,
+                case: tag.attribute(b"case").unwrap_or_default().to_string(),
+                name: decode_text(&self.stream.read_text(&tag)?),
+            });
+        }
+        Ok(())
+    }
 
-        self.stream.match_exact(b"/generator>\n    <case>case-sensitive</case>\n    <namespaces>\n")?;
+    /// The next top-level element's start tag, or None at `</mediawiki>` or if the input ends between pages
+    fn next_top_level(&mut self) -> Result<Option<Tag>, Box<dyn Error>> {
+        if !self.stream.skip_whitespace()? {
+            return Ok(None);
+        }
+        self.stream.next_child(b"mediawiki")
+    }
+
+    /// Read a page's title, ns, id and redirect, through its first `<revision>` tag
+    fn read_page_header(&mut self) -> Result<Page, Box<dyn Error>> {
+        let mut page = Page::new();
+        let mut next = 0;
 
         loop {
-            self.stream.match_exact(b"    ")?;
-            // Check if we've reached the closing tag
-            let first_byte = self.stream.read_byte()?;
+            let tag = match self.stream.next_child(b"page")? {
+                Some(tag) => tag,
+                None => return Err(self.stream.position.error("Page without a <revision>")),
+            };
+            if tag.is(b"revision") {
+                return Ok(page);
+            }
+            tag.check_order("page", PAGE_CHILDREN, &mut next)?;
 
-            if first_byte == b'<' {
-                self.stream.match_exact(b"/namespaces>\n")?;
-                break;
+            if tag.is(b"redirect") {
+                page.redirect = tag.attribute(b"title").map(str::to_string);
+                // Always empty in practice, but read it the same way as any other element
+                self.stream.read_text(&tag)?;
+                continue;
             }
 
-            self.stream.match_exact(b" <namespace key=\"")?;
-            let _ns_key = self.stream.read_until(b'"')?;
-            self.stream.match_exact(b" case=\"")?;
-            let _ns_case = self.stream.read_until(b'"')?;
+            let value = self.stream.read_text(&tag)?;
+            match tag.name.as_slice() {
+                b"title" => page.title = decode_text(&value),
+                b"ns" => page.ns = String::from_utf8_lossy(&value).parse::<i32>().ok(),
+                b"id" => page.id = String::from_utf8_lossy(&value).parse::<i32>().ok(),
+                _ => {}
+            }
+        }
+    }
 
-            let next_byte = self.stream.read_byte()?;
+    /// Read one revision's contents, from just after `<revision>` through `</revision>`
+    fn read_revision(&mut self) -> Result<(Revision, String), Box<dyn Error>> {
+        let mut revision = Revision::new();
+        let mut rev_text = String::new();
+        let mut next = 0;
+
+        while let Some(tag) = self.stream.next_child(b"revision")? {
+            tag.check_order("revision", REVISION_CHILDREN, &mut next)?;
+
+            match tag.name.as_slice() {
+                b"contributor" => {
+                    revision.contributor = if tag.attribute(b"deleted").is_some() {
+                        self.stream.read_text(&tag)?;
+                        Some(Contributor::Deleted)
+                    } else {
+                        self.read_contributor(&mut revision)?;
+                        revision.contributor
+                    };
+                    continue;
+                }
+                b"minor" => revision.minor = true,
+                b"text" => revision.bytes = tag.attribute(b"bytes").and_then(|bytes| bytes.parse::<usize>().ok()),
+                _ => {}
+            }
 
-            if next_byte == b'>' {
-                let _ns_name_data = self.stream.read_until(b'<')?;
-                self.stream.match_exact(b"/namespace>\n")?;
-            } else if next_byte == b' ' {
-                self.stream.match_exact(b"/>\n")?;
-            } else {
-                return Err(Box::new(QwiktError(format!("Expected '>' or ' ', got {:?}", next_byte as char))));
+            let value = self.stream.read_text(&tag)?;
+            match tag.name.as_slice() {
+                b"id" => revision.id = String::from_utf8_lossy(&value).parse::<i32>().ok(),
+                b"parentid" => revision.parent_id = String::from_utf8_lossy(&value).parse::<i32>().ok(),
+                b"timestamp" => revision.timestamp = parse_timestamp(&String::from_utf8_lossy(&value)),
+                // `<comment deleted="deleted" />` leaves the comment as None
+                b"comment" if !tag.empty => revision.comment = Some(decode_text(&value)),
+                b"origin" => revision.origin = String::from_utf8_lossy(&value).parse::<i32>().ok(),
+                b"model" => revision.model = Some(String::from_utf8_lossy(&value).into_owned()),
+                b"format" => revision.format = Some(String::from_utf8_lossy(&value).into_owned()),
+                b"text" => rev_text = decode_text(&value),
+                b"sha1" => revision.sha1 = Some(String::from_utf8_lossy(&value).into_owned()),
+                _ => {}
             }
         }
 
-        self.stream.match_exact(b"  </siteinfo>\n")?;
+        Ok((revision, rev_text))
+    }
+
+    /// Read a `<contributor>`'s username and id, or IP, through `</contributor>`
+    fn read_contributor(&mut self, revision: &mut Revision) -> Result<(), Box<dyn Error>> {
+        let mut next = 0;
+        while let Some(tag) = self.stream.next_child(b"contributor")? {
+            tag.check_order("contributor", CONTRIBUTOR_CHILDREN, &mut next)?;
+            let value = self.stream.read_text(&tag)?;
+            match tag.name.as_slice() {
+                b"username" => revision.set_username(decode_text(&value)),
+                b"id" => revision.set_contributor_id(String::from_utf8_lossy(&value).parse::<i32>().ok()),
+                _ => revision.contributor = Some(Contributor::Ip(String::from_utf8_lossy(&value).into_owned())),
+            }
+        }
         Ok(())
     }
+
+    /// Read what follows a `</revision>`, returning whether another revision of the page comes next
+    fn read_revision_end(&mut self) -> Result<bool, Box<dyn Error>> {
+        match self.stream.next_child(b"page")? {
+            Some(tag) if tag.is(b"revision") => Ok(true),
+            Some(tag) => Err(tag.unexpected_in("page")),
+            None => Ok(false),
+        }
+    }
 }
 
 impl<R: Read> PageSource for QwiktReader<R> {
     fn next_page(&mut self) -> Result<Option<Page>, Box<dyn Error>> {
         // Initialize header on first call
-        if !self.initialized {
+        let first = !self.initialized;
+        if first {
             self.init_header()?;
             self.initialized = true;
         }
 
-        let first_byte = match self.stream.read_byte() {
-            Ok(b) => b,
-            Err(_) => return Ok(None),
-        };
-        
-        if first_byte == b'<' {
-            return Ok(None);
+        if self.page.is_none() && self.resynced {
+            self.resynced = false;
+            self.page = Some(self.read_page_header()?);
         }
 
-        self.stream.match_exact(b" <page>\n    <title>")?;
+        if self.page.is_none() {
+            let mut tag = match self.next_top_level()? {
+                Some(tag) => tag,
+                None => return Ok(None),
+            };
+
+            // siteinfo is optional, but only allowed before the first page
+            if first && tag.is(b"siteinfo") {
+                self.site_info = Some(self.read_site_info()?);
+                tag = match self.next_top_level()? {
+                    Some(tag) => tag,
+                    None => return Ok(None),
+                };
+            }
 
-        let title_bytes = self.stream.read_until(b'<')?;
-        let title = String::from_utf8_lossy(&title_bytes).into_owned();
+            if !tag.is(b"page") {
+                return Err(tag.unexpected_in("mediawiki"));
+            }
+            self.page = Some(self.read_page_header()?);
+        }
+
+        loop {
+            let (revision, rev_text) = self.read_revision()?;
+            let more_revisions = self.read_revision_end()?;
+            if more_revisions && !self.all_revisions {
+                continue;
+            }
+            let page = if more_revisions { self.page.clone() } else { self.page.take() };
+            return Ok(Some(Page {
+                revision,
+                rev_text,
+                ..page.unwrap_or_default()
+            }));
+        }
+    }
 
-        self.stream.match_exact(b"/title>\n    <ns>")?;
-        let ns_bytes = self.stream.read_until(b'<')?;
-        let ns = String::from_utf8_lossy(&ns_bytes).parse::<i32>().ok();
+    fn site_info(&self) -> Option<&SiteInfo> {
+        self.site_info.as_ref()
+    }
 
-        self.stream.match_exact(b"/ns>\n    <id>")?;
-        let id_bytes = self.stream.read_until(b'<')?;
-        let id = String::from_utf8_lossy(&id_bytes).parse::<i32>().ok();
-        
-        self.stream.match_exact(b"/id>\n    <re")?;
+    fn set_all_revisions(&mut self, all: bool) {
+        self.all_revisions = all;
+    }
 
-        let byte = self.stream.read_byte()?;
+    fn byte_offset(&self) -> Option<u64> {
+        Some(self.stream.position.off as u64)
+    }
 
-        if byte == b'd' {
-            self.stream.match_exact(b"irect title=\"")?;
-            let _redirect_title = self.stream.read_until(b'"')?;
-            self.stream.match_exact(b" />\n    <rev")?;
-        } else if byte != b'v' {
-            return Err(Box::new(QwiktError(format!("Expected 'd' or 'v', got {:?}", byte as char))));
+    fn recover(&mut self, recovery: Recovery) -> Result<bool, Box<dyn Error>> {
+        // Whatever part of the header was read, don't go looking for it again
+        self.initialized = true;
+        self.page = None;
+        self.resynced = false;
+        match recovery {
+            Recovery::Strict => Ok(false),
+            Recovery::SkipPage => self.stream.skip_past(b"</page>"),
+            Recovery::Resync => {
+                self.resynced = self.stream.skip_past(b"<page>")?;
+                Ok(self.resynced)
+            }
         }
+    }
+}
+
+/// Text content with its entity and character references decoded
+fn decode_text(bytes: &[u8]) -> String {
+    unescape(&String::from_utf8_lossy(bytes)).into_owned()
+}
 
-        self.stream.match_exact(b"ision>\n      <id>")?;
-        let _rev_id = self.stream.read_until(b'<')?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::read_all;
+    use crate::quick_xml_reader::QuickXmlReader;
 
-        self.stream.match_exact(b"/id>\n      <")?;
+    const DUMP: &str = include_str!("../fixtures/conformance/minor_ip.xml");
 
-        let byte = self.stream.read_byte()?;
-        if byte == b'p' {
-            self.stream.match_exact(b"arentid>")?;
-            let _parent_id = self.stream.read_until(b'<')?;
-            self.stream.match_exact(b"/parentid>\n      <timestamp>")?;
-        } else if byte == b't' {
-            self.stream.match_exact(b"imestamp>")?;
-        } else {
-            return Err(Box::new(QwiktError(format!("Expected 'p' or 't', got {:?}", byte as char))));
-        }
+    /// Hands out at most a few bytes per read, so matches and scans cross buffer refills
+    struct Trickle<'a>(&'a [u8], usize);
 
-        let _timestamp = self.stream.read_until(b'<')?;
-        self.stream.match_exact(b"/timestamp>\n      <contributor")?;
-
-        let byte = self.stream.read_byte()?;
-        let rev_contrib_id: Option<i32> = if byte == b' ' {
-            self.stream.match_exact(b"deleted=\"deleted\" />\n      <")?;
-            None
-        } else {
-            self.stream.match_exact(b"\n        <")?;
-
-            let mut contrib_id = None;
-            let byte = self.stream.read_byte()?;
-
-            // contributor - username+id or IP
-            if byte == b'u' {
-                self.stream.match_exact(b"sername>")?;
-                let _username = self.stream.read_until(b'<')?;
-
-                self.stream.match_exact(b"/username>\n        <id>")?;
-                let contrib_id_bytes = self.stream.read_until(b'<')?;
-                contrib_id = String::from_utf8_lossy(&contrib_id_bytes).parse::<i32>().ok();
-                self.stream.match_exact(b"/id>\n      </contributor>\n      <")?;
-            } else if byte == b'i' {
-                self.stream.match_exact(b"p>")?;
-                let _ip = self.stream.read_until(b'<')?;
-                self.stream.match_exact(b"/ip>\n      </contributor>\n      <")?;
-            } else {
-                return Err(Box::new(QwiktError(format!("Expected 'u' or 'i', got {:?}", byte as char))));
-            }
-            contrib_id
-        };
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.1 = self.1 % 3 + 1;
+            let len = self.1.min(buf.len()).min(self.0.len());
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
 
-        // optional <minor />
-        // optional <comment>...</comment> or <comment deleted="deleted" />
-        // optional <origin>...</origin>
+    #[test]
+    fn test_small_reads() {
+        let expected = read_all(&mut QwiktReader::new(DUMP.as_bytes())).unwrap();
+        assert_eq!(expected.len(), 2);
+        assert_eq!(read_all(&mut QwiktReader::new(Trickle(DUMP.as_bytes(), 0))).unwrap(), expected);
+    }
 
-        let mut next_byte = self.stream.read_byte()?;
+    #[test]
+    fn test_mismatch_position() {
+        let broken = DUMP.replacen("</model>", "</modle>", 1);
+        let at = broken.find("</modle>").unwrap() + 5;
+        let line = broken[..at].matches('\n').count() + 1;
+        let col = at - broken[..at].rfind('\n').unwrap() + 1;
+
+        let mut reader = QwiktReader::new(Trickle(broken.as_bytes(), 0));
+        let err = reader.next_page().unwrap_err().to_string();
+        assert_eq!(
+            err,
+            format!("Mismatch at byte {} (line {}, col {}): expected 'e', got 'l'", at + 1, line, col)
+        );
+        assert_eq!(reader.byte_offset(), Some(at as u64 + 1));
+    }
 
-        if ![b'm', b'c', b'o'].contains(&next_byte) {
-            return Err(Box::new(QwiktError(
-                format!("Expected 'm', 'c' or 'o', got {:?}", next_byte as char)
-            )));
-        }
+    #[test]
+    fn test_unexpected_element() {
+        let broken = DUMP.replacen("<model>", "<modle>", 1);
+        let at = broken.find("<modle>").unwrap();
+        let line = broken[..at].matches('\n').count() + 1;
+        let col = at - broken[..at].rfind('\n').unwrap();
+
+        let err = QwiktReader::new(broken.as_bytes()).next_page().unwrap_err().to_string();
+        assert_eq!(err, format!("Unexpected <modle> in <revision> at byte {} (line {}, col {})", at, line, col));
+
+        // Known elements out of order are just as wrong
+        let swapped = DUMP.replacen("<minor />\n      <comment>typo</comment>", "<comment>typo</comment><minor />", 1);
+        let err = QwiktReader::new(swapped.as_bytes()).next_page().unwrap_err().to_string();
+        assert!(err.starts_with("Unexpected <minor> in <revision>"), "{}", err);
+
+        let old = DUMP.replace("export-0.11/\"", "export-0.9/\"");
+        let err = QwiktReader::new(old.as_bytes()).next_page().unwrap_err().to_string();
+        assert_eq!(
+            err,
+            "Unsupported export schema \"http://www.mediawiki.org/xml/export-0.9/\" at byte 0 (line 1, col 1)"
+        );
+    }
 
-        if next_byte == b'm' {
-            self.stream.match_exact(b"inor />\n      <")?;
-            next_byte = self.stream.read_byte()?;
-        }
+    #[test]
+    fn test_layout_variations() {
+        let mut expected = QwiktReader::new(DUMP.as_bytes());
+        let expected_pages = read_all(&mut expected).unwrap();
+
+        let varied = format!("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{}", DUMP)
+            .replace("\n      <", "\n\t<")
+            .replace("<minor />", "<minor/>")
+            .replace("</title>", "</title >")
+            .replace("<ns>1</ns>\n", "");
+        let mut reader = QwiktReader::new(varied.as_bytes());
+        let pages = read_all(&mut reader).unwrap();
+        assert_eq!(pages[0], expected_pages[0]);
+        assert_eq!(pages[1].ns, None);
+        assert_eq!(pages[1].revision, expected_pages[1].revision);
+        assert_eq!(reader.site_info(), expected.site_info());
+    }
 
-        if next_byte == b'c' {
-            self.stream.match_exact(b"omment")?;
-            let byte = self.stream.read_byte()?;
-            if byte == b'>' {
-                let _comment = self.stream.read_until(b'<')?;
-                self.stream.match_exact(b"/comment>\n      <")?;
-                self.stream.read_byte()?;
-            } else if byte == b' ' {
-                self.stream.match_exact(b"deleted=\"deleted\" />\n      <")?;
-                self.stream.read_byte()?;
-            }
-        }
+    #[test]
+    fn test_sample_file() {
+        let sample = include_str!("../test_sample.xml");
+        let expected = read_all(&mut QuickXmlReader::from_bytes(sample.as_bytes())).unwrap();
+        assert!(!expected.is_empty());
 
-        self.stream.match_exact(b"rigin>")?;
-        let _origin = self.stream.read_until(b'<')?;
-        self.stream.match_exact(b"/origin>\n      <")?;
-
-        self.stream.match_exact(b"model>")?;
-        let _model = self.stream.read_until(b'<')?;
-
-        self.stream.match_exact(b"/model>\n      <")?;
-
-        self.stream.match_exact(b"format>")?;
-        let _format = self.stream.read_until(b'<')?;
-
-        self.stream.match_exact(b"/format>\n      <text bytes=\"")?;
-        let _text_bytes = self.stream.read_until(b'"')?;
-
-        self.stream.match_exact(b" sha1=\"")?;
-        let _text_sha1 = self.stream.read_until(b'"')?;
-
-        self.stream.match_exact(b" ")?;
-        let byte = self.stream.read_byte()?;
-        let rev_text = if byte == b'/' {
-            self.stream.match_exact(b">\n      <sha1>")?;
-            String::new()
-        } else if byte == b'x' {
-            self.stream.match_exact(b"ml:space=\"preserve\">")?;
-            let text_body = self.stream.read_until(b'<')?;
-            self.stream.match_exact(b"/text>\n      <sha1>")?;
-            String::from_utf8_lossy(&text_body).into_owned()
-        } else {
-            return Err(Box::new(QwiktError(format!("Expected '/' or 'x', got {:?}", byte as char))));
-        };
-
-        let _sha1_b = self.stream.read_until(b'<')?;
-        self.stream.match_exact(b"/sha1>\n    </revision>\n  </page>\n")?;
-
-        Ok(Some(Page {
-            title,
-            ns,
-            id,
-            rev_id: None, // Not tracked in output
-            rev_contrib_id,
-            rev_text,
-        }))
+        let mut reader = QwiktReader::new(sample.as_bytes());
+        assert_eq!(read_all(&mut reader).unwrap(), expected);
+        assert_eq!(reader.site_info(), None);
     }
 }
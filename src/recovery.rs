@@ -0,0 +1,241 @@
+//! Carrying on past pages that fail to parse, for long runs over dumps that are partly corrupt or
+//! cut short.
+//!
+//! Readers stop at the first error by default. Wrapping one in `RecoveringSource` with a lenient
+//! `Recovery` policy instead records the failed page as a `SkippedPage` and asks the reader to
+//! discard the rest of it through `PageSource::recover`, then goes on to the next page.
+
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{Page, PageSource, SiteInfo};
+
+/// What to do when a page can't be read
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum Recovery {
+    /// Stop with the error
+    #[default]
+    Strict,
+    /// Drop the page and carry on after its `</page>`
+    SkipPage,
+    /// Drop everything up to the next `<page>` and carry on from there
+    Resync,
+}
+
+impl Recovery {
+    pub const ALL: [Recovery; 3] = [Recovery::Strict, Recovery::SkipPage, Recovery::Resync];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Recovery::Strict => "strict",
+            Recovery::SkipPage => "skip-page",
+            Recovery::Resync => "resync",
+        }
+    }
+}
+
+impl FromStr for Recovery {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Recovery::ALL.into_iter().find(|recovery| recovery.name() == s).ok_or_else(|| {
+            let names: Vec<_> = Recovery::ALL.iter().map(|recovery| recovery.name()).collect();
+            format!("unknown recovery policy {:?}, expected one of {}", s, names.join(", "))
+        })
+    }
+}
+
+/// A page that was dropped because it couldn't be read
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SkippedPage {
+    /// Bytes of input consumed when the error was noticed, for readers that track it
+    pub offset: Option<u64>,
+    pub error: String,
+}
+
+impl fmt::Display for SkippedPage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.offset {
+            Some(offset) => write!(f, "at byte {}: {}", offset, self.error),
+            None => write!(f, "at unknown offset: {}", self.error),
+        }
+    }
+}
+
+/// Adapts a `PageSource` to skip pages it fails on rather than stopping, keeping a list of them.
+pub struct RecoveringSource<S: PageSource> {
+    source: S,
+    recovery: Recovery,
+    skipped: Vec<SkippedPage>,
+    finished: bool,
+}
+
+impl<S: PageSource> RecoveringSource<S> {
+    pub fn new(source: S, recovery: Recovery) -> Self {
+        RecoveringSource {
+            source,
+            recovery,
+            skipped: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// Pages skipped so far, in the order they were met
+    pub fn skipped_pages(&self) -> &[SkippedPage] {
+        &self.skipped
+    }
+
+    pub fn into_inner(self) -> S {
+        self.source
+    }
+}
+
+impl<S: PageSource> PageSource for RecoveringSource<S> {
+    fn next_page(&mut self) -> Result<Option<Page>, Box<dyn Error>> {
+        loop {
+            if self.finished {
+                return Ok(None);
+            }
+            match self.source.next_page() {
+                Ok(page) => return Ok(page),
+                Err(e) if self.recovery == Recovery::Strict => return Err(e),
+                Err(e) => {
+                    self.skipped.push(SkippedPage {
+                        offset: self.source.byte_offset(),
+                        error: e.to_string(),
+                    });
+                    // The input ran out before there was anywhere to pick up from
+                    if !self.source.recover(self.recovery)? {
+                        self.finished = true;
+                    }
+                }
+            }
+        }
+    }
+
+    fn site_info(&self) -> Option<&SiteInfo> {
+        self.source.site_info()
+    }
+
+    fn set_all_revisions(&mut self, all: bool) {
+        self.source.set_all_revisions(all)
+    }
+
+    fn byte_offset(&self) -> Option<u64> {
+        self.source.byte_offset()
+    }
+
+    fn recover(&mut self, recovery: Recovery) -> Result<bool, Box<dyn Error>> {
+        self.source.recover(recovery)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::conformance::read_all;
+    use crate::{new_page_source, ReaderKind};
+
+    const DUMP: &str = include_str!("../fixtures/conformance/redirects.xml");
+
+    /// The fixture's two pages with a third between them whose `<ns>` and `<id>` aren't numbers
+    fn corrupted() -> String {
+        let second = DUMP.find("  <page>\n    <title>Appendix:Colours").unwrap();
+        let broken = DUMP[second..DUMP.rfind("</mediawiki>").unwrap()]
+            .replace("Appendix:Colours", "broken")
+            .replace("<ns>100</ns>", "<ns>1OO</ns>")
+            .replace("<id>702</id>", "<id>7O2</id>");
+        let mut corrupted = DUMP.to_string();
+        corrupted.insert_str(second, &broken);
+        corrupted
+    }
+
+    fn source(kind: ReaderKind, xml: &str) -> Box<dyn PageSource> {
+        new_page_source(kind, Box::new(Cursor::new(xml.as_bytes().to_vec())))
+    }
+
+    fn titles(pages: &[Page]) -> Vec<&str> {
+        pages.iter().map(|page| page.title.as_str()).collect()
+    }
+
+    #[test]
+    fn test_recovery_from_str() {
+        for recovery in Recovery::ALL {
+            assert_eq!(recovery.name().parse::<Recovery>(), Ok(recovery));
+        }
+        assert!("lenient".parse::<Recovery>().is_err());
+    }
+
+    #[test]
+    fn test_skip_broken_page() {
+        let xml = corrupted();
+        // Only the readers that parse numbers strictly notice the broken page
+        for kind in [ReaderKind::QuickXml, ReaderKind::Regex, ReaderKind::StringOps] {
+            assert!(read_all(&mut source(kind, &xml)).is_err(), "{}", kind.name());
+        }
+
+        for kind in [ReaderKind::QuickXml, ReaderKind::Regex, ReaderKind::StringOps] {
+            for recovery in [Recovery::SkipPage, Recovery::Resync] {
+                let mut recovering = RecoveringSource::new(source(kind, &xml), recovery);
+                let pages = read_all(&mut recovering).unwrap();
+                let context = format!("{} / {}", kind.name(), recovery.name());
+                assert_eq!(titles(&pages), ["colour", "Appendix:Colours"], "{}", context);
+                assert_eq!(recovering.skipped_pages().len(), 1, "{}", context);
+                assert!(recovering.skipped_pages()[0].offset.is_some(), "{}", context);
+            }
+        }
+    }
+
+    #[test]
+    fn test_qwikt_recovery() {
+        // qwikt reads ids leniently, but stops at any element it doesn't expect
+        let xml = corrupted().replacen("<ns>1OO</ns>", "<nss>100</nss>", 1);
+        let err = read_all(&mut source(ReaderKind::Qwikt, &xml)).unwrap_err();
+        assert!(err.to_string().starts_with("Unexpected <nss> in <page>"), "{}", err);
+
+        for recovery in [Recovery::SkipPage, Recovery::Resync] {
+            let mut recovering = RecoveringSource::new(source(ReaderKind::Qwikt, &xml), recovery);
+            let pages = read_all(&mut recovering).unwrap();
+            assert_eq!(titles(&pages), ["colour", "Appendix:Colours"], "{}", recovery.name());
+            assert_eq!(recovering.skipped_pages().len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_source_without_recovery() {
+        // Fails after its first page, and can't skip past the failure
+        struct Failing(Option<Page>);
+
+        impl PageSource for Failing {
+            fn next_page(&mut self) -> Result<Option<Page>, Box<dyn Error>> {
+                self.0.take().map(Some).ok_or_else(|| "broken page".into())
+            }
+        }
+
+        let mut first = Page::new();
+        first.title = "colour".to_string();
+        let mut recovering = RecoveringSource::new(Failing(Some(first)), Recovery::Resync);
+        let pages = read_all(&mut recovering).unwrap();
+        assert_eq!(titles(&pages), ["colour"]);
+        assert_eq!(recovering.skipped_pages().len(), 1);
+        assert_eq!(recovering.skipped_pages()[0].error, "broken page");
+    }
+
+    #[test]
+    fn test_truncated_dump() {
+        let cut = DUMP.find("#REDIRECT [[Appendix:Colors]]").unwrap();
+        let xml = &DUMP[..cut];
+        for kind in ReaderKind::ALL {
+            assert!(read_all(&mut source(kind, xml)).is_err(), "{}", kind.name());
+
+            let mut recovering = RecoveringSource::new(source(kind, xml), Recovery::Resync);
+            let pages = read_all(&mut recovering).unwrap();
+            assert_eq!(titles(&pages), ["colour"], "{}", kind.name());
+            assert_eq!(recovering.skipped_pages().len(), 1, "{}", kind.name());
+            assert_eq!(recovering.skipped_pages()[0].offset, Some(xml.len() as u64), "{}", kind.name());
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! Following redirect pages to the entries they point at.
+//!
+//! Wiktionary uses redirects for misspellings, alternative capitalisations and the like. A
+//! redirect can point at another redirect, and occasionally a chain loops back on itself, so
+//! lookups go through `RedirectMap::resolve`, which follows chains and reports cycles.
+
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+
+use crate::{Page, PageSource};
+
+/// A redirect chain that loops back on itself, in the order it was followed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct RedirectCycle(pub Vec<String>);
+
+impl fmt::Display for RedirectCycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Redirect cycle: {}", self.0.join(" → "))
+    }
+}
+
+impl Error for RedirectCycle {}
+
+#[derive(Debug, Default)]
+pub struct RedirectMap {
+    targets: HashMap<String, String>,
+}
+
+impl RedirectMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collect every redirect from a source, discarding the non-redirect pages.
+    pub fn from_source(source: &mut dyn PageSource) -> Result<Self, Box<dyn Error>> {
+        let mut map = Self::new();
+        while let Some(page) = source.next_page()? {
+            map.add_page(&page);
+        }
+        Ok(map)
+    }
+
+    pub fn insert(&mut self, from: impl Into<String>, to: impl Into<String>) {
+        self.targets.insert(from.into(), to.into());
+    }
+
+    /// Record the page if it's a redirect. Returns whether it was.
+    pub fn add_page(&mut self, page: &Page) -> bool {
+        match &page.redirect {
+            Some(target) => {
+                self.insert(page.title.clone(), target.clone());
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.targets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.targets.is_empty()
+    }
+
+    /// The immediate target of a redirect, without following chains
+    pub fn target(&self, title: &str) -> Option<&str> {
+        self.targets.get(title).map(|s| s.as_str())
+    }
+
+    /// Follow redirects from `title` until reaching a page that isn't one.
+    ///
+    /// A title that isn't a redirect resolves to itself.
+    pub fn resolve<'a>(&'a self, title: &'a str) -> Result<&'a str, RedirectCycle> {
+        let mut seen = HashSet::new();
+        let mut chain = vec![title];
+        let mut current = title;
+        seen.insert(title);
+
+        while let Some(next) = self.target(current) {
+            chain.push(next);
+            if !seen.insert(next) {
+                return Err(RedirectCycle(chain.into_iter().map(String::from).collect()));
+            }
+            current = next;
+        }
+        Ok(current)
+    }
+
+    /// Resolve every redirect, returning the final targets and any cycles found.
+    ///
+    /// Each cycle is reported once, starting from the title that sorts first.
+    pub fn resolve_all(&self) -> (HashMap<String, String>, Vec<RedirectCycle>) {
+        let mut resolved = HashMap::with_capacity(self.targets.len());
+        let mut cycles = Vec::new();
+        let mut reported = HashSet::new();
+
+        let mut titles: Vec<&String> = self.targets.keys().collect();
+        titles.sort();
+
+        for title in titles {
+            match self.resolve(title) {
+                Ok(target) => {
+                    resolved.insert(title.clone(), target.to_string());
+                }
+                Err(RedirectCycle(chain)) => {
+                    // Keep only the loop itself, dropping any lead-in
+                    let last = chain.last().unwrap();
+                    let loop_start = chain.iter().position(|t| t == last).unwrap();
+                    let mut cycle: Vec<String> = chain[loop_start..chain.len() - 1].to_vec();
+                    let min = (0..cycle.len()).min_by_key(|&i| &cycle[i]).unwrap();
+                    cycle.rotate_left(min);
+                    if reported.insert(cycle.clone()) {
+                        let first = cycle[0].clone();
+                        cycle.push(first);
+                        cycles.push(RedirectCycle(cycle));
+                    }
+                }
+            }
+        }
+
+        (resolved, cycles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_chain() {
+        let mut map = RedirectMap::new();
+        map.insert("colour", "color");
+        map.insert("Colour", "colour");
+
+        assert_eq!(map.resolve("Colour"), Ok("color"));
+        assert_eq!(map.resolve("colour"), Ok("color"));
+        assert_eq!(map.resolve("color"), Ok("color"));
+        assert_eq!(map.target("Colour"), Some("colour"));
+    }
+
+    #[test]
+    fn test_resolve_cycle() {
+        let mut map = RedirectMap::new();
+        map.insert("a", "b");
+        map.insert("b", "c");
+        map.insert("c", "b");
+        map.insert("x", "y");
+
+        let err = map.resolve("a").unwrap_err();
+        assert_eq!(err.0, ["a", "b", "c", "b"]);
+
+        let (resolved, cycles) = map.resolve_all();
+        assert_eq!(resolved.len(), 1);
+        assert_eq!(resolved["x"], "y");
+        assert_eq!(cycles, [RedirectCycle(vec!["b".into(), "c".into(), "b".into()])]);
+    }
+
+    #[test]
+    fn test_from_source() {
+        use crate::quick_xml_reader::QuickXmlReader;
+
+        let xml = br#"<mediawiki>
+  <page>
+    <title>colour</title>
+    <ns>0</ns>
+    <id>1</id>
+    <redirect title="color" />
+    <revision>
+      <id>10</id>
+      <text>#REDIRECT [[color]]</text>
+    </revision>
+  </page>
+  <page>
+    <title>cat</title>
+    <ns>0</ns>
+    <id>2</id>
+    <revision>
+      <id>20</id>
+      <text>==English==</text>
+    </revision>
+  </page>
+</mediawiki>"#;
+        let map = RedirectMap::from_source(&mut QuickXmlReader::from_bytes(xml)).unwrap();
+        assert_eq!(map.len(), 1);
+        assert_eq!(map.target("colour"), Some("color"));
+        assert_eq!(map.resolve("cat"), Ok("cat"));
+    }
+}
@@ -1,15 +1,28 @@
 use std::error::Error;
 use std::io::BufRead;
 
-use crate::{Page, PageSource};
+use regex::Regex;
+
+use crate::entities::unescape;
+use crate::revision::parse_timestamp;
+use crate::{Contributor, Namespace, Page, PageSource, Recovery, Revision, SiteInfo};
 
 pub struct RegexReader<R: BufRead> {
-    lines: std::io::Lines<R>,
+    reader: R,
+    offset: u64,
     state: State,
+    site_info: Option<SiteInfo>,
     title: Option<String>,
     ns: Option<i32>,
     pid: Option<i32>,
+    redirect: Option<String>,
+    revision: Revision,
     text_buffer: String,
+    all_revisions: bool,
+    field_regex: Regex,
+    text_bytes_regex: Regex,
+    redirect_regex: Regex,
+    namespace_regex: Regex,
 }
 
 #[derive(PartialEq)]
@@ -17,15 +30,21 @@ enum State {
     /// Initial state, waiting for a <page> tag
     PrePage,
 
+    /// Inside the <siteinfo> header
+    InSiteInfo,
+
     /// Inside <page> but haven't found <title> and <id> yet
     InPage,
 
-    /// Inside <page> and have found both title and page ID
+    /// Inside <page> and past its title and page ID, which may be missing
     InPageAfterTitleAndId,
 
     /// Inside a <revision> tag
     InRevision,
 
+    /// Inside a <contributor> tag within a revision
+    InContributor,
+
     /// Inside the text content
     InRevisionText,
 }
@@ -33,12 +52,68 @@ enum State {
 impl<R: BufRead> RegexReader<R> {
     pub fn new(reader: R) -> Self {
         RegexReader {
-            lines: reader.lines(),
+            reader,
+            offset: 0,
             state: State::PrePage,
+            site_info: None,
             title: None,
             ns: None,
             pid: None,
+            redirect: None,
+            revision: Revision::new(),
             text_buffer: String::new(),
+            all_revisions: false,
+            field_regex: Regex::new(
+                r"<(id|parentid|timestamp|username|ip|comment|origin|model|format|sha1|sitename|dbname|base|generator|case)>([^<]*)</",
+            )
+            .unwrap(),
+            text_bytes_regex: Regex::new(r#"^<text[^>]*\sbytes="(\d+)""#).unwrap(),
+            redirect_regex: Regex::new(r#"<redirect title="([^"]*)""#).unwrap(),
+            namespace_regex: Regex::new(r#"<namespace key="(-?\d+)" case="([^"]*)"(?: />|>([^<]*)</namespace>)"#).unwrap(),
+        }
+    }
+
+    /// Read the next line without its line ending, keeping count of the bytes consumed
+    fn next_line(&mut self) -> Result<Option<String>, Box<dyn Error>> {
+        let mut line = String::new();
+        let len = self.reader.read_line(&mut line)?;
+        if len == 0 {
+            return Ok(None);
+        }
+        self.offset += len as u64;
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+
+    /// The title that starts with `rest`, just after `<title>`, reading on through the lines it
+    /// runs over if it's pretty printed
+    fn read_title(&mut self, rest: &str) -> Result<String, Box<dyn Error>> {
+        let mut title = rest.to_string();
+        while !title.contains("</title>") {
+            let line = self
+                .next_line()?
+                .ok_or_else(|| format!("Unexpected EOF inside <title> at byte {}", self.offset))?;
+            title.push('\n');
+            title.push_str(&line);
+        }
+        title.truncate(title.find("</title>").unwrap());
+        Ok(unescape(&title).into_owned())
+    }
+
+    /// The page-level fields seen so far, together with the current revision and its text
+    fn revision_page(&mut self) -> Page {
+        Page {
+            title: self.title.clone().unwrap_or_default(),
+            ns: self.ns,
+            id: self.pid,
+            redirect: self.redirect.clone(),
+            revision: std::mem::take(&mut self.revision),
+            rev_text: std::mem::take(&mut self.text_buffer),
         }
     }
 }
@@ -46,22 +121,18 @@ impl<R: BufRead> RegexReader<R> {
 impl<R: BufRead> PageSource for RegexReader<R> {
     fn next_page(&mut self) -> Result<Option<Page>, Box<dyn Error>> {
         loop {
-            let line = match self.lines.next() {
-                Some(Ok(l)) => l,
-                Some(Err(e)) => return Err(Box::new(e)),
-                None => return Ok(None),
+            let line = match self.next_line()? {
+                Some(l) => l,
+                None if self.state == State::PrePage => return Ok(None),
+                None if self.state == State::InSiteInfo => {
+                    return Err(format!("Unexpected EOF inside <siteinfo> at byte {}", self.offset).into());
+                }
+                None => return Err(format!("Unexpected EOF inside <page> at byte {}", self.offset).into()),
             };
 
-            if self.state == State::PrePage {
-                if line.contains("<page>") {
-                    self.state = State::InPage;
-                }
-            } else if self.state == State::InPage {
+            if self.state == State::InPage {
                 if let Some(title_start) = line.find("<title>") {
-                    if let Some(title_end) = line[title_start..].find("</title>") {
-                        let title_end = title_start + title_end;
-                        self.title = Some(line[title_start + 7..title_end].to_string());
-                    }
+                    self.title = Some(self.read_title(&line[title_start + 7..])?);
                 }
                 if let Some(ns_start) = line.find("<ns>") {
                     if let Some(ns_end) = line[ns_start..].find("</ns>") {
@@ -77,44 +148,164 @@ impl<R: BufRead> PageSource for RegexReader<R> {
                 }
                 if self.title.is_some() && self.pid.is_some() {
                     self.state = State::InPageAfterTitleAndId;
+                    continue;
+                }
+                let header = ["<title>", "<ns>", "<id>"].iter().any(|tag| line.contains(tag));
+                if header || self.title.is_none() {
+                    continue;
+                }
+                // <id> is optional, so once there's a title any other line is the rest of the page
+                self.state = State::InPageAfterTitleAndId;
+            }
+
+            if self.state == State::PrePage {
+                if line.contains("<page>") {
+                    self.state = State::InPage;
+                } else if line.contains("<siteinfo>") {
+                    self.state = State::InSiteInfo;
+                    self.site_info = Some(SiteInfo::new());
+                }
+            } else if self.state == State::InSiteInfo {
+                let site_info = self.site_info.get_or_insert_with(SiteInfo::new);
+                if line.contains("</siteinfo>") {
+                    self.state = State::PrePage;
+                } else if let Some(caps) = self.namespace_regex.captures(&line) {
+                    site_info.namespaces.push(Namespace {
+                        key: caps[1].parse::<i32>()?,
+                        case: caps[2].to_string(),
+                        name: unescape(caps.get(3).map_or("", |m| m.as_str())).into_owned(),
+                    });
+                } else if let Some(caps) = self.field_regex.captures(&line) {
+                    let value = unescape(&caps[2]).into_owned();
+                    match &caps[1] {
+                        "sitename" => site_info.sitename = value,
+                        "dbname" => site_info.dbname = value,
+                        "base" => site_info.base = value,
+                        "generator" => site_info.generator = value,
+                        "case" => site_info.case = value,
+                        _ => {}
+                    }
                 }
             } else if self.state == State::InPageAfterTitleAndId {
                 if line.contains("<revision>") {
                     self.state = State::InRevision;
+                    self.revision = Revision::new();
+                    self.text_buffer.clear();
+                } else if let Some(caps) = self.redirect_regex.captures(&line) {
+                    self.redirect = Some(unescape(&caps[1]).into_owned());
                 } else if line.contains("</page>") {
-                    let page = Page {
-                        title: self.title.take().unwrap_or_default(),
-                        ns: self.ns,
-                        id: self.pid,
-                        rev_id: Some(-1),
-                        rev_contrib_id: None,
-                        rev_text: self.text_buffer.clone(),
-                    };
+                    let page = (!self.all_revisions).then(|| self.revision_page());
+                    self.title = None;
                     self.pid = None;
                     self.ns = None;
-                    self.text_buffer.clear();
+                    self.redirect = None;
                     self.state = State::PrePage;
-                    return Ok(Some(page));
+                    if page.is_some() {
+                        return Ok(page);
+                    }
                 }
             } else if self.state == State::InRevision {
-                if line.contains("<text") {
-                    self.state = State::InRevisionText;
-                    if let Some(end_tag) = line.find(">") {
-                        self.text_buffer.push_str(&line[end_tag + 1..]);
-                        self.text_buffer.push_str("\n");
+                if let Some(text_start) = line.find("<text") {
+                    let text_tag = &line[text_start..];
+                    self.revision.bytes = self
+                        .text_bytes_regex
+                        .captures(text_tag)
+                        .and_then(|caps| caps[1].parse().ok());
+                    if let Some(end_tag) = text_tag.find('>') {
+                        let body = &text_tag[end_tag + 1..];
+                        if text_tag[..end_tag].ends_with('/') {
+                            // <text ... /> has no body
+                        } else if let Some(close) = body.find("</text>") {
+                            self.text_buffer.push_str(&unescape(&body[..close]));
+                        } else {
+                            self.state = State::InRevisionText;
+                            self.text_buffer.push_str(&unescape(body));
+                            self.text_buffer.push('\n');
+                        }
                     }
+                } else if line.contains("<contributor deleted") {
+                    self.revision.contributor = Some(Contributor::Deleted);
+                } else if line.contains("<contributor>") {
+                    self.state = State::InContributor;
+                } else if line.contains("<minor") {
+                    self.revision.minor = true;
                 } else if line.contains("</revision>") {
                     self.state = State::InPageAfterTitleAndId;
+                    if self.all_revisions {
+                        return Ok(Some(self.revision_page()));
+                    }
+                } else if let Some(caps) = self.field_regex.captures(&line) {
+                    let value = &caps[2];
+                    match &caps[1] {
+                        "id" => self.revision.id = Some(value.parse::<i32>()?),
+                        "parentid" => self.revision.parent_id = Some(value.parse::<i32>()?),
+                        "timestamp" => self.revision.timestamp = parse_timestamp(value),
+                        "comment" => self.revision.comment = Some(unescape(value).into_owned()),
+                        "origin" => self.revision.origin = value.parse::<i32>().ok(),
+                        "model" => self.revision.model = Some(value.to_string()),
+                        "format" => self.revision.format = Some(value.to_string()),
+                        "sha1" => self.revision.sha1 = Some(value.to_string()),
+                        _ => {}
+                    }
+                }
+            } else if self.state == State::InContributor {
+                if line.contains("</contributor>") {
+                    self.state = State::InRevision;
+                } else if let Some(caps) = self.field_regex.captures(&line) {
+                    let value = &caps[2];
+                    match &caps[1] {
+                        "username" => self.revision.set_username(unescape(value).into_owned()),
+                        "id" => self.revision.set_contributor_id(Some(value.parse::<i32>()?)),
+                        "ip" => self.revision.contributor = Some(Contributor::Ip(value.to_string())),
+                        _ => {}
+                    }
                 }
             } else if self.state == State::InRevisionText {
-                self.text_buffer.push_str(&line);
-                if line.contains("</text>") {
-                    self.state = State::InPageAfterTitleAndId;
-                    self.text_buffer.truncate(self.text_buffer.len() - 7);
+                if let Some(close) = line.find("</text>") {
+                    self.text_buffer.push_str(&unescape(&line[..close]));
+                    self.state = State::InRevision;
                 } else {
-                    self.text_buffer.push_str("\n");
+                    self.text_buffer.push_str(&unescape(&line));
+                    self.text_buffer.push('\n');
+                }
+            }
+        }
+    }
+
+    fn site_info(&self) -> Option<&SiteInfo> {
+        self.site_info.as_ref()
+    }
+
+    fn set_all_revisions(&mut self, all: bool) {
+        self.all_revisions = all;
+    }
+
+    fn byte_offset(&self) -> Option<u64> {
+        Some(self.offset)
+    }
+
+    fn recover(&mut self, recovery: Recovery) -> Result<bool, Box<dyn Error>> {
+        self.title = None;
+        self.pid = None;
+        self.ns = None;
+        self.redirect = None;
+        self.revision = Revision::new();
+        self.text_buffer.clear();
+        self.state = State::PrePage;
+        let marker = match recovery {
+            Recovery::Strict => return Ok(false),
+            Recovery::SkipPage => "</page>",
+            Recovery::Resync => "<page>",
+        };
+
+        while let Some(line) = self.next_line()? {
+            if line.contains(marker) {
+                if recovery == Recovery::Resync {
+                    self.state = State::InPage;
                 }
+                return Ok(true);
             }
         }
+        Ok(false)
     }
 }
@@ -0,0 +1,143 @@
+//! Revision metadata carried alongside each page's text.
+
+use chrono::{DateTime, Utc};
+
+/// Who made a revision. Deleted means the contributor was suppressed (`<contributor deleted="deleted" />`).
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Contributor {
+    User { username: String, id: Option<i32> },
+    Ip(String),
+    Deleted,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Revision {
+    pub id: Option<i32>,
+    pub parent_id: Option<i32>,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub contributor: Option<Contributor>,
+    /// Edit summary; None when absent or deleted
+    pub comment: Option<String>,
+    pub minor: bool,
+    pub origin: Option<i32>,
+    pub model: Option<String>,
+    pub format: Option<String>,
+    pub sha1: Option<String>,
+    /// Length of the revision text in bytes, from `<text bytes="...">`
+    pub bytes: Option<usize>,
+}
+
+impl Revision {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// User id of the contributor, if it was a logged-in user
+    pub fn contributor_id(&self) -> Option<i32> {
+        match self.contributor {
+            Some(Contributor::User { id, .. }) => id,
+            _ => None,
+        }
+    }
+
+    /// Set the contributor's username, keeping any id already seen
+    pub(crate) fn set_username(&mut self, username: String) {
+        let id = self.contributor_id();
+        self.contributor = Some(Contributor::User { username, id });
+    }
+
+    /// Set the contributor's user id, keeping any username already seen
+    pub(crate) fn set_contributor_id(&mut self, id: Option<i32>) {
+        match &mut self.contributor {
+            Some(Contributor::User { id: user_id, .. }) => *user_id = id,
+            _ => {
+                self.contributor = Some(Contributor::User {
+                    username: String::new(),
+                    id,
+                })
+            }
+        }
+    }
+}
+
+/// Parse a dump timestamp such as `2025-01-20T08:15:42Z`.
+pub fn parse_timestamp(s: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(s.trim()).ok().map(|dt| dt.with_timezone(&Utc))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::read_all;
+    use crate::{new_page_source, ReaderKind};
+    use chrono::{Datelike, Timelike};
+
+    const DUMP: &str = include_str!("../fixtures/conformance/full_history.xml");
+
+    #[test]
+    fn test_all_readers_populate_revision() {
+        let expected = read_all(&mut new_page_source(ReaderKind::QuickXml, Box::new(DUMP.as_bytes()))).unwrap();
+        assert_eq!(expected.len(), 2);
+
+        let cat = &expected[0].revision;
+        assert_eq!(cat.id, Some(200));
+        assert_eq!(cat.parent_id, Some(100));
+        assert_eq!(cat.timestamp, parse_timestamp("2010-06-01T10:00:00Z"));
+        assert_eq!(cat.contributor, Some(Contributor::Ip("192.0.2.1".to_string())));
+        assert!(!cat.minor);
+        assert_eq!(cat.comment, None);
+        assert_eq!(cat.origin, Some(200));
+        assert_eq!(cat.model.as_deref(), Some("wikitext"));
+        assert_eq!(cat.format.as_deref(), Some("text/x-wiki"));
+        assert_eq!(cat.sha1.as_deref(), Some("bbb"));
+        assert_eq!(cat.bytes, Some(38));
+
+        let dog = &expected[1].revision;
+        assert_eq!(
+            dog.contributor,
+            Some(Contributor::User {
+                username: "Bob".to_string(),
+                id: Some(43)
+            })
+        );
+        assert!(dog.minor);
+        assert_eq!(dog.comment.as_deref(), Some("tidy"));
+        assert_eq!(dog.bytes, Some(0));
+        assert_eq!(expected[1].rev_text, "");
+        assert_eq!(expected[0].redirect, None);
+        assert_eq!(expected[1].redirect.as_deref(), Some("hound"));
+
+        for kind in ReaderKind::ALL {
+            let pages = read_all(&mut new_page_source(kind, Box::new(DUMP.as_bytes()))).unwrap();
+            assert_eq!(pages, expected, "{}", kind.name());
+        }
+    }
+
+    #[test]
+    fn test_parse_timestamp() {
+        let ts = parse_timestamp("2025-01-20T08:15:42Z").unwrap();
+        assert_eq!((ts.year(), ts.month(), ts.day()), (2025, 1, 20));
+        assert_eq!((ts.hour(), ts.minute(), ts.second()), (8, 15, 42));
+
+        assert!(parse_timestamp("yesterday").is_none());
+    }
+
+    #[test]
+    fn test_contributor_in_either_order() {
+        let mut rev = Revision::new();
+        rev.set_username("Alice".to_string());
+        rev.set_contributor_id(Some(42));
+        assert_eq!(
+            rev.contributor,
+            Some(Contributor::User {
+                username: "Alice".to_string(),
+                id: Some(42)
+            })
+        );
+
+        let mut rev = Revision::new();
+        rev.set_contributor_id(Some(42));
+        rev.set_username("Alice".to_string());
+        assert_eq!(rev.contributor_id(), Some(42));
+    }
+}
@@ -0,0 +1,229 @@
+//! Parent/child structure over the flat heading array from `wikitext_splitter`.
+//!
+//! Nesting is implicit in heading levels, and a lot can be done by walking the array. But once
+//! several tools need "the headings under this one" or "which Etymology is this Noun in", it's
+//! better worked out once. A `SectionTree` does that without copying: it borrows the headings and
+//! refers to sections by their index in the array, the same index `content_for_heading` takes.
+//!
+//! A heading's parent is the nearest heading before it with a lower level, so level skips
+//! (`==English==` then `====Noun====`) nest under whatever is above them rather than getting lost.
+
+use std::ops::Range;
+
+use crate::wikitext_splitter::{Heading, HeadingRef};
+
+/// A heading's level and text, so a tree can be built over owned or borrowed headings
+pub trait HeadingLike {
+    fn level(&self) -> usize;
+    fn text(&self) -> &str;
+}
+
+impl HeadingLike for Heading {
+    fn level(&self) -> usize {
+        self.level
+    }
+
+    fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+impl HeadingLike for HeadingRef<'_> {
+    fn level(&self) -> usize {
+        self.level
+    }
+
+    fn text(&self) -> &str {
+        self.text
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    parent: Option<usize>,
+    children: Vec<usize>,
+    depth: usize,
+    sibling_index: usize,
+    /// Index just past the section's last descendant
+    end: usize,
+}
+
+/// The sections of a page as a tree, indexed like the heading array it was built from.
+#[derive(Debug, Clone)]
+pub struct SectionTree<'a, H: HeadingLike = Heading> {
+    headings: &'a [H],
+    nodes: Vec<Node>,
+    roots: Vec<usize>,
+}
+
+impl<'a, H: HeadingLike> SectionTree<'a, H> {
+    pub fn new(headings: &'a [H]) -> Self {
+        let mut nodes: Vec<Node> = Vec::with_capacity(headings.len());
+        let mut roots = Vec::new();
+        // Headings whose sections are still open, outermost first
+        let mut open: Vec<usize> = Vec::new();
+
+        for (i, heading) in headings.iter().enumerate() {
+            while let Some(&last) = open.last() {
+                if headings[last].level() < heading.level() {
+                    break;
+                }
+                nodes[last].end = i;
+                open.pop();
+            }
+
+            let parent = open.last().copied();
+            let siblings = match parent {
+                Some(parent) => &mut nodes[parent].children,
+                None => &mut roots,
+            };
+            let sibling_index = siblings.len();
+            siblings.push(i);
+
+            nodes.push(Node {
+                parent,
+                children: Vec::new(),
+                depth: open.len(),
+                sibling_index,
+                end: headings.len(),
+            });
+            open.push(i);
+        }
+
+        SectionTree { headings, nodes, roots }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn heading(&self, index: usize) -> &'a H {
+        &self.headings[index]
+    }
+
+    /// The top-level sections, usually the L2 language headings
+    pub fn roots(&self) -> &[usize] {
+        &self.roots
+    }
+
+    pub fn parent(&self, index: usize) -> Option<usize> {
+        self.nodes[index].parent
+    }
+
+    pub fn children(&self, index: usize) -> &[usize] {
+        &self.nodes[index].children
+    }
+
+    /// How many sections this one is nested in, 0 for a root
+    pub fn depth(&self, index: usize) -> usize {
+        self.nodes[index].depth
+    }
+
+    /// Position among its parent's children (or among the roots), from 0
+    pub fn sibling_index(&self, index: usize) -> usize {
+        self.nodes[index].sibling_index
+    }
+
+    /// Every section nested anywhere under this one, as a range of heading indices
+    pub fn descendants(&self, index: usize) -> Range<usize> {
+        index + 1..self.nodes[index].end
+    }
+
+    /// The content chunks (from `split_by_headings` or `split_spans`) of this section and everything nested in it
+    pub fn section_chunks<'c, C>(&self, index: usize, chunks: &'c [C]) -> &'c [C] {
+        &chunks[index + 1..self.nodes[index].end + 1]
+    }
+
+    /// Indices from the root down to this section
+    pub fn path(&self, index: usize) -> Vec<usize> {
+        let mut path = vec![index];
+        let mut current = index;
+        while let Some(parent) = self.nodes[current].parent {
+            path.push(parent);
+            current = parent;
+        }
+        path.reverse();
+        path
+    }
+
+    /// The headings from the root down to this section, e.g. `English > Etymology 2 > Noun > Translations`
+    pub fn path_text(&self, index: usize) -> String {
+        let texts: Vec<_> = self.path(index).into_iter().map(|i| self.headings[i].text()).collect();
+        texts.join(" > ")
+    }
+
+    /// The first root section with exactly this heading, e.g. a language
+    pub fn find_root(&self, text: &str) -> Option<usize> {
+        self.roots.iter().copied().find(|&i| self.headings[i].text() == text)
+    }
+
+    /// The first child of a section whose heading satisfies the predicate
+    pub fn find_child(&self, index: usize, predicate: impl Fn(&H) -> bool) -> Option<usize> {
+        self.children(index).iter().copied().find(|&i| predicate(&self.headings[i]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wikitext_splitter::{split_by_headings, split_spans};
+
+    const TEXT: &str = "{{also|Cat}}
+==English==
+===Etymology 1===
+====Noun====
+=====Translations=====
+===Etymology 2===
+====Verb====
+==French==
+====Noun====
+# chat
+";
+
+    #[test]
+    fn test_structure() {
+        let (headings, chunks) = split_by_headings(TEXT);
+        let tree = SectionTree::new(&headings);
+
+        assert_eq!(tree.len(), 8);
+        assert_eq!(tree.roots(), [0, 6]);
+        assert_eq!(tree.children(0), [1, 4]);
+        assert_eq!(tree.children(1), [2]);
+        assert_eq!(tree.parent(3), Some(2));
+        assert_eq!(tree.parent(0), None);
+        assert_eq!(tree.depth(3), 3);
+        assert_eq!(tree.sibling_index(4), 1);
+        assert_eq!(tree.sibling_index(6), 1);
+        assert_eq!(tree.descendants(1), 2..4);
+        assert_eq!(tree.descendants(0), 1..6);
+        assert_eq!(tree.path(3), [0, 1, 2, 3]);
+        assert_eq!(tree.path_text(3), "English > Etymology 1 > Noun > Translations");
+        assert_eq!(tree.find_root("French"), Some(6));
+        assert_eq!(tree.find_child(0, |h| h.text == "Etymology 2"), Some(4));
+
+        // Level skip: French's L4 Noun still nests directly under it
+        assert_eq!(tree.children(6), [7]);
+        assert_eq!(tree.depth(7), 1);
+        assert_eq!(tree.section_chunks(6, &chunks), ["", "# chat"]);
+        assert_eq!(tree.section_chunks(4, &chunks).len(), 2);
+    }
+
+    #[test]
+    fn test_spans() {
+        let (headings, chunks) = split_spans(TEXT);
+        let tree = SectionTree::new(&headings);
+        assert_eq!(tree.path_text(5), "English > Etymology 2 > Verb");
+        assert_eq!(tree.heading(5).span, TEXT.find("====Verb").unwrap()..TEXT.find("\n==French").unwrap());
+        assert_eq!(tree.section_chunks(7, &chunks)[0].text, "# chat\n");
+
+        // Headings before the first L2, or deeper than what follows, are still roots
+        let (headings, _) = split_by_headings("===Stray===\n==English==\n");
+        let tree = SectionTree::new(&headings);
+        assert_eq!(tree.roots(), [0, 1]);
+        assert!(SectionTree::<Heading>::new(&[]).is_empty());
+    }
+}
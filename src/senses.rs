@@ -0,0 +1,237 @@
+//! Reading the definitions under a part-of-speech heading.
+//!
+//! Wiktionary writes senses as an ordered list, with the kind of line given by what follows the
+//! `#` markers:
+//!
+//! ```text
+//! # {{lb|en|informal}} A [[cat]].            a sense
+//! #: {{ux|en|The puss sat on the mat.}}      a usage example of it
+//! #* {{quote-book|en|year=1899|...}}         a quotation
+//! #*: The puss was never seen again.         the quoted passage
+//! ## A [[kitten]].                           a subsense
+//! ```
+//!
+//! `parse_senses` turns these into a tree of `Sense`s. The lines come from the wikitext tree, so a
+//! quotation template that runs over several lines stays one line here. Text is kept as wikitext,
+//! and spans are byte ranges in the text that was parsed.
+
+use std::ops::Range;
+
+use crate::template::{self, Part};
+use crate::wikitext_tree::{self, NodeKind};
+
+/// Templates that label a sense, like `{{lb|en|informal|_|chiefly|US}}`
+pub const LABEL_TEMPLATES: &[&str] = &["lb", "lbl", "label"];
+
+/// A line under a sense, such as a usage example or quoted passage
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Line {
+    /// Wikitext after the list markers, trimmed. A deeper `#::` or `#*::` line, usually a
+    /// translation or transliteration, is joined on after a line break.
+    pub text: String,
+    /// Byte range of the list line, or lines, markers included
+    pub span: Range<usize>,
+}
+
+/// A `#*` quotation: the citation line and the `#*:` passage lines under it
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Quotation {
+    pub citation: Line,
+    pub passages: Vec<Line>,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Sense {
+    /// The definition, as wikitext, without its label templates
+    pub gloss: String,
+    /// What the label templates say, e.g. `informal`, leaving out the language code and `_`
+    pub labels: Vec<String>,
+    pub subsenses: Vec<Sense>,
+    /// `#:` usage examples
+    pub examples: Vec<Line>,
+    pub quotations: Vec<Quotation>,
+    /// Byte range of the `#` line
+    pub gloss_span: Range<usize>,
+    /// Byte range of the sense and everything under it
+    pub span: Range<usize>,
+}
+
+impl Sense {
+    fn new(content: &str, span: Range<usize>) -> Self {
+        let (gloss, labels) = split_labels(content);
+        Sense {
+            gloss,
+            labels,
+            gloss_span: span.clone(),
+            span,
+            ..Sense::default()
+        }
+    }
+}
+
+/// The senses in wikitext, usually the content under a part-of-speech heading. Lines that aren't
+/// `#` list items are skipped; an example, quotation or subsense that comes before any sense to put
+/// it under gets an empty sense of its own.
+pub fn parse_senses(wikitext: &str) -> Vec<Sense> {
+    let tree = wikitext_tree::parse(wikitext);
+    let mut senses = Vec::new();
+
+    for item in tree.children.iter().filter(|node| node.kind == NodeKind::ListItem) {
+        let markers = &item.children[0].text;
+        let depth = markers.bytes().take_while(|&b| b == b'#').count();
+        if depth == 0 {
+            continue;
+        }
+        let content = wikitext[item.children[0].span.end..item.span.end].trim();
+        let span = item.span.clone();
+
+        let suffix = &markers[depth..];
+        // Anything but senses, examples and quotations, like `#;`, isn't part of a sense
+        if !(suffix.is_empty() || suffix == "*" || suffix.starts_with("*:") || suffix.starts_with(':')) {
+            continue;
+        }
+        let parent = sense_at(&mut senses, depth, span.clone(), suffix.is_empty());
+        let line = Line {
+            text: content.to_string(),
+            span: span.clone(),
+        };
+        match suffix {
+            "" => *parent = Sense::new(content, span),
+            ":" => parent.examples.push(line),
+            "*" => parent.quotations.push(Quotation {
+                citation: line,
+                passages: Vec::new(),
+            }),
+            suffix if suffix.starts_with("*:") => {
+                let quotation = match parent.quotations.last_mut() {
+                    Some(quotation) => quotation,
+                    None => {
+                        parent.quotations.push(Quotation {
+                            citation: Line { text: String::new(), span: span.start..span.start },
+                            passages: Vec::new(),
+                        });
+                        parent.quotations.last_mut().unwrap()
+                    }
+                };
+                extend_or_push(&mut quotation.passages, line, suffix.len() > 2);
+            }
+            _ => extend_or_push(&mut parent.examples, line, true),
+        }
+    }
+    senses
+}
+
+/// The last sense at this depth, or a new one if the line at `span` starts one, with its span and
+/// those of the senses it's under stretched to take in the line
+fn sense_at(senses: &mut Vec<Sense>, depth: usize, span: Range<usize>, new: bool) -> &mut Sense {
+    let placeholder = || Sense {
+        gloss_span: span.start..span.start,
+        span: span.clone(),
+        ..Sense::default()
+    };
+    let mut level = senses;
+    for _ in 1..depth {
+        if level.is_empty() {
+            level.push(placeholder());
+        }
+        let sense = level.last_mut().unwrap();
+        sense.span.end = span.end;
+        level = &mut sense.subsenses;
+    }
+    if new || level.is_empty() {
+        level.push(placeholder());
+    }
+    let sense = level.last_mut().unwrap();
+    sense.span.end = span.end;
+    sense
+}
+
+/// Join a deeper line onto the last one, or add it if it's not deeper or there's none to join
+fn extend_or_push(lines: &mut Vec<Line>, line: Line, deeper: bool) {
+    match lines.last_mut() {
+        Some(last) if deeper => {
+            last.text.push('\n');
+            last.text.push_str(&line.text);
+            last.span.end = line.span.end;
+        }
+        _ => lines.push(line),
+    }
+}
+
+/// The gloss without its label templates, and the labels
+fn split_labels(content: &str) -> (String, Vec<String>) {
+    let mut gloss = String::new();
+    let mut labels = Vec::new();
+    let mut pos = 0;
+    for part in template::parse(content).parts {
+        let Part::Template(template) = part else {
+            continue;
+        };
+        if !LABEL_TEMPLATES.contains(&template.name.as_str()) {
+            continue;
+        }
+        gloss.push_str(&content[pos..template.span.start]);
+        pos = template.span.end;
+        let values = template.positional.iter().skip(1).map(|value| value.text().trim().to_string());
+        labels.extend(values.filter(|label| !label.is_empty() && label != "_"));
+    }
+    gloss.push_str(&content[pos..]);
+    (gloss.trim().to_string(), labels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOUN: &str = "{{en-noun}}
+
+# {{lb|en|informal|_|chiefly|US}} A [[cat]].
+#: {{ux|en|The puss sat on the mat.}}
+#:: A translation.
+#* {{quote-book|en|year=1899
+|title=Heart of Darkness}}
+#*: The puss was never seen again.
+#*: Nor heard.
+## A [[kitten]].
+##: {{ux|en|A small puss.}}
+# {{lb|en|slang}} A [[girl]] or young woman.
+#* '''2001''', Someone, ''A Book''
+";
+
+    #[test]
+    fn test_senses() {
+        let senses = parse_senses(NOUN);
+        assert_eq!(senses.len(), 2);
+
+        let cat = &senses[0];
+        assert_eq!(cat.gloss, "A [[cat]].");
+        assert_eq!(cat.labels, ["informal", "chiefly", "US"]);
+        assert_eq!(cat.examples.len(), 1);
+        assert_eq!(cat.examples[0].text, "{{ux|en|The puss sat on the mat.}}\nA translation.");
+        assert_eq!(&NOUN[cat.examples[0].span.clone()], "#: {{ux|en|The puss sat on the mat.}}\n#:: A translation.");
+        assert_eq!(cat.quotations.len(), 1);
+        assert_eq!(cat.quotations[0].citation.text, "{{quote-book|en|year=1899\n|title=Heart of Darkness}}");
+        assert_eq!(cat.quotations[0].passages.len(), 2);
+        assert_eq!(cat.quotations[0].passages[1].text, "Nor heard.");
+
+        assert_eq!(cat.subsenses.len(), 1);
+        assert_eq!(cat.subsenses[0].gloss, "A [[kitten]].");
+        assert_eq!(cat.subsenses[0].examples.len(), 1);
+        assert_eq!(&NOUN[cat.gloss_span.clone()], "# {{lb|en|informal|_|chiefly|US}} A [[cat]].");
+        assert_eq!(&NOUN[cat.span.clone()], &NOUN[NOUN.find("# {{lb").unwrap()..NOUN.find("\n# {{lb|en|slang").unwrap()]);
+
+        let girl = &senses[1];
+        assert_eq!((girl.gloss.as_str(), girl.labels.as_slice()), ("A [[girl]] or young woman.", ["slang".to_string()].as_slice()));
+        assert_eq!(girl.quotations[0].citation.text, "'''2001''', Someone, ''A Book''");
+    }
+
+    #[test]
+    fn test_orphans() {
+        let senses = parse_senses("#: An example first.\n## A subsense first.\n* Not a sense.\n#; Nor this.\n");
+        assert_eq!(senses.len(), 1);
+        assert_eq!(senses[0].gloss, "");
+        assert_eq!(senses[0].examples[0].text, "An example first.");
+        assert_eq!(senses[0].subsenses[0].gloss, "A subsense first.");
+        assert!(parse_senses("No list here.").is_empty());
+    }
+}
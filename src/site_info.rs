@@ -0,0 +1,111 @@
+//! The `<siteinfo>` header of a dump, including its namespace table.
+
+/// One entry from `<namespaces>`. The main namespace (key 0) has an empty name.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Namespace {
+    pub key: i32,
+    pub case: String,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct SiteInfo {
+    pub sitename: String,
+    pub dbname: String,
+    pub base: String,
+    pub generator: String,
+    pub case: String,
+    pub namespaces: Vec<Namespace>,
+}
+
+impl SiteInfo {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn namespace(&self, key: i32) -> Option<&Namespace> {
+        self.namespaces.iter().find(|ns| ns.key == key)
+    }
+
+    pub fn namespace_by_name(&self, name: &str) -> Option<&Namespace> {
+        self.namespaces.iter().find(|ns| ns.name == name)
+    }
+
+    /// Split a title like `Appendix:Glossary` into its namespace key and local name.
+    ///
+    /// Titles whose prefix isn't a known namespace, such as `A:B`, belong to the main namespace
+    /// and keep the colon.
+    pub fn split_title<'a>(&self, title: &'a str) -> (i32, &'a str) {
+        if let Some((prefix, local)) = title.split_once(':') {
+            if let Some(ns) = self.namespace_by_name(prefix).filter(|ns| !ns.name.is_empty()) {
+                return (ns.key, local);
+            }
+        }
+        (0, title)
+    }
+
+    /// The local part of a title, with any namespace prefix removed
+    pub fn local_name<'a>(&self, title: &'a str) -> &'a str {
+        self.split_title(title).1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance::read_all;
+    use crate::{new_page_source, ReaderKind};
+
+    fn sample() -> SiteInfo {
+        let ns = |key, name: &str| Namespace {
+            key,
+            case: "case-sensitive".to_string(),
+            name: name.to_string(),
+        };
+        SiteInfo {
+            namespaces: vec![ns(0, ""), ns(1, "Talk"), ns(100, "Appendix"), ns(106, "Rhymes")],
+            ..SiteInfo::new()
+        }
+    }
+
+    #[test]
+    fn test_split_title() {
+        let site = sample();
+        assert_eq!(site.split_title("Appendix:Glossary"), (100, "Glossary"));
+        assert_eq!(site.split_title("Rhymes:English/æt"), (106, "English/æt"));
+        assert_eq!(site.split_title("cat"), (0, "cat"));
+        assert_eq!(site.split_title("A:B"), (0, "A:B"));
+        assert_eq!(site.split_title(":cat"), (0, ":cat"));
+        assert_eq!(site.local_name("Talk:cat"), "cat");
+    }
+
+    #[test]
+    fn test_namespace_lookup() {
+        let site = sample();
+        assert_eq!(site.namespace(106).unwrap().name, "Rhymes");
+        assert_eq!(site.namespace_by_name("Appendix").unwrap().key, 100);
+        assert!(site.namespace(42).is_none());
+    }
+
+    #[test]
+    fn test_all_readers_populate_site_info() {
+        let dump = include_str!("../fixtures/conformance/redirects.xml");
+        for kind in ReaderKind::ALL {
+            let mut source = new_page_source(kind, Box::new(dump.as_bytes()));
+            read_all(&mut source).unwrap();
+            let site = source.site_info().unwrap();
+            assert_eq!(site.sitename, "Wiktionary", "{}", kind.name());
+            assert_eq!(site.dbname, "enwiktionary", "{}", kind.name());
+            assert_eq!(site.base, "https://en.wiktionary.org/wiki/Wiktionary:Main_Page", "{}", kind.name());
+            assert_eq!(site.generator, "MediaWiki 1.44.0-wmf.8", "{}", kind.name());
+            assert_eq!(site.case, "case-sensitive", "{}", kind.name());
+            let namespaces: Vec<_> = site.namespaces.iter().map(|ns| (ns.key, ns.case.as_str(), ns.name.as_str())).collect();
+            assert_eq!(
+                namespaces,
+                [(-1, "first-letter", "Special"), (0, "case-sensitive", ""), (1, "case-sensitive", "Talk"), (100, "case-sensitive", "Appendix")],
+                "{}",
+                kind.name()
+            );
+        }
+    }
+}
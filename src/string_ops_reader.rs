@@ -1,15 +1,22 @@
 use std::error::Error;
 use std::io::BufRead;
 
-use crate::{Page, PageSource};
+use crate::entities::unescape;
+use crate::revision::parse_timestamp;
+use crate::{Contributor, Namespace, Page, PageSource, Recovery, Revision, SiteInfo};
 
 pub struct StringOpsReader<R: BufRead> {
-    lines: std::io::Lines<R>,
+    reader: R,
+    offset: u64,
     state: State,
+    site_info: Option<SiteInfo>,
     title: Option<String>,
     ns: Option<i32>,
     pid: Option<i32>,
+    redirect: Option<String>,
+    revision: Revision,
     text_buffer: String,
+    all_revisions: bool,
 }
 
 #[derive(PartialEq)]
@@ -17,15 +24,21 @@ enum State {
     /// Initial state, waiting for a <page> tag
     PrePage,
 
+    /// Inside the <siteinfo> header
+    InSiteInfo,
+
     /// Inside <page> but haven't found <title> and <id> yet
     InPage,
 
-    /// Inside <page> and have found both title and page ID
+    /// Inside <page> and past its title and page ID, which may be missing
     InPageAfterTitleAndId,
 
     /// Inside a <revision> tag
     InRevision,
 
+    /// Inside a <contributor> tag within a revision
+    InContributor,
+
     /// Inside the text content
     InRevisionText,
 }
@@ -33,12 +46,61 @@ enum State {
 impl<R: BufRead> StringOpsReader<R> {
     pub fn new(reader: R) -> Self {
         StringOpsReader {
-            lines: reader.lines(),
+            reader,
+            offset: 0,
             state: State::PrePage,
+            site_info: None,
             title: None,
             ns: None,
             pid: None,
+            redirect: None,
+            revision: Revision::new(),
             text_buffer: String::new(),
+            all_revisions: false,
+        }
+    }
+
+    /// Read the next line without its line ending, keeping count of the bytes consumed
+    fn next_line(&mut self) -> Result<Option<String>, Box<dyn Error>> {
+        let mut line = String::new();
+        let len = self.reader.read_line(&mut line)?;
+        if len == 0 {
+            return Ok(None);
+        }
+        self.offset += len as u64;
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        Ok(Some(line))
+    }
+
+    /// The title that starts with `rest`, just after `<title>`, reading on through the lines it
+    /// runs over if it's pretty printed
+    fn read_title(&mut self, rest: &str) -> Result<String, Box<dyn Error>> {
+        let mut title = rest.to_string();
+        while !title.contains("</title>") {
+            let line = self
+                .next_line()?
+                .ok_or_else(|| format!("Unexpected EOF inside <title> at byte {}", self.offset))?;
+            title.push('\n');
+            title.push_str(&line);
+        }
+        title.truncate(title.find("</title>").unwrap());
+        Ok(unescape(&title).into_owned())
+    }
+
+    /// The page-level fields seen so far, together with the current revision and its text
+    fn revision_page(&mut self) -> Page {
+        Page {
+            title: self.title.clone().unwrap_or_default(),
+            ns: self.ns,
+            id: self.pid,
+            redirect: self.redirect.clone(),
+            revision: std::mem::take(&mut self.revision),
+            rev_text: std::mem::take(&mut self.text_buffer),
         }
     }
 }
@@ -46,22 +108,19 @@ impl<R: BufRead> StringOpsReader<R> {
 impl<R: BufRead> PageSource for StringOpsReader<R> {
     fn next_page(&mut self) -> Result<Option<Page>, Box<dyn Error>> {
         loop {
-            let line = match self.lines.next() {
-                Some(Ok(l)) => l,
-                Some(Err(e)) => return Err(Box::new(e)),
-                None => return Ok(None),
+            let line = match self.next_line()? {
+                Some(l) => l,
+                None if self.state == State::PrePage => return Ok(None),
+                None if self.state == State::InSiteInfo => {
+                    return Err(format!("Unexpected EOF inside <siteinfo> at byte {}", self.offset).into());
+                }
+                None => return Err(format!("Unexpected EOF inside <page> at byte {}", self.offset).into()),
             };
 
-            if self.state == State::PrePage {
-                if line.contains("<page>") {
-                    self.state = State::InPage;
-                }
-            } else if self.state == State::InPage {
+            if self.state == State::InPage {
                 // Extract title
                 if let Some(start) = line.find("<title>") {
-                    if let Some(end) = line[start + 7..].find("</title>") {
-                        self.title = Some(line[start + 7..start + 7 + end].to_string());
-                    }
+                    self.title = Some(self.read_title(&line[start + 7..])?);
                 }
                 
                 // Extract namespace
@@ -82,44 +141,182 @@ impl<R: BufRead> PageSource for StringOpsReader<R> {
                 
                 if self.title.is_some() && self.pid.is_some() {
                     self.state = State::InPageAfterTitleAndId;
+                    continue;
+                }
+                let header = ["<title>", "<ns>", "<id>"].iter().any(|tag| line.contains(tag));
+                if header || self.title.is_none() {
+                    continue;
+                }
+                // <id> is optional, so once there's a title any other line is the rest of the page
+                self.state = State::InPageAfterTitleAndId;
+            }
+
+            if self.state == State::PrePage {
+                if line.contains("<page>") {
+                    self.state = State::InPage;
+                } else if line.contains("<siteinfo>") {
+                    self.state = State::InSiteInfo;
+                    self.site_info = Some(SiteInfo::new());
+                }
+            } else if self.state == State::InSiteInfo {
+                let site_info = self.site_info.get_or_insert_with(SiteInfo::new);
+                if line.contains("</siteinfo>") {
+                    self.state = State::PrePage;
+                } else if let Some(start) = line.find("<namespace ") {
+                    let tag = &line[start..];
+                    let tag_end = tag.find('>').unwrap_or(tag.len());
+                    let rest = &tag[(tag_end + 1).min(tag.len())..];
+                    site_info.namespaces.push(Namespace {
+                        key: attribute_value(&tag[..tag_end], "key").unwrap_or_default().parse::<i32>()?,
+                        case: attribute_value(&tag[..tag_end], "case").unwrap_or_default().to_string(),
+                        name: unescape(rest.find("</namespace>").map_or("", |end| &rest[..end])).into_owned(),
+                    });
+                } else if let Some(sitename) = element_text(&line, "sitename") {
+                    site_info.sitename = unescape(sitename).into_owned();
+                } else if let Some(dbname) = element_text(&line, "dbname") {
+                    site_info.dbname = unescape(dbname).into_owned();
+                } else if let Some(base) = element_text(&line, "base") {
+                    site_info.base = unescape(base).into_owned();
+                } else if let Some(generator) = element_text(&line, "generator") {
+                    site_info.generator = unescape(generator).into_owned();
+                } else if let Some(case) = element_text(&line, "case") {
+                    site_info.case = case.to_string();
                 }
             } else if self.state == State::InPageAfterTitleAndId {
                 if line.contains("<revision>") {
                     self.state = State::InRevision;
+                    self.revision = Revision::new();
+                    self.text_buffer.clear();
+                } else if let Some(start) = line.find("<redirect") {
+                    self.redirect = attribute_value(&line[start..], "title").map(|t| unescape(t).into_owned());
                 } else if line.contains("</page>") {
-                    let page = Page {
-                        title: self.title.take().unwrap_or_default(),
-                        ns: self.ns,
-                        id: self.pid,
-                        rev_id: Some(-1),
-                        rev_contrib_id: None,
-                        rev_text: self.text_buffer.clone(),
-                    };
+                    let page = (!self.all_revisions).then(|| self.revision_page());
+                    self.title = None;
                     self.pid = None;
                     self.ns = None;
-                    self.text_buffer.clear();
+                    self.redirect = None;
                     self.state = State::PrePage;
-                    return Ok(Some(page));
+                    if page.is_some() {
+                        return Ok(page);
+                    }
                 }
             } else if self.state == State::InRevision {
-                if line.contains("<text") {
-                    self.state = State::InRevisionText;
-                    if let Some(end_tag) = line.find(">") {
-                        self.text_buffer.push_str(&line[end_tag + 1..]);
-                        self.text_buffer.push_str("\n");
+                if let Some(start) = line.find("<text") {
+                    let text_tag = &line[start..];
+                    if let Some(end_tag) = text_tag.find('>') {
+                        self.revision.bytes = attribute_value(&text_tag[..end_tag], "bytes").and_then(|b| b.parse().ok());
+                        let body = &text_tag[end_tag + 1..];
+                        if text_tag[..end_tag].ends_with('/') {
+                            // <text ... /> has no body
+                        } else if let Some(close) = body.find("</text>") {
+                            self.text_buffer.push_str(&unescape(&body[..close]));
+                        } else {
+                            self.state = State::InRevisionText;
+                            self.text_buffer.push_str(&unescape(body));
+                            self.text_buffer.push('\n');
+                        }
                     }
+                } else if line.contains("<contributor deleted") {
+                    self.revision.contributor = Some(Contributor::Deleted);
+                } else if line.contains("<contributor>") {
+                    self.state = State::InContributor;
+                } else if line.contains("<minor") {
+                    self.revision.minor = true;
                 } else if line.contains("</revision>") {
                     self.state = State::InPageAfterTitleAndId;
+                    if self.all_revisions {
+                        return Ok(Some(self.revision_page()));
+                    }
+                } else if let Some(id) = element_text(&line, "id") {
+                    self.revision.id = Some(id.parse::<i32>()?);
+                } else if let Some(parent_id) = element_text(&line, "parentid") {
+                    self.revision.parent_id = Some(parent_id.parse::<i32>()?);
+                } else if let Some(timestamp) = element_text(&line, "timestamp") {
+                    self.revision.timestamp = parse_timestamp(timestamp);
+                } else if let Some(comment) = element_text(&line, "comment") {
+                    self.revision.comment = Some(unescape(comment).into_owned());
+                } else if let Some(origin) = element_text(&line, "origin") {
+                    self.revision.origin = origin.parse::<i32>().ok();
+                } else if let Some(model) = element_text(&line, "model") {
+                    self.revision.model = Some(model.to_string());
+                } else if let Some(format) = element_text(&line, "format") {
+                    self.revision.format = Some(format.to_string());
+                } else if let Some(sha1) = element_text(&line, "sha1") {
+                    self.revision.sha1 = Some(sha1.to_string());
+                }
+            } else if self.state == State::InContributor {
+                if line.contains("</contributor>") {
+                    self.state = State::InRevision;
+                } else if let Some(username) = element_text(&line, "username") {
+                    self.revision.set_username(unescape(username).into_owned());
+                } else if let Some(id) = element_text(&line, "id") {
+                    self.revision.set_contributor_id(Some(id.parse::<i32>()?));
+                } else if let Some(ip) = element_text(&line, "ip") {
+                    self.revision.contributor = Some(Contributor::Ip(ip.to_string()));
                 }
             } else if self.state == State::InRevisionText {
-                self.text_buffer.push_str(&line);
-                if line.contains("</text>") {
-                    self.state = State::InPageAfterTitleAndId;
-                    self.text_buffer.truncate(self.text_buffer.len() - 7);
+                if let Some(close) = line.find("</text>") {
+                    self.text_buffer.push_str(&unescape(&line[..close]));
+                    self.state = State::InRevision;
                 } else {
-                    self.text_buffer.push_str("\n");
+                    self.text_buffer.push_str(&unescape(&line));
+                    self.text_buffer.push('\n');
+                }
+            }
+        }
+    }
+
+    fn site_info(&self) -> Option<&SiteInfo> {
+        self.site_info.as_ref()
+    }
+
+    fn set_all_revisions(&mut self, all: bool) {
+        self.all_revisions = all;
+    }
+
+    fn byte_offset(&self) -> Option<u64> {
+        Some(self.offset)
+    }
+
+    fn recover(&mut self, recovery: Recovery) -> Result<bool, Box<dyn Error>> {
+        self.title = None;
+        self.pid = None;
+        self.ns = None;
+        self.redirect = None;
+        self.revision = Revision::new();
+        self.text_buffer.clear();
+        self.state = State::PrePage;
+        let marker = match recovery {
+            Recovery::Strict => return Ok(false),
+            Recovery::SkipPage => "</page>",
+            Recovery::Resync => "<page>",
+        };
+
+        while let Some(line) = self.next_line()? {
+            if line.contains(marker) {
+                if recovery == Recovery::Resync {
+                    self.state = State::InPage;
                 }
+                return Ok(true);
             }
         }
+        Ok(false)
     }
 }
+
+/// The text between `<tag>` and `</tag>` on a single line
+fn element_text<'a>(line: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let start = line.find(&open)? + open.len();
+    let end = line[start..].find(&close)?;
+    Some(&line[start..start + end])
+}
+
+/// The value of `name="..."` within a start tag
+fn attribute_value<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let key = format!(" {}=\"", name);
+    let start = tag.find(&key)? + key.len();
+    let end = tag[start..].find('"')?;
+    Some(&tag[start..start + end])
+}
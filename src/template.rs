@@ -0,0 +1,542 @@
+//! Parsing `{{templates}}` with their parameters.
+//!
+//! Templates nest (`{{en-noun|s|head=foo|extra={{nested}}}}`), run over several lines, and their
+//! parameters can hold links whose own `|` doesn't split them. So parameter values are parsed as
+//! a sequence of `Part`s (text, templates, `{{{params}}}` and links) rather than cut at the next
+//! `|` or `}}`. As in MediaWiki, `<!-- comments -->` are dropped, `<nowiki>` content is plain text,
+//! other extension tags like `<math>` and `<ref>` are `Tag` parts, kept whole without looking inside
+//! (`extension_tags::regions` gets at their content), and braces or brackets that never close are
+//! plain text too.
+//!
+//! Spans are byte ranges into the wikitext that was parsed.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::ops::Range;
+
+use crate::extension_tags::{RegionFinder, RegionKind};
+use crate::wikilink::WikiLink;
+
+/// A `{{name|positional|key=named}}` template
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Template {
+    /// Everything before the first `|`, trimmed, e.g. `en-noun` or `#if:{{{1}}}`
+    pub name: String,
+    pub positional: Vec<Value>,
+    /// Parameters given as `key=value`, with the key and value trimmed. A later one with the
+    /// same key replaces an earlier one, as in MediaWiki.
+    pub named: BTreeMap<String, Value>,
+    pub span: Range<usize>,
+}
+
+impl Template {
+    /// A parameter by name, with positional ones named by number from 1 as in MediaWiki, so
+    /// `get("1")` is the first positional parameter unless `1=` is given explicitly.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        if let Some(value) = self.named.get(key) {
+            return Some(value);
+        }
+        let n: usize = key.parse().ok()?;
+        self.positional.get(n.checked_sub(1)?)
+    }
+}
+
+/// A `{{{name|default}}}` parameter reference, found in template source
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Param {
+    pub name: Value,
+    pub default: Option<Value>,
+    pub span: Range<usize>,
+}
+
+/// A `[[...]]` link, with its content parsed; `wikilink` says what it links to
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Link {
+    /// Everything between the brackets
+    pub content: Value,
+    pub span: Range<usize>,
+}
+
+impl Link {
+    /// What the link points to and shows, leaving out any link trail, which stays in the text
+    /// after it
+    pub fn wikilink(&self) -> WikiLink {
+        WikiLink::parse(&self.content.to_string())
+    }
+}
+
+/// A run of wikitext, such as a template parameter
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Value {
+    pub parts: Vec<Part>,
+    pub span: Range<usize>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Part {
+    Text(String),
+    Template(Template),
+    Param(Param),
+    Link(Link),
+    /// An extension tag other than `<nowiki>`, like `<ref>...</ref>` or `<math>...</math>`, as
+    /// written
+    Tag(String),
+}
+
+impl Value {
+    /// Just the text parts, leaving out any templates, params, links and tags
+    pub fn text(&self) -> String {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                Part::Text(text) => Some(text.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The templates directly in this value, not those nested inside them
+    pub fn templates(&self) -> impl Iterator<Item = &Template> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Template(template) => Some(template),
+            _ => None,
+        })
+    }
+
+    fn trim(&mut self) {
+        if let Some(Part::Text(text)) = self.parts.first_mut() {
+            *text = text.trim_start().to_string();
+        }
+        if let Some(Part::Text(text)) = self.parts.last_mut() {
+            text.truncate(text.trim_end().len());
+        }
+        self.parts.retain(|part| !matches!(part, Part::Text(text) if text.is_empty()));
+    }
+}
+
+/// Writes the value back as wikitext, less any comments and `<nowiki>` tags
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => write!(f, "{}", text)?,
+                Part::Template(template) => write!(f, "{}", template)?,
+                Part::Param(param) => {
+                    write!(f, "{{{{{{{}", param.name)?;
+                    if let Some(default) = &param.default {
+                        write!(f, "|{}", default)?;
+                    }
+                    write!(f, "}}}}}}")?;
+                }
+                Part::Link(link) => write!(f, "[[{}]]", link.content)?,
+                Part::Tag(tag) => write!(f, "{}", tag)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{{{{}", self.name)?;
+        for value in &self.positional {
+            write!(f, "|{}", value)?;
+        }
+        for (key, value) in &self.named {
+            write!(f, "|{}={}", key, value)?;
+        }
+        write!(f, "}}}}")
+    }
+}
+
+/// Parse all of the wikitext into parts
+pub fn parse(wikitext: &str) -> Value {
+    let mut parser = Parser {
+        src: wikitext,
+        pos: 0,
+        failed: HashSet::new(),
+        regions: RegionFinder::new(wikitext),
+        depth: 0,
+    };
+    let (value, _) = parser.parse_value(Context::Top);
+    value
+}
+
+/// The templates in the wikitext, in order, not counting those nested inside other templates
+pub fn parse_templates(wikitext: &str) -> Vec<Template> {
+    let mut templates = Vec::new();
+    collect_templates(parse(wikitext), &mut templates);
+    templates
+}
+
+/// Templates in links count as top level, since a link is just more text
+fn collect_templates(value: Value, templates: &mut Vec<Template>) {
+    for part in value.parts {
+        match part {
+            Part::Template(template) => templates.push(template),
+            Part::Link(link) => collect_templates(link.content, templates),
+            _ => {}
+        }
+    }
+}
+
+/// Openings nested deeper than this are text, which keeps the stack from overflowing. It's
+/// MediaWiki's own limit on how deep the preprocessor goes.
+pub const MAX_DEPTH: usize = 100;
+
+/// What's being parsed, which decides what ends a value
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+enum Context {
+    Top,
+    Template,
+    Param,
+    Link,
+}
+
+/// How a value ended
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum End {
+    /// A `|` starting another parameter
+    Pipe,
+    /// The closing `}}`, `}}}` or `]]`
+    Close,
+    /// The end of the wikitext, before any closing
+    Eof,
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    /// Where a template, param or link was found never to close, so it isn't tried again, which
+    /// would take exponential time with many unclosed braces
+    failed: HashSet<(usize, Context)>,
+    regions: RegionFinder<'a>,
+    /// How many templates, params and links the parser is inside
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn rest(&self) -> &str {
+        &self.src[self.pos..]
+    }
+
+    /// Parse parts up to whatever ends a value in this context, consuming the ending
+    fn parse_value(&mut self, context: Context) -> (Value, End) {
+        let start = self.pos;
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut text_start = self.pos;
+
+        let end = loop {
+            let rest = self.rest();
+            let Some(special) = rest.find(['{', '}', '[', ']', '|', '<']) else {
+                self.pos = self.src.len();
+                break End::Eof;
+            };
+            self.pos += special;
+            let rest = self.rest();
+
+            let (ending, len) = match context {
+                Context::Template if rest.starts_with("}}") => (Some(End::Close), 2),
+                Context::Param if rest.starts_with("}}}") => (Some(End::Close), 3),
+                Context::Link if rest.starts_with("]]") => (Some(End::Close), 2),
+                Context::Template | Context::Param if rest.starts_with('|') => (Some(End::Pipe), 1),
+                _ => (None, 0),
+            };
+            if let Some(ending) = ending {
+                text.push_str(&self.src[text_start..self.pos]);
+                let value_end = self.pos;
+                self.pos += len;
+                push_text(&mut parts, &mut text);
+                return (Value { parts, span: start..value_end }, ending);
+            }
+
+            let special_start = self.pos;
+            let part = if rest.starts_with("{{{") && !rest.starts_with("{{{{") {
+                self.nested(Self::parse_param)
+            } else if rest.starts_with("{{") {
+                self.nested(Self::parse_template)
+            } else if rest.starts_with("[[") {
+                self.nested(Self::parse_link)
+            } else if let Some(region) = self.regions.region_at(self.pos) {
+                // Comments go, nowiki content is text, and other extension tags stay whole
+                match region.kind {
+                    RegionKind::Comment => text.push_str(&self.src[text_start..special_start]),
+                    RegionKind::Tag("nowiki") => {
+                        text.push_str(&self.src[text_start..special_start]);
+                        text.push_str(region.content);
+                    }
+                    RegionKind::Tag(_) => {
+                        text.push_str(&self.src[text_start..special_start]);
+                        push_text(&mut parts, &mut text);
+                        parts.push(Part::Tag(self.src[region.span.clone()].to_string()));
+                    }
+                }
+                self.pos = region.span.end;
+                text_start = self.pos;
+                continue;
+            } else {
+                self.pos += 1;
+                continue;
+            };
+
+            match part {
+                Some(part) => {
+                    text.push_str(&self.src[text_start..special_start]);
+                    push_text(&mut parts, &mut text);
+                    parts.push(part);
+                    text_start = self.pos;
+                }
+                // Never closed, so the opening braces or brackets are just text
+                None => self.pos = special_start + 2,
+            }
+        };
+
+        text.push_str(&self.src[text_start..self.pos]);
+        push_text(&mut parts, &mut text);
+        (Value { parts, span: start..self.pos }, end)
+    }
+
+    /// Parse a template, param or link, unless that would nest them deeper than `MAX_DEPTH`
+    fn nested(&mut self, parse: fn(&mut Self) -> Option<Part>) -> Option<Part> {
+        if self.depth >= MAX_DEPTH {
+            return None;
+        }
+        self.depth += 1;
+        let part = parse(self);
+        self.depth -= 1;
+        part
+    }
+
+    fn parse_template(&mut self) -> Option<Part> {
+        let start = self.pos;
+        if self.failed.contains(&(start, Context::Template)) {
+            return None;
+        }
+        self.pos += 2;
+
+        let (name, mut end) = self.parse_value(Context::Template);
+        let mut template = Template {
+            name: name.to_string().trim().to_string(),
+            positional: Vec::new(),
+            named: BTreeMap::new(),
+            span: start..start,
+        };
+        while end == End::Pipe {
+            let (mut value, value_end) = self.parse_value(Context::Template);
+            end = value_end;
+            match split_named(&mut value) {
+                Some(key) => {
+                    template.named.insert(key, value);
+                }
+                None => template.positional.push(value),
+            }
+        }
+
+        if end == End::Eof {
+            self.failed.insert((start, Context::Template));
+            self.pos = start;
+            return None;
+        }
+        template.span = start..self.pos;
+        Some(Part::Template(template))
+    }
+
+    fn parse_param(&mut self) -> Option<Part> {
+        let start = self.pos;
+        if self.failed.contains(&(start, Context::Param)) {
+            return None;
+        }
+        self.pos += 3;
+
+        let (mut name, mut end) = self.parse_value(Context::Param);
+        name.trim();
+        let mut default = None;
+        if end == End::Pipe {
+            let (value, value_end) = self.parse_value(Context::Param);
+            default = Some(value);
+            end = value_end;
+            // Anything after a second `|` is ignored, as in MediaWiki
+            while end == End::Pipe {
+                end = self.parse_value(Context::Param).1;
+            }
+        }
+
+        if end == End::Eof {
+            self.failed.insert((start, Context::Param));
+            self.pos = start;
+            return None;
+        }
+        Some(Part::Param(Param {
+            name,
+            default,
+            span: start..self.pos,
+        }))
+    }
+
+    fn parse_link(&mut self) -> Option<Part> {
+        let start = self.pos;
+        if self.failed.contains(&(start, Context::Link)) {
+            return None;
+        }
+        self.pos += 2;
+
+        let (content, end) = self.parse_value(Context::Link);
+        if end == End::Eof {
+            self.failed.insert((start, Context::Link));
+            self.pos = start;
+            return None;
+        }
+        Some(Part::Link(Link {
+            content,
+            span: start..self.pos,
+        }))
+    }
+}
+
+fn push_text(parts: &mut Vec<Part>, text: &mut String) {
+    if !text.is_empty() {
+        parts.push(Part::Text(std::mem::take(text)));
+    }
+}
+
+/// If `value` starts with `key=`, where the key is plain text, split the key off and return it
+fn split_named(value: &mut Value) -> Option<String> {
+    let mut key = String::new();
+    for (i, part) in value.parts.iter_mut().enumerate() {
+        let Part::Text(text) = part else {
+            return None;
+        };
+        if let Some(eq) = text.find('=') {
+            key.push_str(&text[..eq]);
+            *text = text[eq + 1..].to_string();
+            value.parts.drain(..i);
+            value.trim();
+            return Some(key.trim().to_string());
+        }
+        key.push_str(text);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(wikitext: &str) -> Template {
+        let templates = parse_templates(wikitext);
+        assert_eq!(templates.len(), 1, "{:?}", templates);
+        templates.into_iter().next().unwrap()
+    }
+
+    fn texts(values: &[Value]) -> Vec<String> {
+        values.iter().map(Value::to_string).collect()
+    }
+
+    #[test]
+    fn test_parameters() {
+        let t = template("{{en-noun|s| es |head=foo bar | 1 = first }}");
+        assert_eq!(t.name, "en-noun");
+        assert_eq!(texts(&t.positional), ["s", " es "]);
+        assert_eq!(t.named["head"].text(), "foo bar");
+        assert_eq!(t.get("1").unwrap().text(), "first");
+        assert_eq!(t.get("2").unwrap().text(), " es ");
+        assert_eq!(t.get("3"), None);
+        assert_eq!(t.get("0"), None);
+        assert_eq!(t.span, 0..44);
+
+        let t = template("{{head|en|noun|head=a|head=b}}");
+        assert_eq!(t.named["head"].text(), "b");
+    }
+
+    #[test]
+    fn test_nested() {
+        let text = "{{en-noun|s|head=foo|extra={{nested|{{deeper}}}}}}";
+        let t = template(text);
+        assert_eq!(t.named.keys().collect::<Vec<_>>(), ["extra", "head"]);
+        let nested: Vec<_> = t.named["extra"].templates().collect();
+        assert_eq!(nested.len(), 1);
+        assert_eq!(nested[0].name, "nested");
+        assert_eq!(&text[nested[0].span.clone()], "{{nested|{{deeper}}}}");
+        assert_eq!(nested[0].positional[0].templates().next().unwrap().name, "deeper");
+        assert_eq!(t.to_string(), text.replace("head=foo|extra={{nested|{{deeper}}}}", "extra={{nested|{{deeper}}}}|head=foo"));
+    }
+
+    #[test]
+    fn test_multiline() {
+        let text = "==English==\n{{quote-book|en\n|year=1899\n|author={{w|Joseph Conrad}}\n|title=Heart of Darkness\n}}\n# {{lb|en|informal}} a cat";
+        let templates = parse_templates(text);
+        assert_eq!(templates.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), ["quote-book", "lb"]);
+        assert_eq!(templates[0].named["year"].text(), "1899");
+        assert_eq!(templates[0].named["author"].templates().next().unwrap().name, "w");
+        assert_eq!(texts(&templates[0].positional), ["en\n"]);
+    }
+
+    #[test]
+    fn test_links_and_nowiki() {
+        let t = template("{{gloss|see [[cat|the cat]] and [[dog]]|x=<nowiki>a|b}}</nowiki><!-- | -->c}}");
+        assert_eq!(t.positional.len(), 1);
+        let links: Vec<_> = t.positional[0]
+            .parts
+            .iter()
+            .filter_map(|part| match part {
+                Part::Link(link) => Some(link.content.text()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(links, ["cat|the cat", "dog"]);
+        let Some(Part::Link(link)) = t.positional[0].parts.get(1) else {
+            panic!("{:?}", t);
+        };
+        assert_eq!(link.wikilink().display(), "the cat");
+        assert_eq!(t.positional[0].text(), "see  and ");
+        assert_eq!(t.named["x"].text(), "a|b}}c");
+
+        // A template inside a link
+        let templates = parse_templates("[[{{l|en|cat}}]]");
+        assert_eq!(templates[0].name, "l");
+
+        // Nothing inside other extension tags counts
+        let t = template("{{a|<math>{{x|</math>|<ref name=\"r\">{{cite|y}}</ref>}}<nowiki>{{b}}</nowiki>");
+        assert_eq!(texts(&t.positional), ["<math>{{x|</math>", "<ref name=\"r\">{{cite|y}}</ref>"]);
+        assert_eq!(parse("<nowiki>{{b}}</nowiki>").text(), "{{b}}");
+    }
+
+    #[test]
+    fn test_params() {
+        let value = parse("{{#if:{{{1|}}}|{{{1}}}|{{{2|{{{head|x}}}}}}}}");
+        let Some(Part::Template(t)) = value.parts.first() else {
+            panic!("{:?}", value);
+        };
+        assert_eq!(t.name, "#if:{{{1|}}}");
+        let Some(Part::Param(p)) = t.positional[0].parts.first() else {
+            panic!("{:?}", t);
+        };
+        assert_eq!(p.name.text(), "1");
+        assert_eq!(p.default, None);
+        let Some(Part::Param(p)) = t.positional[1].parts.first() else {
+            panic!("{:?}", t);
+        };
+        assert_eq!(p.name.text(), "2");
+        assert_eq!(p.default.as_ref().unwrap().to_string(), "{{{head|x}}}");
+    }
+
+    #[test]
+    fn test_unclosed() {
+        assert_eq!(parse_templates("{{unclosed|a"), []);
+        assert_eq!(parse("{{unclosed|[[a]]").parts.len(), 2);
+        assert_eq!(parse("{{unclosed|a").text(), "{{unclosed|a");
+        assert_eq!(parse("a }} b ]] c | d").text(), "a }} b ]] c | d");
+        let templates = parse_templates("{{outer|{{inner}}");
+        assert_eq!(templates.len(), 1);
+        assert_eq!(templates[0].name, "inner");
+        assert_eq!(templates[0].span, 8..17);
+        assert_eq!(template("[[a {{b}}").name, "b");
+
+        // Each unclosed opening is only tried once
+        let text = "{{a|[[b|{{{c|".repeat(300);
+        assert_eq!(parse(&text).text(), text);
+        let text = format!("{}{}", "{{a|".repeat(300), "}}".repeat(300));
+        assert_eq!(parse_templates(&text).len(), 1);
+    }
+}
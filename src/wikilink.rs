@@ -0,0 +1,287 @@
+//! Reading `[[wikilinks]]`: what they point to and what they show.
+//!
+//! Definitions and "See also" lines link entries to each other with `[[target#English|display]]`,
+//! but the same brackets also hold interwiki links (`[[w:Foo]]`), category tags
+//! (`[[Category:en:Cats]]`), links to category pages (`[[:Category:en:Cats]]`), images
+//! (`[[File:Cat.jpg|thumb|A cat]]`) and links within the page (`[[#Etymology]]`). A `WikiLink`
+//! sorts these out from the link's parts, which come from a `Link` node of the wikitext tree or
+//! straight from the text between the brackets.
+//!
+//! Namespaces and interwiki prefixes are matched against Wiktionary's usual ones rather than
+//! a dump's `SiteInfo`, and lowercase prefixes of two or three letters count as language codes of
+//! other Wiktionaries.
+
+/// What a link does
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LinkKind {
+    /// To a page on this wiki, e.g. another entry or an appendix
+    Internal,
+    /// To another wiki, like `[[w:Foo]]` or the interlanguage `[[fr:chat]]`
+    Interwiki,
+    /// Puts the page in a category; `[[:Category:...]]` is an `Internal` link to the category instead
+    Category,
+    /// Shows an image or other file; `[[:File:...]]` is an `Internal` link to its page instead
+    File,
+    /// To a section of the same page, like `[[#English]]`
+    Anchor,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct WikiLink {
+    pub kind: LinkKind,
+    /// The interwiki prefix, as written, e.g. `w` or `fr`
+    pub interwiki: Option<String>,
+    /// The namespace of `target`, as named in `NAMESPACES`, if it isn't the main one
+    pub namespace: Option<String>,
+    /// The page title, with its namespace but not the interwiki prefix or anchor, trimmed and with
+    /// underscores as spaces. Empty for an `Anchor`.
+    pub target: String,
+    /// What follows `#`, usually a language name on Wiktionary, with underscores as spaces
+    pub anchor: Option<String>,
+    /// What follows the first `|`, as wikitext: the display text, the sort key of a category, or
+    /// the caption (the last part) of a file. A bare `|` gets MediaWiki's "pipe trick" text.
+    pub text: Option<String>,
+    /// Lowercase letters straight after the `]]`, which MediaWiki shows as part of the link
+    pub trail: String,
+}
+
+/// Namespaces on Wiktionary, with the aliases for some of them
+pub const NAMESPACES: &[&str] = &[
+    "Talk", "User", "User talk", "Wiktionary", "Wiktionary talk", "File", "File talk", "MediaWiki", "Template",
+    "Template talk", "Help", "Category", "Category talk", "Appendix", "Appendix talk", "Rhymes", "Thesaurus",
+    "Thesaurus talk", "Citations", "Reconstruction", "Reconstruction talk", "Module", "Special", "Media",
+];
+const NAMESPACE_ALIASES: &[(&str, &str)] = &[("Image", "File"), ("WT", "Wiktionary"), ("Project", "Wiktionary")];
+
+/// Interwiki prefixes to Wikimedia projects, besides language codes
+pub const INTERWIKI_PREFIXES: &[&str] = &[
+    "w", "wikipedia", "wikt", "wiktionary", "c", "commons", "species", "wikispecies", "s", "wikisource", "q",
+    "wikiquote", "b", "wikibooks", "n", "wikinews", "v", "wikiversity", "voy", "wikivoyage", "d", "wikidata", "m",
+    "meta", "mw", "foundation", "wmf", "phab", "simple",
+];
+
+impl WikiLink {
+    /// Read a link from its target and the parts after it (each `|` starts one), as wikitext, with
+    /// the trail that followed it if any
+    pub fn new<S: AsRef<str>>(target: &str, parts: &[S], trail: &str) -> Self {
+        let mut title = target.trim();
+        let colon = title.starts_with(':');
+        if colon {
+            title = title[1..].trim_start();
+        }
+
+        let mut interwiki = None;
+        let mut namespace = None;
+        if let Some((prefix, rest)) = title.split_once(':') {
+            let prefix = prefix.trim();
+            if is_interwiki(prefix) {
+                interwiki = Some(prefix.to_string());
+                title = rest.trim_start();
+            } else if let Some(name) = canonical_namespace(prefix) {
+                namespace = Some(name.to_string());
+                title = rest.trim_start();
+            }
+        }
+
+        let (page, anchor) = match title.split_once('#') {
+            Some((page, anchor)) => (page.trim(), Some(anchor.trim().replace('_', " "))),
+            None => (title, None),
+        };
+        let page = page.replace('_', " ");
+
+        let kind = if interwiki.is_some() {
+            LinkKind::Interwiki
+        } else if page.is_empty() && anchor.is_some() {
+            LinkKind::Anchor
+        } else if colon {
+            LinkKind::Internal
+        } else {
+            match namespace.as_deref() {
+                Some("Category") => LinkKind::Category,
+                Some("File" | "Media") => LinkKind::File,
+                _ => LinkKind::Internal,
+            }
+        };
+
+        let target = match &namespace {
+            Some(namespace) => format!("{}:{}", namespace, page),
+            None => page,
+        };
+        let text = match kind {
+            LinkKind::File => parts.last().map(|part| part.as_ref().to_string()),
+            _ if parts.is_empty() => None,
+            _ => {
+                let text = parts.iter().map(AsRef::as_ref).collect::<Vec<_>>().join("|");
+                Some(if text.is_empty() && kind != LinkKind::Category { pipe_trick(title) } else { text })
+            }
+        };
+        let trail = if takes_trail(kind) { trail.to_string() } else { String::new() };
+
+        WikiLink {
+            kind,
+            interwiki,
+            namespace,
+            target,
+            anchor,
+            text,
+            trail,
+        }
+    }
+
+    /// Read a link from the text between its brackets, e.g. `cat#English|cats`
+    pub fn parse(content: &str) -> Self {
+        let mut parts = split_parts(content);
+        let target = parts.remove(0);
+        WikiLink::new(target, &parts, "")
+    }
+
+    /// What the link shows on the page, as wikitext, or nothing for categories
+    pub fn display(&self) -> String {
+        match (self.kind, &self.text) {
+            (LinkKind::Category, _) => String::new(),
+            (_, Some(text)) => format!("{}{}", text, self.trail),
+            (LinkKind::Anchor, None) => format!("#{}{}", self.anchor.as_deref().unwrap_or(""), self.trail),
+            (_, None) => {
+                let mut shown = self.interwiki.as_ref().map_or(String::new(), |prefix| format!("{}:", prefix));
+                shown.push_str(&self.target);
+                if let Some(anchor) = &self.anchor {
+                    shown.push('#');
+                    shown.push_str(anchor);
+                }
+                shown.push_str(&self.trail);
+                shown
+            }
+        }
+    }
+
+    /// Whether this links to an entry, in the main namespace of this wiki
+    pub fn is_entry(&self) -> bool {
+        self.kind == LinkKind::Internal && self.namespace.is_none() && !self.target.is_empty()
+    }
+}
+
+/// Whether a link to a target like this can have a trail. Categories and files don't show as
+/// links, so letters after them are just text.
+pub fn target_takes_trail(target: &str) -> bool {
+    takes_trail(WikiLink::new::<&str>(target, &[], "").kind)
+}
+
+fn takes_trail(kind: LinkKind) -> bool {
+    !matches!(kind, LinkKind::Category | LinkKind::File)
+}
+
+fn is_interwiki(prefix: &str) -> bool {
+    let is_code = |code: &str| (2..=3).contains(&code.len()) && code.bytes().all(|b| b.is_ascii_lowercase());
+    INTERWIKI_PREFIXES.iter().any(|known| known.eq_ignore_ascii_case(prefix))
+        || (prefix.split('-').next().is_some_and(is_code) && prefix.bytes().all(|b| b.is_ascii_lowercase() || b == b'-'))
+}
+
+fn canonical_namespace(prefix: &str) -> Option<&'static str> {
+    let prefix = prefix.replace('_', " ");
+    NAMESPACES
+        .iter()
+        .find(|name| name.eq_ignore_ascii_case(&prefix))
+        .copied()
+        .or_else(|| NAMESPACE_ALIASES.iter().find(|(alias, _)| alias.eq_ignore_ascii_case(&prefix)).map(|&(_, name)| name))
+}
+
+/// The text MediaWiki fills in for `[[w:Foo (bar)|]]`: the title without prefixes or a final
+/// parenthesis or comma clause
+fn pipe_trick(title: &str) -> String {
+    let title = title.rsplit_once(':').map_or(title, |(_, rest)| rest).replace('_', " ");
+    let title = match title.rfind(" (") {
+        Some(open) if title.ends_with(')') => &title[..open],
+        _ => title.split(", ").next().unwrap_or(&title),
+    };
+    title.trim().to_string()
+}
+
+/// Split link content at each `|` that isn't inside a nested template or link
+fn split_parts(content: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    let bytes = content.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match &bytes[i..] {
+            [b'{', b'{', ..] | [b'[', b'[', ..] => {
+                depth += 1;
+                i += 2;
+                continue;
+            }
+            [b'}', b'}', ..] | [b']', b']', ..] if depth > 0 => {
+                depth -= 1;
+                i += 2;
+                continue;
+            }
+            [b'|', ..] if depth == 0 => {
+                parts.push(&content[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    parts.push(&content[start..]);
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kinds() {
+        let link = WikiLink::parse("cat#English|cats");
+        assert_eq!((link.kind, link.target.as_str()), (LinkKind::Internal, "cat"));
+        assert_eq!(link.anchor.as_deref(), Some("English"));
+        assert_eq!(link.display(), "cats");
+        assert!(link.is_entry());
+
+        let link = WikiLink::parse("w:Felis_catus");
+        assert_eq!((link.kind, link.interwiki.as_deref(), link.target.as_str()), (LinkKind::Interwiki, Some("w"), "Felis catus"));
+        assert_eq!(link.display(), "w:Felis catus");
+        assert_eq!(WikiLink::parse("fr:chat").kind, LinkKind::Interwiki);
+        assert_eq!(WikiLink::parse("zh-min-nan:niau").interwiki.as_deref(), Some("zh-min-nan"));
+
+        let link = WikiLink::parse("Category:en:Cats|cat");
+        assert_eq!((link.kind, link.target.as_str(), link.text.as_deref()), (LinkKind::Category, "Category:en:Cats", Some("cat")));
+        assert_eq!(link.display(), "");
+        let link = WikiLink::parse(":category:en:Cats");
+        assert_eq!((link.kind, link.namespace.as_deref()), (LinkKind::Internal, Some("Category")));
+        assert!(!link.is_entry());
+
+        let link = WikiLink::parse("Image:Cat.jpg|thumb|200px|A [[cat]]");
+        assert_eq!((link.kind, link.target.as_str()), (LinkKind::File, "File:Cat.jpg"));
+        assert_eq!(link.text.as_deref(), Some("A [[cat]]"));
+
+        let link = WikiLink::parse("#Etymology_2");
+        assert_eq!((link.kind, link.anchor.as_deref()), (LinkKind::Anchor, Some("Etymology 2")));
+        assert_eq!(link.display(), "#Etymology 2");
+
+        // Only known namespaces split the title
+        let link = WikiLink::parse("Appendix:Glossary#noun");
+        assert_eq!((link.namespace.as_deref(), link.target.as_str()), (Some("Appendix"), "Appendix:Glossary"));
+        assert_eq!(WikiLink::parse("Star Wars: Episode I").target, "Star Wars: Episode I");
+    }
+
+    #[test]
+    fn test_text_and_trail() {
+        let link = WikiLink::new("cat", &[] as &[&str], "s");
+        assert_eq!(link.display(), "cats");
+        let link = WikiLink::new("Category:Cats", &[] as &[&str], "s");
+        assert_eq!(link.trail, "");
+        assert!(!target_takes_trail("Category:Cats"));
+        assert!(target_takes_trail("w:Cat"));
+
+        // Extra pipes are part of the text, but not those in nested templates
+        assert_eq!(WikiLink::parse("a|b|c").text.as_deref(), Some("b|c"));
+        assert_eq!(WikiLink::parse("a{{!}}b|{{l|en|c}}").target, "a{{!}}b");
+
+        // The pipe trick
+        assert_eq!(WikiLink::parse("w:Pipe (computing)|").display(), "Pipe");
+        assert_eq!(WikiLink::parse("Appendix:Glossary|").display(), "Glossary");
+        assert_eq!(WikiLink::parse("Paris, Texas|").display(), "Paris");
+    }
+}
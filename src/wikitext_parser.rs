@@ -1,30 +1,27 @@
-use regex::Regex;
+use crate::entities::escape;
+use crate::entry::{self, PosBlock};
+use crate::wikitext_splitter;
 use crate::Page;
 
-/// Parse wikitext from a page and output extracted data
-/// Currently prints directly; future version will return structured Entry
+/// Parse wikitext from a page and output its English and Translingual nouns with their head
+/// lines, or, in the lightweight XML format if `xml`, the outline of each of those language
+/// sections. The structure comes from `entry::parse_entry`.
 pub fn parse_page_wikitext(
     page: &Page,
     page_num: &mut u64,
     section_num: &mut u64,
+    xml: bool,
 ) {
-    if page.ns.unwrap() != 0 {
+    if page.ns != Some(0) {
         return;
     }
 
-    let all_lang_headings_regex = Regex::new(r"(?m)^== ?([^=]*?) ?== *$\n").unwrap();
-    let our_lang_headings_regex = Regex::new(r"(?m)^== ?(English|Translingual) ?== *$\n").unwrap();
-    let mut lang_headings: Vec<String> = Vec::new();
-    let mut languages: Vec<String> = Vec::new();
-
-    for capture in all_lang_headings_regex.captures_iter(&page.rev_text) {
-        if let (Some(heading), Some(lang)) = (capture.get(0), capture.get(1)) {
-            lang_headings.push(heading.as_str().to_string());
-            languages.push(lang.as_str().to_string());
-        }
-    }
-
-    languages.retain(|lang| lang == "English" || lang == "Translingual");
+    let entry = entry::parse_entry(page);
+    let languages: Vec<_> = entry
+        .languages
+        .iter()
+        .filter(|language| language.name == "English" || language.name == "Translingual")
+        .collect();
 
     if languages.is_empty() {
         return;
@@ -33,64 +30,85 @@ pub fn parse_page_wikitext(
     // only count pages we don't reject
     *page_num += 1;
 
-    // now split the text by the same regex
-    let split_page_text = our_lang_headings_regex.split(&page.rev_text).collect::<Vec<&str>>();
-
-    let _lang_sections_output_vec: Vec<String> = Vec::new();
+    if xml {
+        println!(
+            "  <p n=\"{}\" pid=\"{}\" rid=\"{}\">",
+            page_num,
+            page.id.map_or(String::new(), |id| id.to_string()),
+            page.revision.id.map_or(String::new(), |id| id.to_string()),
+        );
+        println!("    <t>{}</t>", escape(&page.title));
+    }
 
-    // skip the prologue before the first heading, usually contains {{also}}
-    for (i, lang_sec_text) in split_page_text.iter().enumerate().skip(1) {
+    for language in languages {
         *section_num += 1;
 
-        let _lang_section_output = languages[i - 1].clone();
-
-        // get everything after this heading
-        let mut lang_sec_text = *lang_sec_text;
-        // but keep only up to the next heading
-        if let Some(heading) = all_lang_headings_regex.find(lang_sec_text) {
-            lang_sec_text = &lang_sec_text[0..heading.start()];
+        if xml {
+            print!("{}", xml_section(*section_num, &language.name, &page.rev_text[language.span.clone()]));
+            continue;
         }
 
-        let all_headings_regex = Regex::new(r"(?m)^==(?:=+) ?([^=]*?) ?==(?:=+) *$\n").unwrap();
-        let our_headings_regex = Regex::new(r"(?m)^==(?:=+) ?(Noun) ?==(?:=+) *$\n").unwrap();
-        let mut headings: Vec<String> = Vec::new();
-        let mut heading_names: Vec<String> = Vec::new();
-
-        for capture in all_headings_regex.captures_iter(lang_sec_text) {
-            if let (Some(heading), Some(heading_name)) = (capture.get(0), capture.get(1)) {
-                headings.push(heading.as_str().to_string());
-                heading_names.push(heading_name.as_str().to_string());
-            }
+        let nouns = language
+            .etymologies
+            .iter()
+            .flat_map(|group| &group.pos_blocks)
+            .filter(|block| block.pos == "Noun");
+        for (j, noun) in nouns.enumerate() {
+            println!("{}", noun_row(&page.title, &language.name, j + 1, noun));
         }
+    }
 
-        heading_names.retain(|heading_name| heading_name == "Noun");
+    if xml {
+        println!("  </p>");
+    }
+}
 
-        if heading_names.is_empty() {
-            continue;
-        }
+/// A noun as a tab-separated row. A head template can run over several lines, so the head's line
+/// breaks are written as `\n` to keep the row on one line.
+fn noun_row(title: &str, language: &str, n: usize, noun: &PosBlock) -> String {
+    let head = noun.head.lines().collect::<Vec<_>>().join("\\n");
+    format!("{}\t{}\t{}\t{}\t{}", title, language, n, noun.pos, head)
+}
 
-        let split_section_text = our_headings_regex.split(&lang_sec_text).collect::<Vec<&str>>();
+/// A language section in the XML format, holding the outline of its headings
+fn xml_section(section_num: u64, language: &str, text: &str) -> String {
+    let mut outline = String::new();
+    for heading in wikitext_splitter::heading_spans(text) {
+        outline.push('\n');
+        outline.push_str(&" ".repeat(10 + 2 * heading.level.saturating_sub(3)));
+        outline.push_str(&escape(heading.text));
+    }
+    format!("    <s n=\"{}\" l=\"{}\">\n      <x>{}</x>\n    </s>\n", section_num, escape(language), outline)
+}
 
-        let _heading_sections_output_vec: Vec<String> = Vec::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noun_row() {
+        let noun = PosBlock {
+            pos: "Noun".to_string(),
+            head: "{{en-noun|s\r\n|es}} [[x]]".to_string(),
+            ..PosBlock::default()
+        };
+        assert_eq!(noun_row("word", "English", 2, &noun), "word\tEnglish\t2\tNoun\t{{en-noun|s\\n|es}} [[x]]");
+    }
 
-        for (j, section_text) in split_section_text.iter().enumerate().skip(1) {
-            // let lump = section_text.replace("\n", "\\n").chars().take(72).collect::<String>();
-            // let's find 'lump' a different way: let's iterate through the lines in section_text
-            // and the first line to begin with { is the lump
-            let mut lump = String::new();
-            for line in section_text.lines() {
-                if line.starts_with("{{en-") || line.starts_with("{{head|en|") || line.starts_with("{{head|mul|") {
-                    lump = line.to_string();
-                    break;
-                }
-            }
-            println!("{}\t{}\t{}\t{}\t{}{}",
-                page.title,
-                languages[i - 1],
-                j,
-                if j == 0 { "⏺" } else { &heading_names[j - 1] },
-                if j == 0 { "⏺" } else { "" },
-                lump);
-        }
+    #[test]
+    fn test_xml_section() {
+        let text = "{{wikipedia}}\n===Etymology 1===\nFrom...\n====Noun====\n# a\n===Etymology 2===\n====Verb & noun====\n";
+        assert_eq!(
+            xml_section(8, "English", text),
+            concat!(
+                "    <s n=\"8\" l=\"English\">\n",
+                "      <x>\n",
+                "          Etymology 1\n",
+                "            Noun\n",
+                "          Etymology 2\n",
+                "            Verb &amp; noun</x>\n",
+                "    </s>\n",
+            )
+        );
     }
 }
@@ -1,10 +1,22 @@
-/// Core wikitext splitter following MediaWiki PHP approach:
-/// Split once into (headings, content) arrays, work out nesting by analyzing heading levels.
-/// 
-/// This keeps structure parsing clean and separate from semantic interpretation,
-/// allows lazy extraction of only needed sections, and avoids reparsing.
+//! Core wikitext splitter following MediaWiki PHP approach:
+//! Split once into (headings, content) arrays, work out nesting by analyzing heading levels.
+//!
+//! This keeps structure parsing clean and separate from semantic interpretation,
+//! allows lazy extraction of only needed sections, and avoids reparsing.
+//!
+//! `split_spans` does the same split without copying: its headings and chunks borrow from the
+//! wikitext and carry their byte ranges in it.
+//!
+//! Headings follow MediaWiki's rules rather than needing a tidy `==Text==` line: the `=` must
+//! start the line, but the two sides may be unbalanced (`===Noun==` is a level 2 heading titled
+//! `=Noun`), levels stop at 6, and whitespace and `<!-- comments -->` may follow the closing `=`.
+//! Lines inside comments and extension tags like `<nowiki>`, `<pre>` and `<ref>` are never
+//! headings. `HeadingRef::normalized` says which of these leniencies a heading needed.
 
 use std::fmt;
+use std::ops::Range;
+
+use crate::extension_tags;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Heading {
@@ -28,7 +40,7 @@ impl fmt::Display for Heading {
 /// content_chunks[i] is the text under headings[i-1] (for i >= 1)
 ///
 /// Example:
-/// ```
+/// ```text
 /// Some prologue
 /// ==English==
 /// Etymology text
@@ -40,63 +52,211 @@ impl fmt::Display for Heading {
 /// - headings: [(2, "English"), (3, "Etymology"), (4, "Noun")]
 /// - content_chunks: ["Some prologue\n", "Etymology text\n", "Noun definition\n", ""]
 pub fn split_by_headings(wikitext: &str) -> (Vec<Heading>, Vec<String>) {
-    let mut headings = Vec::new();
-    let mut content_chunks = Vec::new();
-    let mut current_content = String::new();
-
-    for line in wikitext.lines() {
-        let trimmed = line.trim();
-        if let Some(heading) = parse_heading(trimmed) {
-            // We hit a heading - save current content and record heading
-            content_chunks.push(current_content);
-            current_content = String::new();
-            headings.push(heading);
-        } else {
-            // Regular content line
-            if !current_content.is_empty() {
-                current_content.push('\n');
-            }
-            current_content.push_str(line);
-        }
-    }
+    let (headings, chunks) = split_spans(wikitext);
+    let headings = headings.iter().map(HeadingRef::to_heading).collect();
 
-    // Push final content chunk
-    content_chunks.push(current_content);
+    // Chunks are rejoined line by line, leaving out blank lines at the start and the final line break
+    let content_chunks = chunks
+        .iter()
+        .map(|chunk| {
+            let mut content = String::new();
+            for line in chunk.text.lines() {
+                if !content.is_empty() {
+                    content.push('\n');
+                }
+                content.push_str(line);
+            }
+            content
+        })
+        .collect();
 
     (headings, content_chunks)
 }
 
-/// Try to parse a line as a heading. Returns Some(Heading) or None.
-fn parse_heading(line: &str) -> Option<Heading> {
-    let trimmed = line.trim();
-    
-    // Count leading = signs
-    let leading = trimmed.chars().take_while(|c| *c == '=').count();
-    
-    // Must have at least 2
-    if leading < 2 {
+/// Leniencies MediaWiki allows in a heading line, beyond the plain `==Text==` form
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub struct Normalized {
+    /// More `=` on one side than the other. The extras are part of the text.
+    pub unbalanced: bool,
+    /// More than six `=` on both sides. The level is 6 and the extras are part of the text.
+    pub capped: bool,
+    /// Whitespace after the closing `=`, possibly non-breaking
+    pub trailing_whitespace: bool,
+    /// A comment after the closing `=`, possibly running on to later lines
+    pub trailing_comment: bool,
+}
+
+impl Normalized {
+    pub fn any(&self) -> bool {
+        self.unbalanced || self.capped || self.trailing_whitespace || self.trailing_comment
+    }
+}
+
+/// Try to parse a line as a heading, returning its level, where its text is in the line and
+/// what had to be overlooked to get them.
+fn parse_heading_line(line: &str) -> Option<(usize, Range<usize>, Normalized)> {
+    if !line.starts_with('=') {
         return None;
     }
-    
-    // Count trailing = signs
-    let trailing = trimmed.chars().rev().take_while(|c| *c == '=').count();
-    
-    // Leading and trailing must match, and there must be text between
-    if leading != trailing || leading * 2 >= trimmed.len() {
+    let mut normalized = Normalized::default();
+
+    // A comment opened after the heading that closes on a later line
+    let mut body = line;
+    if let Some(open) = body.rfind("<!--") {
+        if !body[open..].contains("-->") {
+            body = &body[..open];
+            normalized.trailing_comment = true;
+        }
+    }
+    // Whitespace and complete comments after the closing =
+    loop {
+        let trimmed = body.trim_end();
+        if trimmed.len() < body.len() {
+            normalized.trailing_whitespace = true;
+        }
+        body = trimmed;
+        match body.strip_suffix("-->").and_then(|before| before.rfind("<!--")) {
+            Some(open) => {
+                body = &body[..open];
+                normalized.trailing_comment = true;
+            }
+            None => break,
+        }
+    }
+
+    let leading = body.bytes().take_while(|&b| b == b'=').count();
+    let trailing = body.bytes().rev().take_while(|&b| b == b'=').count();
+    let count = if leading == body.len() {
+        // Nothing but = signs, which MediaWiki splits evenly around a text of the odd one out
+        (leading - 1) / 2
+    } else {
+        normalized.unbalanced = leading != trailing;
+        leading.min(trailing)
+    };
+    if count == 0 {
         return None;
     }
-    
-    // Extract text between = signs
-    let text = trimmed[leading..trimmed.len() - trailing]
-        .trim()
-        .to_string();
-    
-    Some(Heading {
-        level: leading,
-        text,
+    let level = count.min(6);
+    normalized.capped = count > 6;
+
+    // Text between the = signs, trimmed
+    let inner = &body[level..body.len() - level];
+    let text = inner.trim();
+    let start = level + (text.as_ptr() as usize - inner.as_ptr() as usize);
+
+    Some((level, start..start + text.len(), normalized))
+}
+
+/// A heading borrowed from the wikitext, with where it is in it
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct HeadingRef<'a> {
+    pub level: usize,
+    /// Text between the = signs, trimmed
+    pub text: &'a str,
+    /// Byte range of the whole heading line, without its line break
+    pub span: Range<usize>,
+    /// Byte range of `text`
+    pub text_span: Range<usize>,
+    /// What MediaWiki had to overlook to read the line as a heading
+    pub normalized: Normalized,
+}
+
+impl HeadingRef<'_> {
+    pub fn to_heading(&self) -> Heading {
+        Heading {
+            level: self.level,
+            text: self.text.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for HeadingRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "L{}: {}", self.level, self.text)
+    }
+}
+
+/// Text between two headings, borrowed from the wikitext, with its byte range in it
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Chunk<'a> {
+    pub text: &'a str,
+    pub span: Range<usize>,
+}
+
+/// Every heading in the wikitext, in order, found without allocating.
+pub fn heading_spans(wikitext: &str) -> impl Iterator<Item = HeadingRef<'_>> {
+    let mut pos = 0;
+    let mut regions = extension_tags::regions(wikitext).peekable();
+    std::iter::from_fn(move || {
+        while pos < wikitext.len() {
+            let start = pos;
+            let (end, next) = match wikitext[start..].find('\n') {
+                Some(newline) => (start + newline, start + newline + 1),
+                None => (wikitext.len(), wikitext.len()),
+            };
+            pos = next;
+            let end = if wikitext[..end].ends_with('\r') { end - 1 } else { end };
+            let line = &wikitext[start..end];
+
+            // Lines that start inside a comment or extension tag aren't headings
+            while regions.next_if(|region| region.span.end <= start).is_some() {}
+            if regions.peek().is_some_and(|region| region.span.start < start) {
+                continue;
+            }
+
+            if let Some((level, text, normalized)) = parse_heading_line(line) {
+                let text_span = start + text.start..start + text.end;
+                return Some(HeadingRef {
+                    level,
+                    text: &wikitext[text_span.clone()],
+                    span: start..end,
+                    text_span,
+                    normalized,
+                });
+            }
+        }
+        None
     })
 }
 
+/// Split wikitext into headings and content chunks like `split_by_headings`, borrowing them from
+/// the wikitext rather than copying.
+///
+/// The chunks are exact slices: each runs from the line break ending one heading line to the start
+/// of the next heading line, so the chunks, heading lines and their line breaks piece back together
+/// into the whole wikitext.
+pub fn split_spans(wikitext: &str) -> (Vec<HeadingRef<'_>>, Vec<Chunk<'_>>) {
+    let headings: Vec<_> = heading_spans(wikitext).collect();
+    let mut chunks = Vec::with_capacity(headings.len() + 1);
+
+    let mut start = 0;
+    for heading in &headings {
+        chunks.push(Chunk {
+            text: &wikitext[start..heading.span.start],
+            span: start..heading.span.start,
+        });
+        start = line_break_end(wikitext, heading.span.end);
+    }
+    chunks.push(Chunk {
+        text: &wikitext[start..],
+        span: start..wikitext.len(),
+    });
+
+    (headings, chunks)
+}
+
+/// Where the next line starts, given where this one's text ends
+fn line_break_end(wikitext: &str, end: usize) -> usize {
+    let rest = &wikitext[end..];
+    if rest.starts_with("\r\n") {
+        end + 2
+    } else if rest.starts_with('\n') {
+        end + 1
+    } else {
+        end
+    }
+}
+
 /// Find the byte range (start_idx, end_idx) of headings that belong to a language section.
 ///
 /// Returns (start, end) such that headings[start..end] are in the language section,
@@ -115,6 +275,11 @@ pub fn find_language_section(headings: &[Heading], language: &str) -> Option<(us
     Some((start, end))
 }
 
+/// Whether the wikitext has an L2 heading for exactly this language, without splitting it up.
+pub fn has_language_section(wikitext: &str, language: &str) -> bool {
+    heading_spans(wikitext).any(|h| h.level == 2 && h.text == language)
+}
+
 /// Extract all L3 headings within a section (between start and end indices).
 pub fn l3_headings_in_section(headings: &[Heading], start: usize, end: usize) -> Vec<usize> {
     headings[start..end]
@@ -154,6 +319,132 @@ mod tests {
         assert!(content[2].contains("Etym text"));
     }
 
+    #[test]
+    fn test_split_spans() {
+        let wikitext = "Prolog\n== English ==\nSome text\r\n===Etymology===  \r\nEtym text\n\n =Not=\n";
+        let (headings, chunks) = split_spans(wikitext);
+
+        assert_eq!(
+            headings.iter().map(HeadingRef::to_heading).collect::<Vec<_>>(),
+            split_by_headings(wikitext).0
+        );
+        assert_eq!(headings[0].span, 7..20);
+        assert_eq!(&wikitext[headings[0].text_span.clone()], "English");
+        assert_eq!(&wikitext[headings[1].span.clone()], "===Etymology===  ");
+        assert_eq!(headings[1].text, "Etymology");
+
+        let texts: Vec<_> = chunks.iter().map(|chunk| chunk.text).collect();
+        assert_eq!(texts, ["Prolog\n", "Some text\r\n", "Etym text\n\n =Not=\n"]);
+        for chunk in &chunks {
+            assert_eq!(&wikitext[chunk.span.clone()], chunk.text);
+        }
+
+        // Pieced back together
+        let mut whole = String::new();
+        for (chunk, heading) in chunks.iter().zip(&headings) {
+            whole.push_str(chunk.text);
+            whole.push_str(&wikitext[heading.span.start..line_break_end(wikitext, heading.span.end)]);
+        }
+        whole.push_str(chunks.last().unwrap().text);
+        assert_eq!(whole, wikitext);
+
+        let (headings, chunks) = split_spans("==English==");
+        assert_eq!(headings[0].span, 0..11);
+        assert_eq!(chunks.iter().map(|chunk| chunk.span.clone()).collect::<Vec<_>>(), [0..0, 11..11]);
+    }
+
+    fn heading(line: &str) -> Option<(usize, String, Normalized)> {
+        heading_spans(line).next().map(|h| (h.level, h.text.to_string(), h.normalized))
+    }
+
+    #[test]
+    fn test_heading_rules() {
+        let plain = Normalized::default();
+        assert_eq!(heading("==English=="), Some((2, "English".to_string(), plain)));
+        assert_eq!(heading("=== Etymology 1 ==="), Some((3, "Etymology 1".to_string(), plain)));
+        assert_eq!(heading("=Top="), Some((1, "Top".to_string(), plain)));
+        assert_eq!(heading("== =="), Some((2, "".to_string(), plain)));
+
+        // Unbalanced: the extras are part of the text
+        let unbalanced = Normalized {
+            unbalanced: true,
+            ..plain
+        };
+        assert_eq!(heading("===Noun=="), Some((2, "=Noun".to_string(), unbalanced)));
+        assert_eq!(heading("==Noun==="), Some((2, "Noun=".to_string(), unbalanced)));
+
+        // Capped at 6
+        let capped = Normalized {
+            capped: true,
+            ..plain
+        };
+        assert_eq!(heading("========Deep========"), Some((6, "==Deep==".to_string(), capped)));
+
+        // Only = signs
+        assert_eq!(heading("====="), Some((2, "=".to_string(), plain)));
+        assert_eq!(heading("=="), None);
+        assert_eq!(heading("="), None);
+
+        // Trailing whitespace, including non-breaking spaces, and comments
+        let whitespace = Normalized {
+            trailing_whitespace: true,
+            ..plain
+        };
+        assert_eq!(heading("===Etymology 1===\u{a0} \t"), Some((3, "Etymology 1".to_string(), whitespace)));
+        let comment = Normalized {
+            trailing_comment: true,
+            ..plain
+        };
+        assert_eq!(heading("===Noun===<!-- see talk -->"), Some((3, "Noun".to_string(), comment)));
+        let both = Normalized {
+            trailing_whitespace: true,
+            trailing_comment: true,
+            ..plain
+        };
+        assert_eq!(heading("===Noun=== <!-- a --> <!-- b -->  "), Some((3, "Noun".to_string(), both)));
+        assert_eq!(heading("===Noun=== <!-- runs on"), Some((3, "Noun".to_string(), both)));
+
+        // Not headings
+        assert_eq!(heading(" ==English=="), None);
+        assert_eq!(heading("==English== text"), None);
+        assert_eq!(heading("English=="), None);
+        assert_eq!(heading("# ==English=="), None);
+    }
+
+    #[test]
+    fn test_headings_hidden() {
+        let text = "==English==
+<!--
+==Commented out==
+-->
+===Noun=== <!-- spans
+===Also commented===
+-->
+<nowiki>
+==In nowiki==
+</NOWIKI>==Not at line start==
+<pre class=\"x\">
+==In pre==
+</pre>
+<nowiki/>
+<pre/>
+==Verb==
+<nowiki>==</nowiki>
+==Adjective==
+<ref>{{cite
+==In ref==
+}}</ref>
+<math>
+==In math==
+</math>
+<ref>
+==Unclosed ref==
+";
+        let texts: Vec<_> = heading_spans(text).map(|h| h.text).collect();
+        assert_eq!(texts, ["English", "Noun", "Verb", "Adjective", "Unclosed ref"]);
+        assert_eq!(split_by_headings(text).0.len(), 5);
+    }
+
     #[test]
     fn test_find_language_section() {
         let headings = vec![
@@ -172,6 +463,15 @@ mod tests {
         assert_eq!(end, 4);
     }
 
+    #[test]
+    fn test_has_language_section() {
+        let text = "==English==\n===Noun===\n# a\n\n== Old English ==\n===Verb===";
+        assert!(has_language_section(text, "English"));
+        assert!(has_language_section(text, "Old English"));
+        assert!(!has_language_section(text, "Noun"));
+        assert!(!has_language_section(text, "Engl"));
+    }
+
     #[test]
     fn test_l3_headings() {
         let headings = vec![
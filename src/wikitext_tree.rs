@@ -0,0 +1,618 @@
+//! A lossless syntax tree for wikitext.
+//!
+//! `split_by_headings` and the template parser read what they need and throw the rest away, which
+//! is fine for extracting data but not for changing a page: nothing can be written back without
+//! the exact spacing, line breaks and comments around it. The tree here keeps every byte. Each
+//! node has its byte span in the wikitext, the leaves (`Text`, `Markup` and `Comment`) hold their
+//! source, and the children of any other node tile its span exactly, so `to_wikitext()` gives back
+//! the input unchanged. Edit the tree, with `replace` for instance, and it gives back the edited
+//! page with nothing else disturbed.
+//!
+//! Headings are the ones `split_spans` finds, so a `SectionTree` over them lines up with the tree.
+//! A template, link or tag that isn't closed before the next heading is plain text, as is one
+//! that's never closed at all.
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::ops::Range;
+
+use crate::template::MAX_DEPTH;
+use crate::wikilink::{self, WikiLink};
+use crate::extension_tags::{RegionFinder, RegionKind};
+use crate::wikitext_splitter::{split_spans, HeadingRef};
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum NodeKind {
+    /// The whole page
+    Document,
+    Heading { level: usize },
+    /// A line starting with `*`, `#`, `:` or `;`, the markers being its first child
+    ListItem,
+    /// `{{`, `Name`, then `|` and an `Argument` for each parameter, then `}}`
+    Template,
+    /// `{{{`, `Name`, `|` and a `Value` for the default if there is one, then `}}}`
+    Param,
+    /// `[[`, `Name` for the target, then `|` and a `Value` for each further part, then `]]` and
+    /// the link trail, if any, as `Text`
+    Link,
+    /// An HTML tag like `<br />`, or an extension tag like `<ref>` or `<math>` with its content and
+    /// closing tag. The content of `<ref>`, `<references>` and `<poem>` is parsed; that of others
+    /// is `Text`.
+    Tag { name: String },
+    /// A template or param name, a link target, or the key of a named argument
+    Name,
+    /// A `Value`, or a `Name`, `=` and `Value` for a named argument
+    Argument,
+    Value,
+    Comment,
+    Text,
+    /// Syntax, like `{{`, `|`, the `==` of a heading or the `#:` of a list item
+    Markup,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Node<'a> {
+    pub kind: NodeKind,
+    /// Byte range in the wikitext that was parsed
+    pub span: Range<usize>,
+    /// What a leaf holds, empty for other nodes
+    pub text: Cow<'a, str>,
+    pub children: Vec<Node<'a>>,
+}
+
+impl<'a> Node<'a> {
+    fn leaf(kind: NodeKind, wikitext: &'a str, span: Range<usize>) -> Self {
+        Node {
+            kind,
+            text: Cow::Borrowed(&wikitext[span.clone()]),
+            span,
+            children: Vec::new(),
+        }
+    }
+
+    fn branch(kind: NodeKind, span: Range<usize>, children: Vec<Node<'a>>) -> Self {
+        Node {
+            kind,
+            span,
+            text: Cow::Borrowed(""),
+            children,
+        }
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// The wikitext this node covers, as it is now
+    pub fn to_wikitext(&self) -> String {
+        let mut out = String::new();
+        self.write_wikitext(&mut out);
+        out
+    }
+
+    pub fn write_wikitext(&self, out: &mut String) {
+        if self.children.is_empty() {
+            out.push_str(&self.text);
+        }
+        for child in &self.children {
+            child.write_wikitext(out);
+        }
+    }
+
+    /// This node and everything under it, depth first in document order
+    pub fn descendants(&self) -> impl Iterator<Item = &Node<'a>> {
+        let mut stack = vec![self];
+        std::iter::from_fn(move || {
+            let node = stack.pop()?;
+            stack.extend(node.children.iter().rev());
+            Some(node)
+        })
+    }
+
+    /// The `Name` child of a template, param, link or named argument
+    pub fn name(&self) -> Option<&Node<'a>> {
+        self.children.iter().find(|child| child.kind == NodeKind::Name)
+    }
+
+    /// What a `Link` node links to
+    pub fn link(&self) -> Option<WikiLink> {
+        if self.kind != NodeKind::Link {
+            return None;
+        }
+        let target = self.name()?.to_wikitext();
+        let parts: Vec<_> = self.children.iter().filter(|child| child.kind == NodeKind::Value).map(Node::to_wikitext).collect();
+        let trail = match self.children.last() {
+            Some(last) if last.kind == NodeKind::Text => last.to_wikitext(),
+            _ => String::new(),
+        };
+        Some(WikiLink::new(&target, &parts, &trail))
+    }
+
+    /// Every link under this node, nested ones (like those in a file caption) included
+    pub fn links(&self) -> impl Iterator<Item = WikiLink> + '_ {
+        self.descendants().filter_map(Node::link)
+    }
+
+    /// Turn this node into plain text, e.g. to substitute a template. Its span stays the one it
+    /// was parsed from.
+    pub fn replace(&mut self, text: impl Into<Cow<'a, str>>) {
+        self.kind = NodeKind::Text;
+        self.text = text.into();
+        self.children.clear();
+    }
+}
+
+/// Parse a whole page into a `Document` node
+pub fn parse(wikitext: &str) -> Node<'_> {
+    let (headings, chunks) = split_spans(wikitext);
+    let mut parser = Parser {
+        src: wikitext,
+        pos: 0,
+        limit: 0,
+        failed: HashSet::new(),
+        regions: RegionFinder::new(wikitext),
+        depth: 0,
+    };
+
+    let mut children = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        parser.parse_lines(chunk.span.clone(), &mut children);
+        if let Some(heading) = headings.get(i) {
+            children.push(parser.parse_heading(heading));
+            let next = chunks[i + 1].span.start;
+            parser.push_text(&mut children, heading.span.end, next);
+        }
+    }
+    Node::branch(NodeKind::Document, 0..wikitext.len(), children)
+}
+
+/// Extension tags whose content is wikitext of its own, parsed into the tree; the content of
+/// others is kept as text
+const WIKITEXT_TAGS: &[&str] = &["ref", "references", "poem"];
+
+/// What's being parsed, which decides what ends it
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+enum Context {
+    /// A line of a list item or paragraph, up to the line break
+    Line,
+    /// Up to the limit, like the text of a heading
+    Bounded,
+    Template,
+    Param,
+    Link,
+}
+
+/// What a run of nodes ended at, not yet consumed
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Stop {
+    Pipe,
+    /// A closing `}}`, `}}}`, `]]` or tag, of this length
+    Close(usize),
+    Newline,
+    Limit,
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    /// Where the current part of the page ends; nothing is parsed past it
+    limit: usize,
+    /// Where a template, param or link was found not to close before a limit, so it isn't tried
+    /// again
+    failed: HashSet<(usize, Context, usize)>,
+    regions: RegionFinder<'a>,
+    /// How many templates, params, links and tags the parser is inside
+    depth: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn set_limit(&mut self, start: usize, limit: usize) {
+        self.pos = start;
+        self.limit = limit;
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..self.limit]
+    }
+
+    /// Push a text leaf, joined onto the text before it if there is some
+    fn push_text(&self, nodes: &mut Vec<Node<'a>>, start: usize, end: usize) {
+        if start == end {
+            return;
+        }
+        if let Some(last) = nodes.last_mut() {
+            if last.kind == NodeKind::Text && last.span.end == start {
+                *last = Node::leaf(NodeKind::Text, self.src, last.span.start..end);
+                return;
+            }
+        }
+        nodes.push(Node::leaf(NodeKind::Text, self.src, start..end));
+    }
+
+    fn markup(&mut self, len: usize) -> Node<'a> {
+        self.pos += len;
+        Node::leaf(NodeKind::Markup, self.src, self.pos - len..self.pos)
+    }
+
+    /// The lines between two headings, as list items, text and inline nodes
+    fn parse_lines(&mut self, span: Range<usize>, nodes: &mut Vec<Node<'a>>) {
+        self.set_limit(span.start, span.end);
+        while self.pos < self.limit {
+            let start = self.pos;
+            let markers = self.rest().bytes().take_while(|b| b"*#:;".contains(b)).count();
+            if markers > 0 {
+                let mut children = vec![self.markup(markers)];
+                let (inline, _) = self.parse_parts(Context::Line);
+                children.extend(inline);
+                nodes.push(Node::branch(NodeKind::ListItem, start..self.pos, children));
+            } else {
+                let (inline, _) = self.parse_parts(Context::Line);
+                for node in inline {
+                    match node.kind {
+                        NodeKind::Text => self.push_text(nodes, node.span.start, node.span.end),
+                        _ => nodes.push(node),
+                    }
+                }
+            }
+            if self.rest().starts_with('\n') {
+                self.push_text(nodes, self.pos, self.pos + 1);
+                self.pos += 1;
+            }
+        }
+    }
+
+    fn parse_heading(&mut self, heading: &HeadingRef) -> Node<'a> {
+        let HeadingRef { span, text_span, .. } = heading;
+        let mut children = Vec::new();
+
+        self.set_limit(span.start, span.end);
+        children.push(self.markup(text_span.start - span.start));
+        self.limit = text_span.end;
+        children.extend(self.parse_parts(Context::Bounded).0);
+        self.limit = span.end;
+
+        // The closing = signs, then any whitespace and comments after them
+        let after = self.rest();
+        let close = after.len() - after.trim_start().len() + heading.level;
+        children.push(self.markup(close.min(after.len())));
+        children.extend(self.parse_parts(Context::Bounded).0);
+        children.retain(|child| !child.span.is_empty());
+
+        Node::branch(NodeKind::Heading { level: heading.level }, span.clone(), children)
+    }
+
+    /// Parse nodes up to whatever ends them in this context, leaving that unconsumed
+    fn parse_parts(&mut self, context: Context) -> (Vec<Node<'a>>, Stop) {
+        let mut nodes = Vec::new();
+        let mut text_start = self.pos;
+
+        loop {
+            let Some(special) = self.rest().find(['{', '}', '[', ']', '|', '<', '\n']) else {
+                self.pos = self.limit;
+                self.push_text(&mut nodes, text_start, self.pos);
+                return (nodes, Stop::Limit);
+            };
+            self.pos += special;
+            let rest = self.rest();
+
+            let stop = match context {
+                Context::Line if rest.starts_with('\n') => Some(Stop::Newline),
+                Context::Template if rest.starts_with("}}") => Some(Stop::Close(2)),
+                Context::Param if rest.starts_with("}}}") => Some(Stop::Close(3)),
+                Context::Link if rest.starts_with("]]") => Some(Stop::Close(2)),
+                Context::Template | Context::Param | Context::Link if rest.starts_with('|') => Some(Stop::Pipe),
+                _ => None,
+            };
+            if let Some(stop) = stop {
+                self.push_text(&mut nodes, text_start, self.pos);
+                return (nodes, stop);
+            }
+
+            let start = self.pos;
+            let is_param = rest.starts_with("{{{") && !rest.starts_with("{{{{");
+            let node = if is_param {
+                self.nested(|parser| parser.parse_group(NodeKind::Param, 3, Context::Param))
+            } else if rest.starts_with("{{") {
+                self.nested(|parser| parser.parse_group(NodeKind::Template, 2, Context::Template))
+            } else if rest.starts_with("[[") {
+                self.nested(|parser| parser.parse_group(NodeKind::Link, 2, Context::Link))
+            } else if rest.starts_with('<') {
+                self.nested(Self::parse_tag)
+            } else {
+                None
+            };
+
+            match node {
+                Some(node) => {
+                    self.push_text(&mut nodes, text_start, start);
+                    nodes.push(node);
+                    text_start = self.pos;
+                }
+                // Not closed, so the opening is text; after a brace that wasn't a param, there
+                // may still be a template
+                None if is_param => self.pos = start + 1,
+                None if rest.starts_with("{{") || rest.starts_with("[[") => self.pos = start + 2,
+                None => self.pos = start + 1,
+            }
+        }
+    }
+
+    /// Parse a nested node, unless that would nest them deeper than `MAX_DEPTH`, the opening then
+    /// being text
+    fn nested(&mut self, parse: impl FnOnce(&mut Self) -> Option<Node<'a>>) -> Option<Node<'a>> {
+        if self.depth >= MAX_DEPTH {
+            return None;
+        }
+        self.depth += 1;
+        let node = parse(self);
+        self.depth -= 1;
+        node
+    }
+
+    /// A template, param or link: opening, name, `|`-separated parts and closing
+    fn parse_group(&mut self, kind: NodeKind, open: usize, context: Context) -> Option<Node<'a>> {
+        let start = self.pos;
+        let key = (start, context, self.limit);
+        if self.failed.contains(&key) {
+            return None;
+        }
+
+        let mut children = vec![self.markup(open)];
+        let mut part_kind = NodeKind::Name;
+        loop {
+            let part_start = self.pos;
+            let (nodes, stop) = self.parse_parts(context);
+            let part_span = part_start..self.pos;
+            children.push(match part_kind {
+                NodeKind::Argument => self.argument(nodes, part_span),
+                _ => Node::branch(part_kind.clone(), part_span, nodes),
+            });
+            part_kind = if kind == NodeKind::Template { NodeKind::Argument } else { NodeKind::Value };
+
+            match stop {
+                Stop::Pipe => children.push(self.markup(1)),
+                Stop::Close(len) => {
+                    children.push(self.markup(len));
+                    if kind == NodeKind::Link && wikilink::target_takes_trail(&children[1].to_wikitext()) {
+                        let trail = self.rest().bytes().take_while(u8::is_ascii_lowercase).count();
+                        self.push_text(&mut children, self.pos, self.pos + trail);
+                        self.pos += trail;
+                    }
+                    return Some(Node::branch(kind, start..self.pos, children));
+                }
+                Stop::Newline | Stop::Limit => {
+                    self.failed.insert(key);
+                    self.pos = start;
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// A template argument, split into `Name`, `=` and `Value` if the `=` comes before anything
+    /// but text and comments
+    fn argument(&self, mut nodes: Vec<Node<'a>>, span: Range<usize>) -> Node<'a> {
+        let equals = nodes.iter().position(|node| node.kind != NodeKind::Comment && node.kind != NodeKind::Text);
+        let named = nodes[..equals.unwrap_or(nodes.len())]
+            .iter()
+            .position(|node| node.kind == NodeKind::Text && node.text.contains('='));
+        let Some(i) = named else {
+            return Node::branch(NodeKind::Argument, span.clone(), vec![Node::branch(NodeKind::Value, span, nodes)]);
+        };
+
+        let text = nodes[i].span.clone();
+        let at = text.start + nodes[i].text.find('=').unwrap();
+        let value_nodes = nodes.split_off(i + 1);
+        nodes.pop();
+
+        let mut name = nodes;
+        self.push_text(&mut name, text.start, at);
+        let mut value = Vec::new();
+        self.push_text(&mut value, at + 1, text.end);
+        value.extend(value_nodes);
+
+        let children = vec![
+            Node::branch(NodeKind::Name, span.start..at, name),
+            Node::leaf(NodeKind::Markup, self.src, at..at + 1),
+            Node::branch(NodeKind::Value, at + 1..span.end, value),
+        ];
+        Node::branch(NodeKind::Argument, span, children)
+    }
+
+    /// A comment, or a tag with its content and closing tag if it has them
+    fn parse_tag(&mut self) -> Option<Node<'a>> {
+        let start = self.pos;
+        let region = self.regions.region_at(start);
+        match region {
+            Some(region) if region.kind == RegionKind::Comment => {
+                self.pos = region.span.end.min(self.limit);
+                return Some(Node::leaf(NodeKind::Comment, self.src, start..self.pos));
+            }
+            Some(region) if region.span.end <= self.limit => {
+                let RegionKind::Tag(name) = region.kind else {
+                    unreachable!();
+                };
+                let mut children = vec![self.markup(region.content_span.start - start)];
+                if region.span.end > region.content_span.end {
+                    if WIKITEXT_TAGS.contains(&name) {
+                        let limit = self.limit;
+                        self.limit = region.content_span.end;
+                        children.extend(self.parse_parts(Context::Bounded).0);
+                        self.limit = limit;
+                    } else {
+                        self.push_text(&mut children, self.pos, region.content_span.end);
+                        self.pos = region.content_span.end;
+                    }
+                    children.push(self.markup(region.span.end - self.pos));
+                }
+                let name = name.to_string();
+                return Some(Node::branch(NodeKind::Tag { name }, start..self.pos, children));
+            }
+            _ => {}
+        }
+
+        let (name, len) = tag_token(self.rest())?;
+        let children = vec![self.markup(len)];
+        Some(Node::branch(NodeKind::Tag { name }, start..self.pos, children))
+    }
+}
+
+/// If `text` starts with an HTML-like tag, opening, closing or both, its lowercased name and length
+fn tag_token(text: &str) -> Option<(String, usize)> {
+    let closing = text[1..].starts_with('/');
+    let name_start = 1 + closing as usize;
+    let name_len = text[name_start..].bytes().take_while(u8::is_ascii_alphanumeric).count();
+    if name_len == 0 || !text.as_bytes()[name_start].is_ascii_alphabetic() {
+        return None;
+    }
+    let after = &text[name_start + name_len..];
+    if !after.starts_with(['>', '/', ' ', '\t', '\n']) {
+        return None;
+    }
+    let close = after.find(['>', '<']).filter(|&close| after.as_bytes()[close] == b'>')?;
+    let name = text[name_start..name_start + name_len].to_ascii_lowercase();
+    Some((name, name_start + name_len + close + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wikilink::LinkKind;
+
+    fn kinds<'n>(nodes: &'n [Node]) -> Vec<&'n NodeKind> {
+        nodes.iter().map(|node| &node.kind).collect()
+    }
+
+    /// Leaves hold their source and every other node's children tile its span
+    fn check_lossless(wikitext: &str) {
+        let tree = parse(wikitext);
+        assert_eq!(tree.to_wikitext(), wikitext, "{:#?}", tree);
+        for node in tree.descendants() {
+            if node.is_leaf() {
+                assert_eq!(node.text, &wikitext[node.span.clone()], "{:?} in {:?}", node, wikitext);
+                continue;
+            }
+            let mut pos = node.span.start;
+            for child in &node.children {
+                assert_eq!(child.span.start, pos, "{:?} in {:?}", node, wikitext);
+                pos = child.span.end;
+            }
+            assert_eq!(pos, node.span.end, "{:?} in {:?}", node, wikitext);
+        }
+    }
+
+    #[test]
+    fn test_structure() {
+        let text = "{{also|Cat}}\n==English== <!-- en -->\r\n===Noun===\n{{en-noun|head=[[cat|the cat]]|s}}\n# {{lb|en|x}} a [[feline]]<ref name=\"a\">{{cite|x}}</ref>\n#: <nowiki>{{not}}</nowiki> ex<br/>\n";
+        check_lossless(text);
+        let tree = parse(text);
+
+        assert_eq!(
+            kinds(&tree.children),
+            [
+                &NodeKind::Template,
+                &NodeKind::Text,
+                &NodeKind::Heading { level: 2 },
+                &NodeKind::Text,
+                &NodeKind::Heading { level: 3 },
+                &NodeKind::Text,
+                &NodeKind::Template,
+                &NodeKind::Text,
+                &NodeKind::ListItem,
+                &NodeKind::Text,
+                &NodeKind::ListItem,
+                &NodeKind::Text,
+            ]
+        );
+
+        let heading = &tree.children[2];
+        assert_eq!(kinds(&heading.children), [&NodeKind::Markup, &NodeKind::Text, &NodeKind::Markup, &NodeKind::Text, &NodeKind::Comment]);
+        assert_eq!(heading.children[1].text, "English");
+        assert_eq!(tree.children[3].text, "\r\n");
+
+        let noun = &tree.children[6];
+        assert_eq!(noun.name().unwrap().to_wikitext(), "en-noun");
+        let head = &noun.children[3];
+        assert_eq!(head.kind, NodeKind::Argument);
+        assert_eq!(head.name().unwrap().to_wikitext(), "head");
+        assert_eq!(head.children[2].children[0].kind, NodeKind::Link);
+        assert_eq!(noun.children[5].children[0].to_wikitext(), "s");
+
+        let sense = &tree.children[8];
+        assert_eq!(sense.children[0].text, "#");
+        let tags: Vec<_> = tree.descendants().filter_map(|node| match &node.kind {
+            NodeKind::Tag { name } => Some((name.as_str(), node.children.len())),
+            _ => None,
+        }).collect();
+        assert_eq!(tags, [("ref", 3), ("nowiki", 3), ("br", 1)]);
+        assert_eq!(tree.descendants().filter(|node| node.kind == NodeKind::Template).count(), 4);
+    }
+
+    #[test]
+    fn test_multiline_and_unclosed() {
+        let text = "#* {{quote-book|en\n|year=1899\n}}\n{{unclosed|\n==English==\n[[a|{{b}}";
+        check_lossless(text);
+        let tree = parse(text);
+        let quote = &tree.children[0];
+        assert_eq!(quote.kind, NodeKind::ListItem);
+        assert_eq!(quote.span, 0..32);
+        // The unclosed template doesn't run into the heading, and the unclosed link is text
+        assert_eq!(tree.children[2].kind, NodeKind::Heading { level: 2 });
+        assert_eq!(tree.children[3].text, "\n[[a|");
+        assert_eq!(tree.children[4].kind, NodeKind::Template);
+
+        // Each unclosed opening is only tried once
+        check_lossless(&"{{a|[[b|{{{c|<ref>".repeat(300));
+        check_lossless(&format!("{}{}", "{{a|".repeat(300), "}}".repeat(300)));
+    }
+
+    #[test]
+    fn test_links() {
+        let text = "# [[cat#English|feline]]s, [[dog]]s' and [[w:Wolf]]\n[[Category:en:Cats]]s [[File:Cat.jpg|thumb|A [[kitten]]]]";
+        check_lossless(text);
+        let tree = parse(text);
+        let links: Vec<_> = tree.links().map(|link| (link.kind, link.display(), link.target)).collect();
+        assert_eq!(
+            links,
+            [
+                (LinkKind::Internal, "felines".to_string(), "cat".to_string()),
+                (LinkKind::Internal, "dogs".to_string(), "dog".to_string()),
+                (LinkKind::Interwiki, "w:Wolf".to_string(), "Wolf".to_string()),
+                (LinkKind::Category, String::new(), "Category:en:Cats".to_string()),
+                (LinkKind::File, "A [[kitten]]".to_string(), "File:Cat.jpg".to_string()),
+                (LinkKind::Internal, "kitten".to_string(), "kitten".to_string()),
+            ]
+        );
+        // The trail is part of the link node, but not after a category
+        assert_eq!(tree.children[0].children[2].to_wikitext(), "[[cat#English|feline]]s");
+        assert_eq!(tree.children[2].span.end, text.find("s [[File").unwrap());
+    }
+
+    #[test]
+    fn test_replace() {
+        let mut tree = parse("# {{lb|en|informal}} a  cat\n");
+        tree.children[0].children[2].replace("(informal)");
+        assert_eq!(tree.to_wikitext(), "# (informal) a  cat\n");
+    }
+
+    #[test]
+    fn test_round_trip() {
+        const PIECES: &[&str] = &[
+            "{{", "}}", "{{{", "}}}", "[[", "]]", "|", "=", "==", "\n", "\r\n", "*", "#:", " ", "a", "é",
+            "<!--", "-->", "<ref>", "</ref>", "<nowiki>", "</NOWIKI >", "<pre>", "</pre>", "<br/>", "<", ">",
+        ];
+        // xorshift, so the cases are the same every run
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        for _ in 0..3000 {
+            let mut text = String::new();
+            let len = state % 40;
+            for _ in 0..len {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                text.push_str(PIECES[(state % PIECES.len() as u64) as usize]);
+            }
+            check_lossless(&text);
+        }
+    }
+}